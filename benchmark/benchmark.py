@@ -91,4 +91,21 @@ def benchmark_call_def_8name():
     return y
 
 
+def op8(x):
+    y = x + 1
+    return y
+
+
+def op9(x):
+    y = op8(x)
+    return op8(y)
+
+
+def benchmark_call_def_nested():
+    y = 0
+    for x in range(REPEAT_100M):
+        y = op9(x)
+    return y
+
+
 print(benchmark_call_def_1name())
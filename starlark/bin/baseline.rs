@@ -0,0 +1,190 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Baselines record the lints a tree already has, so `--check` can report only new ones.
+//!
+//! A lint is identified by its path, its name, and a fingerprint of the source code it
+//! refers to, so it still matches after unrelated edits move it to a different line.
+//! Each identity has a count, so a second copy of an existing problem is reported.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::anyhow;
+use serde::Deserialize;
+use serde::Serialize;
+use starlark::errors::EvalMessage;
+use starlark::errors::EvalSeverity;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    path: String,
+    name: String,
+    fingerprint: String,
+}
+
+/// The form of a baseline file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BaselineFile {
+    lints: Vec<BaselineEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BaselineEntry {
+    path: String,
+    name: String,
+    fingerprint: String,
+    count: usize,
+}
+
+/// FNV-1a, which unlike the standard library hashers is stable between releases,
+/// of the text with whitespace normalised.
+fn fingerprint(x: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for (i, word) in x.split_whitespace().enumerate() {
+        let space = if i == 0 { "" } else { " " };
+        for b in space.bytes().chain(word.bytes()) {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// A multiset of lints.
+#[derive(Debug, Default)]
+pub(crate) struct Baseline(BTreeMap<Key, usize>);
+
+impl Baseline {
+    fn key(x: &EvalMessage) -> Key {
+        Key {
+            path: x.path.clone(),
+            name: x.name.clone(),
+            fingerprint: fingerprint(x.original.as_ref().unwrap_or(&x.description)),
+        }
+    }
+
+    pub(crate) fn read(path: &Path) -> anyhow::Result<Self> {
+        let file: BaselineFile = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("Invalid baseline `{}`: {}", path.display(), e))?;
+        let mut res = Self::default();
+        for x in file.lints {
+            let key = Key {
+                path: x.path,
+                name: x.name,
+                fingerprint: x.fingerprint,
+            };
+            *res.0.entry(key).or_default() += x.count;
+        }
+        Ok(res)
+    }
+
+    pub(crate) fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let file = BaselineFile {
+            lints: self
+                .0
+                .iter()
+                .map(|(key, count)| BaselineEntry {
+                    path: key.path.clone(),
+                    name: key.name.clone(),
+                    fingerprint: key.fingerprint.clone(),
+                    count: *count,
+                })
+                .collect(),
+        };
+        let mut contents = serde_json::to_string_pretty(&file)?;
+        contents.push('\n');
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// Record a message. Disabled messages aren't problems, so aren't recorded.
+    pub(crate) fn add(&mut self, x: &EvalMessage) {
+        if x.severity != EvalSeverity::Disabled {
+            *self.0.entry(Self::key(x)).or_default() += 1;
+        }
+    }
+
+    /// If the message is in the baseline, remove one copy of it and return `true`.
+    pub(crate) fn take(&mut self, x: &EvalMessage) -> bool {
+        match self.0.get_mut(&Self::key(x)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The baseline to hide messages with, and the one to record them in, if asked for.
+#[derive(Debug, Default)]
+pub(crate) struct Baselines {
+    pub(crate) existing: Option<Baseline>,
+    pub(crate) record: Option<Baseline>,
+}
+
+impl Baselines {
+    /// Whether to report the message, which is recorded whether or not it is.
+    pub(crate) fn keep(&mut self, x: &EvalMessage) -> bool {
+        if let Some(record) = &mut self.record {
+            record.add(x);
+        }
+        !self.existing.as_mut().map_or(false, |b| b.take(x))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use starlark::syntax::AstModule;
+    use starlark::syntax::Dialect;
+
+    use super::*;
+
+    fn messages(code: &str) -> Vec<EvalMessage> {
+        let module = AstModule::parse("a.star", code.to_owned(), &Dialect::Extended).unwrap();
+        module
+            .lint(None)
+            .into_iter()
+            .map(EvalMessage::from)
+            .filter(|x| x.severity != EvalSeverity::Disabled)
+            .collect()
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        assert_eq!(fingerprint("x  =\n  1"), fingerprint("x = 1"));
+        assert_ne!(fingerprint("x = 1"), fingerprint("x = 2"));
+    }
+
+    #[test]
+    fn test_baseline() {
+        let mut baseline = Baseline::default();
+        for x in messages("def f():\n    return\n    x = 1\n") {
+            baseline.add(&x);
+        }
+        // The existing problem moves down a line, and a copy of it is added.
+        let now = messages("def f():\n\n    return\n    x = 1\ndef g():\n    return\n    x = 1\n");
+        assert_eq!(now.len(), 2);
+        let kept: Vec<_> = now.iter().filter(|x| !baseline.take(x)).collect();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].span.unwrap().begin_line, 6);
+    }
+}
@@ -0,0 +1,318 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The `--dap` mode, which debugs the program named in the `launch` request.
+//! Because DAP debugging is hard, we write everything we see to a log file.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::iter;
+use std::path::Path;
+use std::path::PathBuf;
+
+use gazebo::prelude::*;
+use serde_json::Map;
+use serde_json::Value;
+use starlark::debug::dap::DapContext;
+use starlark::debug::dap::Debuggee;
+use starlark::environment::FrozenModule;
+use starlark::environment::Module;
+use starlark::eval::Evaluator;
+use starlark::eval::FileLoader;
+use starlark::syntax::AstModule;
+
+use crate::eval::dialect;
+use crate::eval::globals;
+
+// Debugging anything through DAP is a nightmare, because VS Code doesn't surface any logs.
+// Therefore, do the hacky thing of putting logs next to the binary.
+fn log_file() -> PathBuf {
+    let mut res = env::current_exe().unwrap();
+    res.set_extension("dap.log");
+    res
+}
+
+fn log_begin() {
+    File::create(log_file()).unwrap();
+}
+
+fn log(x: &str) {
+    let mut file = OpenOptions::new().append(true).open(log_file()).unwrap();
+    file.write_all(format!("{}\n", x).as_bytes()).unwrap()
+}
+
+struct Context;
+
+impl DapContext for Context {
+    fn launch(&self, args: &Map<String, Value>, debuggee: &Debuggee) -> anyhow::Result<String> {
+        // Expecting program of type string
+        let path = match args.get("program") {
+            Some(Value::String(path)) => PathBuf::from(path),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Couldn't find a program to launch, got args {:?}",
+                    args
+                ));
+            }
+        };
+        let root = match args.get("root") {
+            None => None,
+            Some(Value::String(root)) => Some(canonical(PathBuf::from(root))),
+            Some(root) => {
+                return Err(anyhow::anyhow!(
+                    "Expected `root` to be a string, got {}",
+                    root
+                ));
+            }
+        };
+        let prelude = match args.get("prelude") {
+            None => Vec::new(),
+            Some(Value::Array(xs)) if xs.iter().all(|x| x.is_string()) => {
+                xs.map(|x| x.as_str().unwrap().to_owned())
+            }
+            Some(prelude) => {
+                return Err(anyhow::anyhow!(
+                    "Expected `prelude` to be a list of strings, got {}",
+                    prelude
+                ));
+            }
+        };
+        let mut program = Program::new(Some(debuggee), root);
+        program.eval_prelude(&prelude)?;
+        let (_, res) = program.eval_file(&canonical(path))?;
+        Ok(res)
+    }
+
+    fn parse_file(&self, path: &Path) -> anyhow::Result<AstModule> {
+        AstModule::parse_file(path, &dialect())
+    }
+
+    fn log(&self, message: &str) {
+        log(message)
+    }
+}
+
+/// The absolute path to a file, without symbolic links, so it is named the same way as in
+/// the breakpoints the client sets. Files which can't be found are left for parsing to report.
+fn canonical(path: PathBuf) -> PathBuf {
+    fs::canonicalize(&path).unwrap_or(path)
+}
+
+/// The program being debugged, and the modules it has loaded so far.
+struct Program<'a> {
+    /// The debugger to evaluate modules with, or `None` to evaluate them without one.
+    debuggee: Option<&'a Debuggee>,
+    /// The directory loads are relative to, or `None` to load relative to the loading file.
+    root: Option<PathBuf>,
+    /// Modules whose public symbols are available to every other module.
+    prelude: Vec<FrozenModule>,
+    /// Modules which have already been loaded, so are not evaluated again.
+    loaded: RefCell<HashMap<PathBuf, FrozenModule>>,
+    /// The files being evaluated, each loaded by the one before it.
+    loading: RefCell<Vec<PathBuf>>,
+}
+
+impl<'a> Program<'a> {
+    fn new(debuggee: Option<&'a Debuggee>, root: Option<PathBuf>) -> Self {
+        Self {
+            debuggee,
+            root,
+            prelude: Vec::new(),
+            loaded: Default::default(),
+            loading: Default::default(),
+        }
+    }
+
+    /// Evaluate the files of the prelude, relative to the current directory. As with
+    /// `--prelude`, each file only sees the builtins, not the files before it.
+    fn eval_prelude(&mut self, paths: &[String]) -> anyhow::Result<()> {
+        let prelude = paths.try_map(|path| {
+            let path = self.resolve(path, Path::new(""));
+            self.eval_file(&path)?.0.freeze()
+        })?;
+        self.prelude = prelude;
+        Ok(())
+    }
+
+    /// The file a load in the directory `dir` refers to.
+    fn resolve(&self, path: &str, dir: &Path) -> PathBuf {
+        canonical(self.root.as_deref().unwrap_or(dir).join(path))
+    }
+
+    /// Evaluate a file, with the prelude available. Fails if the file is already being
+    /// evaluated, since it must have loaded itself.
+    fn eval_file(&self, path: &Path) -> anyhow::Result<(Module, String)> {
+        {
+            let mut loading = self.loading.borrow_mut();
+            if let Some(i) = loading.iter().position(|x| x == path) {
+                let cycle = loading[i..]
+                    .iter()
+                    .map(|x| x.as_path())
+                    .chain(iter::once(path))
+                    .map(|x| x.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                return Err(anyhow::anyhow!("Cycle in loads: {}", cycle));
+            }
+            loading.push(path.to_owned());
+        }
+        let res = self.eval_file_unchecked(path);
+        self.loading.borrow_mut().pop();
+        res
+    }
+
+    fn eval_file_unchecked(&self, path: &Path) -> anyhow::Result<(Module, String)> {
+        let ast = AstModule::parse_file(path, &dialect())?;
+        let module = Module::new();
+        for p in &self.prelude {
+            module.import_public_symbols(p);
+        }
+        let loader = ProgramLoader {
+            program: self,
+            dir: path.parent().map(|x| x.to_owned()).unwrap_or_default(),
+        };
+        let res = {
+            let mut eval = Evaluator::new(&module);
+            eval.set_loader(&loader);
+            let globals = globals();
+            match self.debuggee {
+                Some(debuggee) => debuggee.eval_module(ast, &globals, &mut eval)?,
+                None => eval.eval_module(ast, &globals)?,
+            }
+            .to_string()
+        };
+        Ok((module, res))
+    }
+}
+
+/// Evaluates loaded modules with the debugger attached, so we can break and step
+/// inside the functions they define.
+struct ProgramLoader<'a> {
+    program: &'a Program<'a>,
+    dir: PathBuf,
+}
+
+impl FileLoader for ProgramLoader<'_> {
+    fn load(&self, path: &str) -> anyhow::Result<FrozenModule> {
+        let path = self.program.resolve(path, &self.dir);
+        if let Some(module) = self.program.loaded.borrow().get(&path) {
+            return Ok(module.dupe());
+        }
+        let module = self.program.eval_file(&path)?.0.freeze()?;
+        self.program.loaded.borrow_mut().insert(path, module.dupe());
+        Ok(module)
+    }
+}
+
+pub(crate) fn server() -> anyhow::Result<()> {
+    log_begin();
+
+    // Because of the eval we're running in, we probably can't see panics.
+    // So mirror them to the log file.
+    let orig_hook = std::panic::take_hook();
+    std::panic::set_hook(box move |panic_info| {
+        if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
+            log(&format!("Panic occurred: {:?}", s));
+        } else {
+            log("Panic occurred: Unknown message");
+        }
+        orig_hook(panic_info);
+    });
+
+    starlark::debug::dap::server(Context, io::stdin(), io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    /// A directory of its own in the temporary directory, removed when this is dropped,
+    /// even if the test fails.
+    struct TempDir {
+        dir: PathBuf,
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// A [`TempDir`] holding the `files`.
+    fn files(name: &str, files: &[(&str, &str)]) -> TempDir {
+        let dir = env::temp_dir().join(format!("starlark-test-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+        TempDir { dir: canonical(dir) }
+    }
+
+    #[test]
+    fn test_prelude_sees_only_builtins() {
+        let temp = files(
+            "dap-prelude",
+            &[
+                ("a.star", "x = 1\n"),
+                ("b.star", "y = 2\nz = x\n"),
+                ("main.star", "x + y\n"),
+            ],
+        );
+        let dir = &temp.dir;
+        let prelude = |files: &[&str]| files.map(|x| dir.join(x).to_str().unwrap().to_owned());
+        let mut program = Program::new(None, None);
+
+        let err = program.eval_prelude(&prelude(&["a.star", "b.star"]));
+        assert!(err.unwrap_err().to_string().contains("Variable `x` not found"));
+
+        fs::write(dir.join("b.star"), "y = 2\n").unwrap();
+        program.eval_prelude(&prelude(&["a.star", "b.star"])).unwrap();
+        let (_, res) = program.eval_file(&dir.join("main.star")).unwrap();
+        assert_eq!(res, "3");
+    }
+
+    #[test]
+    fn test_load_cycle() {
+        let temp = files(
+            "dap-cycle",
+            &[
+                ("main.star", "load('a.star', 'a')\n"),
+                ("a.star", "load('b.star', 'b')\na = 1\n"),
+                ("b.star", "load('a.star', 'a')\nb = 1\n"),
+            ],
+        );
+        let dir = &temp.dir;
+        let program = Program::new(None, None);
+        let err = program.eval_file(&dir.join("main.star")).unwrap_err();
+        let cycle = format!(
+            "Cycle in loads: {0}/a.star -> {0}/b.star -> {0}/a.star",
+            dir.display()
+        );
+        assert!(format!("{:#}", err).contains(&cycle));
+        // Nothing is left half loaded.
+        assert!(program.loading.borrow().is_empty());
+    }
+}
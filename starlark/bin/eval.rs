@@ -15,20 +15,29 @@
  * limitations under the License.
  */
 
+use std::collections::HashMap;
+use std::env;
 use std::fs;
 use std::io;
 use std::iter;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Mutex;
 
+use anyhow::anyhow;
 use gazebo::prelude::*;
 use itertools::Either;
 use lsp_types::Diagnostic;
 use lsp_types::Url;
+use once_cell::sync::OnceCell;
+use starlark::debug::trace::TraceRecorder;
 use starlark::environment::FrozenModule;
 use starlark::environment::Globals;
 use starlark::environment::Module;
 use starlark::errors::EvalMessage;
+use starlark::errors::LintConfig;
+use starlark::errors::LintOptions;
+use starlark::errors::Signatures;
 use starlark::eval::Evaluator;
 use starlark::lsp::server::LoadContentsError;
 use starlark::lsp::server::LspContext;
@@ -37,6 +46,7 @@ use starlark::lsp::server::ResolveLoadError;
 use starlark::lsp::server::StringLiteralResult;
 use starlark::syntax::AstModule;
 use starlark::syntax::Dialect;
+use starlark::values::docs::DocItem;
 
 #[derive(Debug)]
 pub(crate) enum ContextMode {
@@ -49,7 +59,18 @@ pub(crate) struct Context {
     pub(crate) mode: ContextMode,
     pub(crate) print_non_none: bool,
     pub(crate) prelude: Vec<FrozenModule>,
-    pub(crate) module: Option<Module>,
+    /// Behind a mutex so the LSP server can share the context between threads.
+    pub(crate) module: Option<Mutex<Module>>,
+    /// The documentation of the globals, computed the first time the LSP server asks for it.
+    pub(crate) global_documentation: OnceCell<HashMap<String, Option<DocItem>>>,
+    /// Which lints to report, and how severely.
+    pub(crate) lint_config: LintConfig,
+    /// The parameters of the globals and prelude functions, to check calls against.
+    signatures: Signatures,
+    /// The names of the globals and prelude symbols, which are in scope in every file.
+    builtins: Vec<String>,
+    /// Records the statements run, if a trace was asked for.
+    pub(crate) trace: Option<TraceRecorder>,
 }
 
 /// The outcome of evaluating (checking, parsing or running) given starlark code.
@@ -78,8 +99,19 @@ impl Context {
             env.freeze()
         })?;
 
+        let mut builtins: Vec<String> = globals.names().map(|x| x.as_str().to_owned()).collect();
+        for p in &prelude {
+            builtins.extend(p.names().map(|x| x.as_str().to_owned()));
+        }
+
+        let mut signatures = Signatures::new();
+        signatures.add_globals(&globals.member_documentation());
+        for p in &prelude {
+            signatures.add_globals(&p.module_documentation().members);
+        }
+
         let module = if module {
-            Some(Self::new_module(&prelude))
+            Some(Mutex::new(Self::new_module(&prelude)))
         } else {
             None
         };
@@ -89,6 +121,11 @@ impl Context {
             print_non_none,
             prelude,
             module,
+            global_documentation: OnceCell::new(),
+            lint_config: LintConfig::default(),
+            signatures,
+            builtins,
+            trace: None,
         })
     }
 
@@ -105,7 +142,7 @@ impl Context {
         let mut errors = Either::Left(iter::empty());
         let final_ast = match self.mode {
             ContextMode::Check => {
-                warnings = Either::Right(self.check(&ast));
+                warnings = Either::Right(self.check(file, &ast));
                 Some(ast)
             }
             ContextMode::Run => {
@@ -170,8 +207,12 @@ impl Context {
 
     fn run(&self, file: &str, ast: AstModule) -> EvalResult<impl Iterator<Item = EvalMessage>> {
         let new_module;
+        let locked_module;
         let module = match self.module.as_ref() {
-            Some(module) => module,
+            Some(module) => {
+                locked_module = module.lock().unwrap();
+                &*locked_module
+            }
             None => {
                 new_module = Self::new_module(&self.prelude);
                 &new_module
@@ -182,7 +223,11 @@ impl Context {
         let globals = globals();
         Self::err(
             file,
-            eval.eval_module(ast, &globals).map(|v| {
+            match &self.trace {
+                Some(trace) => trace.eval_module(ast, &globals, &mut eval),
+                None => eval.eval_module(ast, &globals),
+            }
+            .map(|v| {
                 if self.print_non_none && !v.is_none() {
                     println!("{}", v);
                 }
@@ -194,18 +239,55 @@ impl Context {
         )
     }
 
-    fn check(&self, module: &AstModule) -> impl Iterator<Item = EvalMessage> {
-        let mut globals = Vec::new();
-        for x in &self.prelude {
-            globals.extend(x.names().map(|s| s.as_str()));
+    /// The known signatures, plus those of the files `module` loads which can be parsed.
+    fn signatures(&self, file: &str, module: &AstModule) -> Signatures {
+        let mut signatures = self.signatures.clone();
+        for load in module.loads() {
+            let loaded = self
+                .resolve_load(load, Path::new(file))
+                .ok()
+                .and_then(|url| AstModule::parse_file(Path::new(url.path()), &dialect()).ok());
+            if let Some(loaded) = loaded {
+                signatures.add_module_ast(load, &loaded);
+            }
         }
-        let globals = if self.prelude.is_empty() {
-            None
-        } else {
-            Some(globals.as_slice())
-        };
+        signatures
+    }
 
-        module.lint(globals).into_iter().map(EvalMessage::from)
+    fn check(&self, file: &str, module: &AstModule) -> impl Iterator<Item = EvalMessage> {
+        let globals = self.builtins.map(|x| x.as_str());
+        module
+            .lint_with_options(&LintOptions {
+                globals: Some(&globals),
+                signatures: Some(&self.signatures(file, module)),
+                config: Some(&self.lint_config),
+            })
+            .into_iter()
+            .map(EvalMessage::from)
+    }
+
+    /// Lint the checked `modules`, parsed from `files`, together. Loads are resolved
+    /// relative to the loading file, and only checked if they load one of the `files`.
+    pub(crate) fn check_workspace(
+        &self,
+        files: &[PathBuf],
+        modules: &[AstModule],
+    ) -> impl Iterator<Item = EvalMessage> {
+        let names: HashMap<PathBuf, String> = files
+            .iter()
+            .filter_map(|x| Some((fs::canonicalize(x).ok()?, x.to_string_lossy().into_owned())))
+            .collect();
+        let resolve_load = |path: &str, current: &str| {
+            let dir = Path::new(current).parent().unwrap_or_else(|| Path::new(""));
+            let path = fs::canonicalize(dir.join(path))?;
+            names
+                .get(&path)
+                .cloned()
+                .ok_or_else(|| anyhow!("`{}` is not being checked", path.display()))
+        };
+        AstModule::lint_workspace(modules, resolve_load, Some(&self.lint_config))
+            .into_iter()
+            .map(EvalMessage::from)
     }
 }
 
@@ -226,6 +308,8 @@ impl LspContext for Context {
             (Some(current_file_dir), false) => Ok(current_file_dir.join(&path)),
             (None, false) => Err(ResolveLoadError::MissingCurrentFilePath(path)),
         }?;
+        // Files named on the command line may be relative to the working directory.
+        let absolute_path = env::current_dir()?.join(absolute_path);
         Ok(Url::from_file_path(absolute_path).unwrap())
     }
 
@@ -242,6 +326,13 @@ impl LspContext for Context {
         })
     }
 
+    fn get_global_documentation(&self, name: &str) -> Option<DocItem> {
+        self.global_documentation
+            .get_or_init(|| globals().member_documentation())
+            .get(name)?
+            .clone()
+    }
+
     fn get_load_contents(&self, uri: &Url) -> anyhow::Result<Option<String>> {
         let path = PathBuf::from(uri.path());
         match path.is_absolute() {
@@ -0,0 +1,271 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Apply the fixes attached to lints to the files they were found in, for `--check --fix`.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+
+use starlark::codemap::ResolvedSpan;
+use starlark::errors::EvalMessage;
+use starlark::errors::EvalSeverity;
+use starlark::errors::LintFix;
+use starlark::syntax::AstModule;
+use starlark::syntax::Dialect;
+
+use crate::eval::dialect;
+use crate::eval::Context;
+use crate::eval::EvalResult;
+
+/// Fixes which overlap are applied in successive rounds, each checking the files again,
+/// so give up if they never settle.
+const MAX_ROUNDS: usize = 10;
+
+/// The fixes to apply, by the path of the file they edit.
+#[derive(Debug, Default)]
+pub(crate) struct Fixes(BTreeMap<String, Vec<LintFix>>);
+
+impl Fixes {
+    /// Record the first fix of a message which is safe to apply automatically,
+    /// unless the message is disabled, so not reported.
+    pub(crate) fn add(&mut self, x: &EvalMessage) {
+        if x.severity == EvalSeverity::Disabled {
+            return;
+        }
+        if let Some(fix) = x.fixes.iter().find(|x| x.safe) {
+            self.0.entry(x.path.clone()).or_default().push(fix.clone());
+        }
+    }
+
+    /// Apply the fixes to each file, checking the result still parses before writing it.
+    /// Returns the number of fixes applied to each file changed.
+    pub(crate) fn apply(self, dialect: &Dialect) -> anyhow::Result<BTreeMap<String, usize>> {
+        let mut res = BTreeMap::new();
+        for (path, xs) in self.0 {
+            let (contents, applied) = apply_fixes(&fs::read_to_string(&path)?, &xs);
+            if applied == 0 {
+                continue;
+            }
+            if let Err(e) = AstModule::parse(&path, contents.clone(), dialect) {
+                eprintln!(
+                    "Not fixing `{}`, since the fixed file does not parse: {}",
+                    path, e
+                );
+                continue;
+            }
+            fs::write(&path, contents)?;
+            res.insert(path, applied);
+        }
+        Ok(res)
+    }
+}
+
+/// Check the files and apply the fixes of the lints found, until none are left which
+/// can be applied. Returns the number of fixes applied, and the number of files changed.
+pub(crate) fn fix_files(ctx: &Context, files: &[PathBuf]) -> anyhow::Result<(usize, usize)> {
+    let mut fixed = 0;
+    let mut changed = BTreeSet::new();
+    for _ in 0..MAX_ROUNDS {
+        let mut fixes = Fixes::default();
+        let mut checked = Vec::new();
+        let mut modules = Vec::new();
+        for file in files {
+            let EvalResult { messages, ast } = ctx.file(file);
+            messages.for_each(|x| fixes.add(&x));
+            if let Some(ast) = ast {
+                checked.push(file.clone());
+                modules.push(ast);
+            }
+        }
+        ctx.check_workspace(&checked, &modules)
+            .for_each(|x| fixes.add(&x));
+        let applied = fixes.apply(&dialect())?;
+        if applied.is_empty() {
+            break;
+        }
+        fixed += applied.values().sum::<usize>();
+        changed.extend(applied.into_keys());
+    }
+    Ok((fixed, changed.len()))
+}
+
+/// The byte offsets in `source` of the beginning and end of the span, if it is within it.
+fn byte_range(lines: &[(usize, &str)], span: &ResolvedSpan) -> Option<(usize, usize)> {
+    let offset = |line: usize, column: usize| {
+        let (start, text) = lines.get(line)?;
+        let byte = if column == text.chars().count() {
+            text.len()
+        } else {
+            text.char_indices().nth(column)?.0
+        };
+        Some(start + byte)
+    };
+    Some((
+        offset(span.begin_line, span.begin_column)?,
+        offset(span.end_line, span.end_column)?,
+    ))
+}
+
+/// Apply those of the `fixes` which don't overlap an earlier one to the `source`,
+/// returning the result and the number applied.
+fn apply_fixes(source: &str, fixes: &[LintFix]) -> (String, usize) {
+    // Each line with its offset, keeping the newline, so the end of a line is just before
+    // the start of the next.
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in source.split_inclusive('\n') {
+        lines.push((start, line));
+        start += line.len();
+    }
+    lines.push((start, ""));
+
+    let mut edits: Vec<(usize, usize, &str)> = Vec::new();
+    let mut applied = 0;
+    for fix in fixes {
+        let ranges: Option<Vec<_>> = fix
+            .edits
+            .iter()
+            .map(|x| byte_range(&lines, &x.span).map(|(b, e)| (b, e, x.replacement.as_str())))
+            .collect();
+        let ranges = match ranges {
+            Some(ranges) => ranges,
+            None => continue,
+        };
+        // Two insertions at the same place also overlap, since their order matters.
+        let overlaps = |(b1, e1, _): &(usize, usize, &str), (b2, e2, _): &(usize, usize, &str)| {
+            (b1 < e2 && b2 < e1) || b1 == b2
+        };
+        let conflict = ranges
+            .iter()
+            .enumerate()
+            .any(|(i, x)| edits.iter().chain(&ranges[..i]).any(|y| overlaps(x, y)));
+        if !conflict {
+            edits.extend(ranges);
+            applied += 1;
+        }
+    }
+
+    edits.sort_by_key(|(b, _, _)| *b);
+    let mut res = String::with_capacity(source.len());
+    let mut pos = 0;
+    for (begin, end, replacement) in edits {
+        res.push_str(&source[pos..begin]);
+        res.push_str(replacement);
+        pos = end;
+    }
+    res.push_str(&source[pos..]);
+    (res, applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use starlark::errors::LintConfig;
+    use starlark::errors::LintEdit;
+    use starlark::errors::LintOptions;
+
+    use super::*;
+
+    fn messages(code: &str, config: &LintConfig) -> Vec<EvalMessage> {
+        let module = AstModule::parse("a.star", code.to_owned(), &Dialect::Extended).unwrap();
+        module
+            .lint_with_options(&LintOptions {
+                config: Some(config),
+                ..LintOptions::default()
+            })
+            .into_iter()
+            .map(EvalMessage::from)
+            .collect()
+    }
+
+    fn edit(begin: (usize, usize), end: (usize, usize), replacement: &str) -> LintFix {
+        LintFix {
+            title: String::new(),
+            edits: vec![LintEdit {
+                span: ResolvedSpan {
+                    begin_line: begin.0,
+                    begin_column: begin.1,
+                    end_line: end.0,
+                    end_column: end.1,
+                },
+                replacement: replacement.to_owned(),
+            }],
+            safe: true,
+        }
+    }
+
+    #[test]
+    fn test_apply_fixes() {
+        let source = "é = 1\nx = 2\n";
+        let fixes = [
+            edit((0, 4), (0, 5), "3"),
+            // Overlaps the first, so is skipped.
+            edit((0, 0), (0, 5), "y = 4"),
+            edit((1, 0), (2, 0), ""),
+            // Beyond the end of a line, so stale.
+            edit((1, 9), (1, 10), "z"),
+        ];
+        assert_eq!(apply_fixes(source, &fixes), ("é = 3\n".to_owned(), 2));
+    }
+
+    #[test]
+    fn test_fixes_reported_lints() {
+        let source = r#"load("b.star", "y")
+load("a.star", "x", "unused")
+def f(k, v):
+    print(x, y, dict(**k))
+    return
+"#;
+        let fixed = |config: &LintConfig| {
+            let mut fixes = Fixes::default();
+            messages(source, config).iter().for_each(|x| fixes.add(x));
+            apply_fixes(source, &fixes.0.remove("a.star").unwrap_or_default()).0
+        };
+        // Unused loads, unsorted loads, redundant returns and unused arguments are all
+        // disabled by default, so only the dict copy is fixed.
+        assert_eq!(
+            fixed(&LintConfig::default()),
+            source.replace("dict(**k)", "dict(k)")
+        );
+        // Renaming an unused argument is never safe, since callers may pass it by keyword.
+        let config = LintConfig {
+            enable: ["unused-load", "redundant-return", "unused-argument"]
+                .iter()
+                .map(|x| (*x).to_owned())
+                .collect(),
+            ..LintConfig::default()
+        };
+        assert_eq!(
+            fixed(&config),
+            "load(\"b.star\", \"y\")\nload(\"a.star\", \"x\")\ndef f(k, v):\n    print(x, y, dict(k))\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_lint_fixes() {
+        let source = "def f(x):\n    print(dict(**x))\n    return\n";
+        let fixes: Vec<LintFix> = messages(source, &LintConfig::default())
+            .iter()
+            .filter_map(|x| x.fixes.first().cloned())
+            .collect();
+        assert_eq!(
+            apply_fixes(source, &fixes),
+            ("def f(x):\n    print(dict(x))\n".to_owned(), 2)
+        );
+    }
+}
@@ -29,26 +29,41 @@
 use std::ffi::OsStr;
 use std::fmt;
 use std::fmt::Display;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use anyhow::anyhow;
 use eval::Context;
+use eval::EvalResult;
 use gazebo::prelude::*;
 use itertools::Either;
+use starlark::debug::trace::TraceRecorder;
 use starlark::errors::EvalMessage;
 use starlark::errors::EvalSeverity;
 use starlark::lsp;
 use starlark::read_line::ReadLine;
+use starlark::syntax::AstModule;
 use structopt::clap::AppSettings;
 use structopt::StructOpt;
 use walkdir::WalkDir;
 
+use crate::baseline::Baseline;
+use crate::baseline::Baselines;
+use crate::eval::dialect;
 use crate::eval::ContextMode;
+use crate::output::FailOn;
+use crate::output::OutputFormat;
 use crate::types::LintMessage;
+use crate::types::MetricsMessage;
 
+mod baseline;
 mod dap;
 mod eval;
+mod fix;
+mod output;
 mod types;
 
 #[derive(Debug, StructOpt)]
@@ -66,6 +81,11 @@ struct Args {
             "dap",
             "check",
             "json",
+            "output-format",
+            "fail-on",
+            "baseline",
+            "write-baseline",
+            "fix",
             "evaluate",
             "files",
         ],
@@ -81,6 +101,15 @@ struct Args {
             "lsp",
             "check",
             "json",
+            "output-format",
+            "fail-on",
+            "baseline",
+            "write-baseline",
+            "fix",
+            "record-trace",
+            "lint-config",
+            "typecheck",
+            "metrics",
             "extension",
             "prelude",
             "evaluate",
@@ -103,6 +132,76 @@ struct Args {
     )]
     json: bool,
 
+    #[structopt(
+        long = "output-format",
+        help = "How to show the output: `text`, `json`, `sarif` or `checkstyle`.",
+        conflicts_with_all = &["lsp", "dap", "json"],
+    )]
+    output_format: Option<OutputFormat>,
+
+    #[structopt(
+        long = "fail-on",
+        help = "The least severe messages which give a non-zero exit code: `error`, `warning`, `advice` or `never`. Defaults to `error` for text output and `never` otherwise.",
+        conflicts_with_all = &["lsp", "dap"],
+    )]
+    fail_on: Option<FailOn>,
+
+    #[structopt(
+        long = "baseline",
+        help = "A baseline file of existing lints, which are not reported.",
+        requires = "check"
+    )]
+    baseline: Option<PathBuf>,
+
+    #[structopt(
+        long = "write-baseline",
+        help = "Write the lints found to a baseline file, for use with `--baseline`.",
+        requires = "check"
+    )]
+    write_baseline: Option<PathBuf>,
+
+    #[structopt(
+        long = "fix",
+        help = "Apply the safe fixes of the lints reported, editing the files in place, then report the lints which remain. Disabled lints are not fixed.",
+        requires = "check"
+    )]
+    fix: bool,
+
+    #[structopt(
+        long = "record-trace",
+        help = "Record the statements executed to a file, which the DAP server can replay.",
+        conflicts_with_all = &["lsp", "dap", "check"],
+    )]
+    record_trace: Option<PathBuf>,
+
+    #[structopt(
+        long = "trace-locals",
+        help = "Record the local variables which change in the trace.",
+        requires = "record-trace"
+    )]
+    trace_locals: bool,
+
+    #[structopt(
+        long = "lint-config",
+        help = "A JSON file choosing which lints to report, and how severely.",
+        conflicts_with_all = &["dap"],
+    )]
+    lint_config: Option<PathBuf>,
+
+    #[structopt(
+        long = "typecheck",
+        help = "Check types statically, as part of the lints.",
+        conflicts_with_all = &["dap"],
+    )]
+    typecheck: bool,
+
+    #[structopt(
+        long = "metrics",
+        help = "Show the size and complexity metrics of each file and its functions, as JSON lines.",
+        conflicts_with_all = &["lsp", "dap", "check", "evaluate"],
+    )]
+    metrics: bool,
+
     #[structopt(
         long = "extension",
         help = "File extension when searching directories."
@@ -181,21 +280,48 @@ impl Stats {
             EvalSeverity::Disabled => self.disabled += 1,
         }
     }
+
+    /// The number of messages which cause failure.
+    fn failures(&self, fail_on: FailOn) -> usize {
+        [
+            (EvalSeverity::Error, self.error),
+            (EvalSeverity::Warning, self.warning),
+            (EvalSeverity::Advice, self.advice),
+            (EvalSeverity::Disabled, self.disabled),
+        ]
+        .iter()
+        .filter(|(severity, _)| fail_on.fails(*severity))
+        .map(|(_, count)| count)
+        .sum()
+    }
 }
 
-fn drain(xs: impl Iterator<Item = EvalMessage>, json: bool, stats: &mut Stats) {
+/// Print the messages, or for formats which are a single document, save them in `report`
+/// to print at the end.
+fn drain(
+    xs: impl Iterator<Item = EvalMessage>,
+    format: OutputFormat,
+    stats: &mut Stats,
+    report: &mut Vec<EvalMessage>,
+) {
     for x in xs {
         stats.increment(x.severity);
-        if json {
-            println!("{}", serde_json::to_string(&LintMessage::new(x)).unwrap());
-        } else if let Some(error) = x.full_error_with_span {
-            let mut error = error.to_owned();
-            if !error.is_empty() && !error.ends_with('\n') {
-                error.push('\n');
+        match format {
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string(&LintMessage::new(x)).unwrap())
+            }
+            OutputFormat::Sarif | OutputFormat::Checkstyle => report.push(x),
+            OutputFormat::Text => {
+                if let Some(error) = x.full_error_with_span {
+                    let mut error = error.to_owned();
+                    if !error.is_empty() && !error.ends_with('\n') {
+                        error.push('\n');
+                    }
+                    print!("{}", error);
+                } else {
+                    println!("{}", x);
+                }
             }
-            print!("{}", error);
-        } else {
-            println!("{}", x);
         }
     }
 }
@@ -206,7 +332,12 @@ fn interactive(ctx: &Context) -> anyhow::Result<()> {
         match rl.read_line("$> ")? {
             Some(line) => {
                 let mut stats = Stats::default();
-                drain(ctx.expression(line).messages, false, &mut stats);
+                drain(
+                    ctx.expression(line).messages,
+                    OutputFormat::Text,
+                    &mut stats,
+                    &mut Vec::new(),
+                );
             }
             // User pressed EOF - disconnected terminal, or similar
             None => return Ok(()),
@@ -220,7 +351,7 @@ fn main() -> anyhow::Result<()> {
     let args = argfile::expand_args(argfile::parse_fromfile, argfile::PREFIX)?;
     let args: Args = Args::from_iter(args);
     if args.dap {
-        dap::server();
+        dap::server()?;
     } else {
         let is_interactive = args.evaluate.is_empty() && args.files.is_empty();
 
@@ -239,30 +370,119 @@ fn main() -> anyhow::Result<()> {
             &expand_dirs(ext, args.prelude).collect::<Vec<_>>(),
             is_interactive,
         )?;
+        if let Some(path) = &args.lint_config {
+            ctx.lint_config = serde_json::from_str(&fs::read_to_string(path)?)
+                .map_err(|e| anyhow!("Invalid lint config `{}`: {}", path.display(), e))?;
+        }
+        ctx.lint_config.typecheck |= args.typecheck;
+        if let Some(path) = &args.record_trace {
+            let file = BufWriter::new(File::create(path)?);
+            ctx.trace = Some(TraceRecorder::new(file, args.trace_locals));
+        }
 
         if args.lsp {
             ctx.mode = ContextMode::Check;
             lsp::server::stdio_server(ctx)?;
         } else if is_interactive {
             interactive(&ctx)?;
+        } else if args.metrics {
+            let mut errors = 0;
+            for file in expand_dirs(ext, args.files.clone()) {
+                match AstModule::parse_file(&file, &dialect()) {
+                    Ok(module) => println!(
+                        "{}",
+                        serde_json::to_string(&MetricsMessage::new(&file, module.metrics()))?
+                    ),
+                    Err(e) => {
+                        errors += 1;
+                        eprintln!("{}", EvalMessage::from_anyhow(&file.to_string_lossy(), &e));
+                    }
+                }
+            }
+            if errors > 0 {
+                return Err(anyhow!("Failed with {} errors", errors));
+            }
         } else {
+            let format = args.output_format.unwrap_or(if args.json {
+                OutputFormat::Json
+            } else {
+                OutputFormat::Text
+            });
             let mut stats = Stats::default();
+            let mut report = Vec::new();
+            let mut baselines = Baselines {
+                existing: args
+                    .baseline
+                    .as_ref()
+                    .map(|x| Baseline::read(x))
+                    .transpose()?,
+                record: args.write_baseline.as_ref().map(|_| Baseline::default()),
+            };
             for e in args.evaluate.clone() {
                 stats.increment_file();
-                drain(ctx.expression(e).messages, args.json, &mut stats);
+                drain(
+                    ctx.expression(e).messages.filter(|x| baselines.keep(x)),
+                    format,
+                    &mut stats,
+                    &mut report,
+                );
             }
 
-            for file in expand_dirs(ext, args.files.clone()) {
-                stats.increment_file();
-                drain(ctx.file(&file).messages, args.json, &mut stats);
+            let paths: Vec<PathBuf> = expand_dirs(ext, args.files.clone()).collect();
+            if args.fix {
+                let (fixed, changed) = fix::fix_files(&ctx, &paths)?;
+                eprintln!("Applied {} fixes to {} files", fixed, changed);
             }
 
-            if !args.json {
-                println!("{}", stats);
-                if stats.error > 0 {
-                    return Err(anyhow!("Failed with {} errors", stats.error));
+            let mut files = Vec::new();
+            let mut modules = Vec::new();
+            for file in paths {
+                stats.increment_file();
+                let EvalResult { messages, ast } = ctx.file(&file);
+                drain(
+                    messages.filter(|x| baselines.keep(x)),
+                    format,
+                    &mut stats,
+                    &mut report,
+                );
+                if let Some(ast) = ast {
+                    files.push(file);
+                    modules.push(ast);
                 }
             }
+            if args.check {
+                drain(
+                    ctx.check_workspace(&files, &modules)
+                        .filter(|x| baselines.keep(x)),
+                    format,
+                    &mut stats,
+                    &mut report,
+                );
+            }
+
+            if let Some(trace) = ctx.trace.take() {
+                trace.finish()?;
+            }
+
+            if let (Some(path), Some(baseline)) = (&args.write_baseline, &baselines.record) {
+                baseline.write(path)?;
+            }
+
+            match format {
+                OutputFormat::Text => println!("{}", stats),
+                OutputFormat::Json => {}
+                OutputFormat::Sarif => println!("{}", output::sarif(&report)),
+                OutputFormat::Checkstyle => print!("{}", output::checkstyle(&report)),
+            }
+            let fail_on = args.fail_on.unwrap_or(if format == OutputFormat::Text {
+                FailOn::Error
+            } else {
+                FailOn::Never
+            });
+            let failures = stats.failures(fail_on);
+            if failures > 0 {
+                return Err(anyhow!("Failed with {} {}", failures, fail_on.describe()));
+            }
         }
     }
     Ok(())
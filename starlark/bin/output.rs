@@ -0,0 +1,440 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The formats `starlark --check` can report its messages in, for tools to consume.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use gazebo::prelude::*;
+use serde::Serialize;
+use starlark::codemap::ResolvedSpan;
+use starlark::errors::EvalMessage;
+use starlark::errors::EvalSeverity;
+
+#[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    /// Human readable, one message per line.
+    Text,
+    /// One [`LintMessage`](crate::types::LintMessage) per line.
+    Json,
+    /// A single SARIF 2.1.0 log.
+    Sarif,
+    /// A single checkstyle XML report.
+    Checkstyle,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            "checkstyle" => Ok(Self::Checkstyle),
+            _ => Err(anyhow!(
+                "Unknown output format `{}`, expected one of `text`, `json`, `sarif` or `checkstyle`",
+                s
+            )),
+        }
+    }
+}
+
+/// The least severe messages which make the process fail.
+#[derive(Debug, Clone, Copy, Dupe, PartialEq, Eq)]
+pub(crate) enum FailOn {
+    Error,
+    Warning,
+    Advice,
+    Never,
+}
+
+impl FromStr for FailOn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warning" => Ok(Self::Warning),
+            "advice" => Ok(Self::Advice),
+            "never" => Ok(Self::Never),
+            _ => Err(anyhow!(
+                "Unknown severity `{}`, expected one of `error`, `warning`, `advice` or `never`",
+                s
+            )),
+        }
+    }
+}
+
+impl FailOn {
+    /// Does a message of this severity cause failure.
+    pub(crate) fn fails(self, x: EvalSeverity) -> bool {
+        let rank = |x| match x {
+            EvalSeverity::Error => 3,
+            EvalSeverity::Warning => 2,
+            EvalSeverity::Advice => 1,
+            EvalSeverity::Disabled => 0,
+        };
+        match self {
+            Self::Error => rank(x) >= 3,
+            Self::Warning => rank(x) >= 2,
+            Self::Advice => rank(x) >= 1,
+            Self::Never => false,
+        }
+    }
+
+    /// The kind of messages which cause failure, in the plural.
+    pub(crate) fn describe(self) -> &'static str {
+        match self {
+            Self::Error => "errors",
+            Self::Warning => "errors and warnings",
+            Self::Advice | Self::Never => "errors, warnings and advices",
+        }
+    }
+}
+
+// The subset of the SARIF 2.1.0 format we produce, see
+// <https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html>.
+
+#[derive(Serialize)]
+struct SarifLog {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<SarifRun>,
+}
+
+#[derive(Serialize)]
+struct SarifRun {
+    tool: SarifTool,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Serialize)]
+struct SarifTool {
+    driver: SarifDriver,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifDriver {
+    name: &'static str,
+    information_uri: &'static str,
+    version: &'static str,
+    rules: Vec<SarifRule>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifRule {
+    id: String,
+    short_description: SarifMessage,
+    default_configuration: SarifConfiguration,
+    properties: SarifRuleProperties,
+}
+
+#[derive(Serialize)]
+struct SarifConfiguration {
+    level: &'static str,
+}
+
+#[derive(Serialize)]
+struct SarifRuleProperties {
+    serious: bool,
+}
+
+#[derive(Serialize)]
+struct SarifMessage {
+    text: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifResult {
+    rule_id: String,
+    rule_index: usize,
+    level: &'static str,
+    message: SarifMessage,
+    locations: Vec<SarifLocation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fixes: Vec<SarifFix>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifLocation {
+    physical_location: SarifPhysicalLocation,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifPhysicalLocation {
+    artifact_location: SarifArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    region: Option<SarifRegion>,
+}
+
+#[derive(Serialize)]
+struct SarifArtifactLocation {
+    uri: String,
+}
+
+/// SARIF lines and columns are 1-based, and the end column is exclusive.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifRegion {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+impl From<ResolvedSpan> for SarifRegion {
+    fn from(x: ResolvedSpan) -> Self {
+        Self {
+            start_line: x.begin_line + 1,
+            start_column: x.begin_column + 1,
+            end_line: x.end_line + 1,
+            end_column: x.end_column + 1,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifFix {
+    description: SarifMessage,
+    artifact_changes: Vec<SarifArtifactChange>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifArtifactChange {
+    artifact_location: SarifArtifactLocation,
+    replacements: Vec<SarifReplacement>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SarifReplacement {
+    deleted_region: SarifRegion,
+    inserted_content: SarifMessage,
+}
+
+fn sarif_level(x: EvalSeverity) -> &'static str {
+    match x {
+        EvalSeverity::Error => "error",
+        EvalSeverity::Warning => "warning",
+        EvalSeverity::Advice => "note",
+        EvalSeverity::Disabled => "none",
+    }
+}
+
+/// Render the messages as a SARIF log. Disabled messages are left out.
+pub(crate) fn sarif(messages: &[EvalMessage]) -> String {
+    let mut rules = Vec::new();
+    let mut rule_index = HashMap::new();
+    let mut results = Vec::new();
+    for x in messages {
+        if x.severity == EvalSeverity::Disabled {
+            continue;
+        }
+        let index = *rule_index.entry(x.name.clone()).or_insert_with(|| {
+            rules.push(SarifRule {
+                id: x.name.clone(),
+                short_description: SarifMessage {
+                    text: if x.name == "error" {
+                        "The program could not be parsed or evaluated".to_owned()
+                    } else {
+                        format!("The `{}` lint", x.name)
+                    },
+                },
+                default_configuration: SarifConfiguration {
+                    level: if x.serious { "warning" } else { "none" },
+                },
+                properties: SarifRuleProperties { serious: x.serious },
+            });
+            rules.len() - 1
+        });
+        let artifact = || SarifArtifactLocation {
+            uri: x.path.clone(),
+        };
+        results.push(SarifResult {
+            rule_id: x.name.clone(),
+            rule_index: index,
+            level: sarif_level(x.severity),
+            message: SarifMessage {
+                text: x.description.clone(),
+            },
+            locations: vec![SarifLocation {
+                physical_location: SarifPhysicalLocation {
+                    artifact_location: artifact(),
+                    region: x.span.map(SarifRegion::from),
+                },
+            }],
+            fixes: x.fixes.map(|fix| SarifFix {
+                description: SarifMessage {
+                    text: fix.title.clone(),
+                },
+                artifact_changes: vec![SarifArtifactChange {
+                    artifact_location: artifact(),
+                    replacements: fix.edits.map(|edit| SarifReplacement {
+                        deleted_region: SarifRegion::from(edit.span),
+                        inserted_content: SarifMessage {
+                            text: edit.replacement.clone(),
+                        },
+                    }),
+                }],
+            }),
+        });
+    }
+    let log = SarifLog {
+        schema: "https://json.schemastore.org/sarif-2.1.0.json",
+        version: "2.1.0",
+        runs: vec![SarifRun {
+            tool: SarifTool {
+                driver: SarifDriver {
+                    name: "starlark",
+                    information_uri: "https://github.com/facebookexperimental/starlark-rust",
+                    version: env!("CARGO_PKG_VERSION"),
+                    rules,
+                },
+            },
+            results,
+        }],
+    };
+    serde_json::to_string_pretty(&log).unwrap()
+}
+
+fn xml_escape(x: &str) -> String {
+    let mut res = String::with_capacity(x.len());
+    for c in x.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            '\n' => res.push_str("&#10;"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Render the messages as a checkstyle report, grouped by file in the order the files
+/// are first mentioned. Disabled messages are left out.
+pub(crate) fn checkstyle(messages: &[EvalMessage]) -> String {
+    let mut files: Vec<(&str, Vec<&EvalMessage>)> = Vec::new();
+    for x in messages {
+        if x.severity == EvalSeverity::Disabled {
+            continue;
+        }
+        match files.iter_mut().find(|(path, _)| *path == x.path) {
+            Some((_, xs)) => xs.push(x),
+            None => files.push((&x.path, vec![x])),
+        }
+    }
+
+    let mut res = String::new();
+    res.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    res.push_str("<checkstyle version=\"4.3\">\n");
+    for (path, xs) in files {
+        writeln!(res, "  <file name=\"{}\">", xml_escape(path)).unwrap();
+        for x in xs {
+            res.push_str("    <error");
+            if let Some(span) = x.span {
+                write!(
+                    res,
+                    " line=\"{}\" column=\"{}\"",
+                    span.begin_line + 1,
+                    span.begin_column + 1
+                )
+                .unwrap();
+            }
+            let severity = match x.severity {
+                EvalSeverity::Error => "error",
+                EvalSeverity::Warning => "warning",
+                EvalSeverity::Advice | EvalSeverity::Disabled => "info",
+            };
+            writeln!(
+                res,
+                " severity=\"{}\" message=\"{}\" source=\"starlark.{}\"/>",
+                severity,
+                xml_escape(&x.description),
+                xml_escape(&x.name)
+            )
+            .unwrap();
+        }
+        res.push_str("  </file>\n");
+    }
+    res.push_str("</checkstyle>\n");
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use starlark::syntax::AstModule;
+    use starlark::syntax::Dialect;
+
+    use super::*;
+
+    fn messages() -> Vec<EvalMessage> {
+        let module = AstModule::parse(
+            "a&b.star",
+            "def f():\n    return\n    x = 1 < 2\n".to_owned(),
+            &Dialect::Extended,
+        )
+        .unwrap();
+        module
+            .lint(None)
+            .into_iter()
+            .map(EvalMessage::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_checkstyle() {
+        assert_eq!(
+            checkstyle(&messages()),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<checkstyle version="4.3">
+  <file name="a&amp;b.star">
+    <error line="3" column="5" severity="warning" message="Unreachable statement `x = (1 &lt; 2)`" source="starlark.unreachable"/>
+  </file>
+</checkstyle>
+"#
+        );
+    }
+
+    #[test]
+    fn test_sarif() {
+        let log: serde_json::Value = serde_json::from_str(&sarif(&messages())).unwrap();
+        let run = &log["runs"][0];
+        assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "unreachable");
+        assert_eq!(run["results"].as_array().unwrap().len(), 1);
+        let result = &run["results"][0];
+        assert_eq!(result["level"], "warning");
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["region"],
+            serde_json::json!({"startLine": 3, "startColumn": 5, "endLine": 3, "endColumn": 14})
+        );
+    }
+}
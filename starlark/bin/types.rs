@@ -15,9 +15,14 @@
  * limitations under the License.
  */
 
+use std::path::Path;
+
+use gazebo::prelude::*;
 use serde::Serialize;
 use starlark::errors::EvalMessage;
 use starlark::errors::EvalSeverity;
+use starlark::errors::FunctionMetrics;
+use starlark::errors::ModuleMetrics;
 
 /// A JSON-deriving type that gives a stable interface to downstream types.
 /// Do NOT change this type, change Message instead.
@@ -50,3 +55,51 @@ impl LintMessage {
         }
     }
 }
+
+/// The metrics of a file, as produced by `--metrics`.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct MetricsMessage {
+    path: String,
+    statements: usize,
+    loaded_symbols: usize,
+    functions: Vec<FunctionMetricsMessage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct FunctionMetricsMessage {
+    name: String,
+    line: usize,
+    char: usize,
+    cyclomatic_complexity: usize,
+    nesting_depth: usize,
+    statements: usize,
+    parameters: usize,
+    loaded_symbols: usize,
+}
+
+impl MetricsMessage {
+    pub(crate) fn new(path: &Path, x: ModuleMetrics) -> Self {
+        Self {
+            path: path.to_string_lossy().into_owned(),
+            statements: x.statements,
+            loaded_symbols: x.loaded_symbols,
+            functions: x.functions.into_map(FunctionMetricsMessage::new),
+        }
+    }
+}
+
+impl FunctionMetricsMessage {
+    fn new(x: FunctionMetrics) -> Self {
+        let span = x.location.resolve_span();
+        Self {
+            name: x.name,
+            line: span.begin_line + 1,
+            char: span.begin_column + 1,
+            cyclomatic_complexity: x.cyclomatic_complexity,
+            nesting_depth: x.nesting_depth,
+            statements: x.statements,
+            parameters: x.parameters,
+            loaded_symbols: x.loaded_symbols,
+        }
+    }
+}
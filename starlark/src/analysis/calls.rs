@@ -0,0 +1,491 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Check the arguments of calls against the signature of the function being called,
+//! where it is known: a `def` in the same file, a symbol loaded from a module whose
+//! signatures are known, or a global such as a native function.
+
+use std::collections::HashMap;
+
+use gazebo::variants::VariantName;
+use thiserror::Error;
+
+use crate::analysis::bind;
+use crate::analysis::bind::Assigner;
+use crate::analysis::bind::Bind;
+use crate::analysis::bind::Scope;
+use crate::analysis::typecheck::Ty;
+use crate::analysis::types::LintT;
+use crate::analysis::types::LintWarning;
+use crate::codemap::CodeMap;
+use crate::errors::did_you_mean::did_you_mean;
+use crate::syntax::ast::Argument;
+use crate::syntax::ast::AstArgument;
+use crate::syntax::ast::AstExpr;
+use crate::syntax::ast::AstParameter;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::Expr;
+use crate::syntax::ast::Parameter;
+use crate::syntax::ast::Stmt;
+use crate::syntax::AstModule;
+use crate::values::docs;
+use crate::values::docs::DocItem;
+
+#[derive(Error, Debug, VariantName)]
+pub(crate) enum CallWarning {
+    #[error(
+        "Call to `{0}` with unexpected keyword argument `{1}`{}",
+        .2.as_ref().map_or_else(String::new, |x| format!(", did you mean `{}`?", x))
+    )]
+    UnknownKeyword(String, String, Option<String>),
+    #[error("Call to `{0}` is missing required argument `{1}`")]
+    MissingArgument(String, String),
+    #[error("Call to `{0}` with {2} positional arguments, but it takes at most {1}")]
+    TooManyPositionals(String, usize, usize),
+}
+
+impl LintWarning for CallWarning {
+    fn is_serious(&self) -> bool {
+        true
+    }
+}
+
+/// A parameter of a function.
+#[derive(Debug, Clone)]
+pub(crate) struct Param {
+    pub(crate) name: String,
+    pub(crate) required: bool,
+    /// Whether the default is `None`, in which case passing `None` is always allowed.
+    pub(crate) none_default: bool,
+    pub(crate) typ: Ty,
+}
+
+/// The parameters a function accepts, enough to check the arguments of a call.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Signature {
+    /// Parameters which can be passed by position.
+    pub(crate) positional: Vec<Param>,
+    /// Parameters which can only be passed by name.
+    pub(crate) named_only: Vec<Param>,
+    /// Whether there is a `*args` parameter.
+    pub(crate) args: bool,
+    /// Whether there is a `**kwargs` parameter.
+    pub(crate) kwargs: bool,
+    /// The type the function returns.
+    pub(crate) ret: Ty,
+}
+
+impl Signature {
+    pub(crate) fn from_ast(params: &[AstParameter], ret: Option<&AstExpr>) -> Self {
+        let mut res = Self {
+            ret: ret.map_or(Ty::Any, Ty::from_expr),
+            ..Self::default()
+        };
+        let mut named_only = false;
+        for p in params {
+            let (name, typ, default) = match &p.node {
+                Parameter::Normal(name, typ) => (name, typ, None),
+                Parameter::WithDefaultValue(name, typ, default) => (name, typ, Some(default)),
+                Parameter::NoArgs => {
+                    named_only = true;
+                    continue;
+                }
+                Parameter::Args(..) => {
+                    named_only = true;
+                    res.args = true;
+                    continue;
+                }
+                Parameter::KwArgs(..) => {
+                    res.kwargs = true;
+                    continue;
+                }
+            };
+            res.add(
+                named_only,
+                Param {
+                    name: name.0.clone(),
+                    required: default.is_none(),
+                    none_default: matches!(default.map(|x| &***x), Some(Expr::Identifier(x, _)) if x.node == "None"),
+                    typ: typ.as_ref().map_or(Ty::Any, |x| Ty::from_expr(x)),
+                },
+            );
+        }
+        res
+    }
+
+    pub(crate) fn from_docs(f: &docs::Function) -> Self {
+        let mut res = Self {
+            ret: Ty::from_docs(f.ret.typ.as_ref()),
+            ..Self::default()
+        };
+        let mut named_only = false;
+        for p in &f.params {
+            match p {
+                docs::Param::Arg {
+                    name,
+                    typ,
+                    default_value,
+                    ..
+                } => res.add(
+                    named_only,
+                    Param {
+                        name: name.clone(),
+                        required: default_value.is_none(),
+                        none_default: default_value.as_deref() == Some("None"),
+                        typ: Ty::from_docs(typ.as_ref()),
+                    },
+                ),
+                docs::Param::NoArgs => named_only = true,
+                docs::Param::Args { .. } => {
+                    named_only = true;
+                    res.args = true;
+                }
+                docs::Param::Kwargs { .. } => res.kwargs = true,
+            }
+        }
+        res
+    }
+
+    fn add(&mut self, named_only: bool, param: Param) {
+        if named_only {
+            self.named_only.push(param)
+        } else {
+            self.positional.push(param)
+        }
+    }
+
+    fn params(&self) -> impl Iterator<Item = &Param> {
+        self.positional.iter().chain(&self.named_only)
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.params().map(|x| x.name.as_str())
+    }
+
+    /// The parameter an argument is passed to, by position or by name.
+    pub(crate) fn param(&self, arg: &Argument, position: usize) -> Option<&Param> {
+        match arg {
+            Argument::Positional(_) => self.positional.get(position),
+            Argument::Named(name, _) => self.params().find(|x| x.name == name.node),
+            _ => None,
+        }
+    }
+}
+
+/// The signatures of functions defined outside the module being linted,
+/// so that [`AstModule::lint_with_options`] can check calls to them.
+/// Functions without documentation for their parameters are not checked.
+#[derive(Debug, Clone, Default)]
+pub struct Signatures {
+    globals: HashMap<String, Signature>,
+    modules: HashMap<String, HashMap<String, Signature>>,
+}
+
+impl Signatures {
+    /// No known signatures.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn functions(members: &HashMap<String, Option<DocItem>>) -> HashMap<String, Signature> {
+        members
+            .iter()
+            .filter_map(|(name, doc)| match doc {
+                Some(DocItem::Function(f)) => Some((name.clone(), Signature::from_docs(f))),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Add functions available as globals, e.g. from
+    /// [`Globals::member_documentation`](crate::environment::Globals::member_documentation),
+    /// or the members of a prelude module.
+    pub fn add_globals(&mut self, members: &HashMap<String, Option<DocItem>>) {
+        self.globals.extend(Self::functions(members));
+    }
+
+    /// Add the functions of the module loaded as `path`, e.g. the members from
+    /// [`FrozenModule::module_documentation`](crate::environment::FrozenModule::module_documentation).
+    pub fn add_module(&mut self, path: &str, members: &HashMap<String, Option<DocItem>>) {
+        self.modules
+            .entry(path.to_owned())
+            .or_default()
+            .extend(Self::functions(members));
+    }
+
+    /// Add the functions defined at the top level of a module loaded as `path`,
+    /// without having to evaluate it.
+    pub fn add_module_ast(&mut self, path: &str, module: &AstModule) {
+        let mut defs = HashMap::new();
+        top_level_defs(&module.statement, &mut defs);
+        self.modules.entry(path.to_owned()).or_default().extend(
+            defs.into_iter()
+                .filter(|(name, _)| !name.starts_with('_'))
+                .map(|(name, sig)| (name.to_owned(), sig)),
+        );
+    }
+}
+
+fn def_signature(x: &AstStmt) -> Option<(&str, Signature)> {
+    match &**x {
+        Stmt::Def(name, params, ret, ..) => Some((
+            &name.0,
+            Signature::from_ast(params, ret.as_ref().map(|x| &**x)),
+        )),
+        _ => None,
+    }
+}
+
+fn top_level_defs<'a>(x: &'a AstStmt, res: &mut HashMap<&'a str, Signature>) {
+    match def_signature(x) {
+        Some((name, sig)) => {
+            res.insert(name, sig);
+        }
+        None => x.visit_stmt(|x| top_level_defs(x, res)),
+    }
+}
+
+fn all_defs<'a>(x: &'a AstStmt, res: &mut HashMap<&'a str, Signature>) {
+    if let Some((name, sig)) = def_signature(x) {
+        res.insert(name, sig);
+    }
+    x.visit_stmt(|x| all_defs(x, res))
+}
+
+/// Every binding of every name, in any scope.
+fn bindings(scope: &Scope, res: &mut HashMap<String, Vec<Assigner>>) {
+    for x in &scope.inner {
+        match x {
+            Bind::Set(assigner, name) => res
+                .entry(name.0.clone())
+                .or_default()
+                .push(assigner.clone()),
+            Bind::Scope(scope) => bindings(scope, res),
+            _ => {}
+        }
+    }
+}
+
+/// Finds the signatures of the functions called in a module.
+pub(crate) struct Callees<'a> {
+    defs: HashMap<&'a str, Signature>,
+    bound: HashMap<String, Vec<Assigner>>,
+    signatures: &'a Signatures,
+}
+
+impl<'a> Callees<'a> {
+    pub(crate) fn new(module: &'a AstModule, signatures: &'a Signatures) -> Self {
+        let mut defs = HashMap::new();
+        all_defs(&module.statement, &mut defs);
+        let mut bound = HashMap::new();
+        bindings(&bind::scope(module), &mut bound);
+        Self {
+            defs,
+            bound,
+            signatures,
+        }
+    }
+
+    /// Find the signature of the function called `name`. Names bound more than once anywhere
+    /// in the module are skipped, since it is not clear which binding a call refers to.
+    pub(crate) fn resolve(&self, name: &str) -> Option<&Signature> {
+        match self.bound.get(name).map(|x| x.as_slice()) {
+            None => self.signatures.globals.get(name),
+            Some([Assigner::Load { path, name }]) => {
+                self.signatures.modules.get(&path.node)?.get(&name.node)
+            }
+            Some([Assigner::Assign]) => self.defs.get(name),
+            _ => None,
+        }
+    }
+}
+
+fn check_call(
+    codemap: &CodeMap,
+    call: &AstExpr,
+    name: &str,
+    sig: &Signature,
+    args: &[AstArgument],
+    res: &mut Vec<LintT<CallWarning>>,
+) {
+    let mut positional = Vec::new();
+    let mut named = Vec::new();
+    let mut unpacked = false;
+    for x in args {
+        match &x.node {
+            Argument::Positional(_) => positional.push(x.span),
+            Argument::Named(name, _) => named.push(name),
+            Argument::Args(_) | Argument::KwArgs(_) => unpacked = true,
+        }
+    }
+
+    if !sig.args && positional.len() > sig.positional.len() {
+        res.push(LintT::new(
+            codemap,
+            positional[sig.positional.len()],
+            CallWarning::TooManyPositionals(
+                name.to_owned(),
+                sig.positional.len(),
+                positional.len(),
+            ),
+        ));
+    }
+
+    if !sig.kwargs {
+        for x in &named {
+            if !sig.names().any(|n| n == x.node) {
+                let suggestion = did_you_mean(&x.node, sig.names()).map(str::to_owned);
+                let mut lint = LintT::new(
+                    codemap,
+                    x.span,
+                    CallWarning::UnknownKeyword(
+                        name.to_owned(),
+                        x.node.clone(),
+                        suggestion.clone(),
+                    ),
+                );
+                if let Some(suggestion) = suggestion {
+                    let title = format!("Replace `{}` with `{}`", x.node, suggestion);
+                    lint = lint.with_unsafe_fix(codemap, title, vec![(x.span, suggestion)]);
+                }
+                res.push(lint);
+            }
+        }
+    }
+
+    // With `*args` or `**kwargs` in the call we can't tell what has been passed.
+    if !unpacked {
+        let missing = sig
+            .positional
+            .iter()
+            .skip(positional.len())
+            .chain(&sig.named_only)
+            .filter(|p| p.required && !named.iter().any(|x| x.node == p.name));
+        for p in missing {
+            res.push(LintT::new(
+                codemap,
+                call.span,
+                CallWarning::MissingArgument(name.to_owned(), p.name.clone()),
+            ));
+        }
+    }
+}
+
+pub(crate) fn call_warnings(
+    module: &AstModule,
+    signatures: Option<&Signatures>,
+) -> Vec<LintT<CallWarning>> {
+    fn expr(x: &AstExpr, codemap: &CodeMap, callees: &Callees, res: &mut Vec<LintT<CallWarning>>) {
+        if let Expr::Call(f, args) = &**x {
+            if let Expr::Identifier(name, _) = &***f {
+                if let Some(sig) = callees.resolve(&name.node) {
+                    check_call(codemap, x, &name.node, sig, args, res);
+                }
+            }
+        }
+        x.visit_expr(|x| expr(x, codemap, callees, res));
+    }
+
+    let empty = Signatures::default();
+    let callees = Callees::new(module, signatures.unwrap_or(&empty));
+    let mut res = Vec::new();
+    module
+        .statement
+        .visit_expr(|x| expr(x, &module.codemap, &callees, &mut res));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::environment::Globals;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    #[test]
+    fn test_lint_calls_in_file() {
+        let m = module(
+            r#"
+def f(name, srcs = [], *, visibility = None):
+    pass
+f(nme = "x", visibility = [])
+f("x", [], [], visibility = [])
+f(visibility = [], name = "x")
+f(*args, **kwargs)
+def k():
+    pass
+def g(k):
+    # Not the `k` above, so neither call is checked.
+    k(1, 2, 3, 4)
+k(1)
+def h(*args, **kwargs):
+    pass
+h(1, 2, x = 3)
+"#,
+        );
+        let res = call_warnings(&m, None);
+        assert_eq!(
+            res.map(|x| x.to_string()),
+            &[
+                "X:4:3-6: Call to `f` with unexpected keyword argument `nme`, did you mean `name`?",
+                "X:4:1-30: Call to `f` is missing required argument `name`",
+                "X:5:12-14: Call to `f` with 3 positional arguments, but it takes at most 2",
+            ]
+        );
+        assert_eq!(res[0].fixes[0].edits[0].replacement, "name");
+    }
+
+    #[test]
+    fn test_lint_calls_known_signatures() {
+        let lib = module(
+            r#"
+def rule(name, deps = []):
+    pass
+def _private(x):
+    pass
+"#,
+        );
+        let mut signatures = Signatures::new();
+        signatures.add_globals(&Globals::standard().member_documentation());
+        signatures.add_module_ast("lib.star", &lib);
+        let m = module(
+            r#"
+load("lib.star", "rule", my_private = "_private")
+rule(name = "x", dep = [])
+my_private()
+len()
+len([], [])
+sorted([], revers = True)
+"#,
+        );
+        let res = call_warnings(&m, Some(&signatures));
+        assert_eq!(
+            res.map(|x| x.problem.to_string()),
+            &[
+                "Call to `rule` with unexpected keyword argument `dep`, did you mean `deps`?",
+                "Call to `len` is missing required argument `a`",
+                "Call to `len` with 2 positional arguments, but it takes at most 1",
+                "Call to `sorted` with unexpected keyword argument `revers`, did you mean `reverse`?",
+            ]
+        );
+    }
+}
@@ -0,0 +1,359 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Choose which lints are reported, both for a whole project with a [`LintConfig`],
+//! and in the file itself with comments such as `# starlark-lint: disable=unused-assign`.
+//!
+//! A comment after code disables the lints on its line. A comment on a line of its own
+//! disables them until the end of the block it is in, as judged by its indentation, so
+//! at the top level it applies to the rest of the file. `disable-file=` applies to the
+//! whole file, wherever the comment is. Several lints can be separated by commas,
+//! and the directive can follow another comment, as in `# why  # starlark-lint: disable=x`.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use gazebo::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::analysis::calls::Signatures;
+use crate::analysis::metrics::MetricLimits;
+use crate::analysis::style::NamingConventions;
+use crate::analysis::types::EvalSeverity;
+use crate::analysis::types::Lint;
+use crate::codemap::Pos;
+use crate::syntax::format::comments;
+use crate::syntax::lexer::Lexer;
+use crate::syntax::AstModule;
+
+/// Which lints to report, and how severely, usually read from a project's configuration file.
+/// Lints are named by their [`short_name`](Lint::short_name).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    /// Lints to report as warnings even though they are not serious, which are otherwise
+    /// reported as disabled.
+    pub enable: HashSet<String>,
+    /// Lints not to report at all.
+    pub disable: HashSet<String>,
+    /// The severity to report lints with, taking precedence over `enable`.
+    pub severity: HashMap<String, EvalSeverity>,
+    /// Whether to check types statically, using type annotations and the types of
+    /// native functions.
+    pub typecheck: bool,
+    /// Limits on the size and complexity of functions, which are reported as advice
+    /// when exceeded.
+    pub metrics: MetricLimits,
+    /// The case names must be written in, which is reported as advice when not followed.
+    pub naming: NamingConventions,
+    /// Whether public top-level functions must have docstrings, and whether docstrings must
+    /// document all of a function's parameters and no others in their `Args:` section.
+    /// Reported as advice.
+    pub docstrings: bool,
+}
+
+impl LintConfig {
+    /// Whether to report the lint, setting its severity according to the configuration.
+    pub(crate) fn apply(&self, lint: &mut Lint) -> bool {
+        if self.disable.contains(&lint.short_name) {
+            return false;
+        }
+        if let Some(severity) = self.severity.get(&lint.short_name) {
+            lint.severity = *severity;
+        } else if self.enable.contains(&lint.short_name)
+            && matches!(lint.severity, EvalSeverity::Disabled)
+        {
+            lint.severity = EvalSeverity::Warning;
+        }
+        true
+    }
+}
+
+/// What [`AstModule::lint_with_options`] knows about the module being linted,
+/// and how to report what it finds.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LintOptions<'a> {
+    /// The complete set of global variables, if known, resulting in name-resolution lints.
+    pub globals: Option<&'a [&'a str]>,
+    /// The signatures of functions not defined in the module, to check calls to them against.
+    pub signatures: Option<&'a Signatures>,
+    /// Which lints to report, and how severely.
+    pub config: Option<&'a LintConfig>,
+}
+
+/// The comment which disables lints.
+const DIRECTIVE: &str = "starlark-lint:";
+
+/// Parse the text of a comment, after the `#`, as a directive. Returns whether it applies
+/// to the whole file, and the names of the lints it disables, separated by commas.
+fn directive(text: &str) -> Option<(bool, &str)> {
+    let directive = text.trim().strip_prefix(DIRECTIVE)?.trim();
+    if let Some(names) = directive.strip_prefix("disable-file=") {
+        Some((true, names))
+    } else {
+        directive.strip_prefix("disable=").map(|names| (false, names))
+    }
+}
+
+/// The edit which disables the lint called `name` on `line` (0-based) of the module: a
+/// `# starlark-lint: disable=` comment at the end of the line, or the name added to one
+/// already there. Returns the byte offset to insert at and the text to insert, or `None`
+/// if the line doesn't exist or ends inside a string, so can't have a comment added.
+pub(crate) fn disable_lint_edit(
+    module: &AstModule,
+    line: usize,
+    name: &str,
+) -> Option<(usize, String)> {
+    let codemap = &module.codemap;
+    let source = codemap.source();
+    if line > codemap.find_line(codemap.full_span().end()) {
+        return None;
+    }
+    let begin = codemap.line_span(line).begin().get() as usize;
+    let end = begin + codemap.source_line(line).trim_end().len();
+    let mut tokens = Lexer::new(source, &module.dialect, codemap.dupe()).flatten();
+    if tokens.any(|(b, _, e)| b < end && end < e) {
+        return None;
+    }
+    let existing = comments(module)
+        .into_iter()
+        .find(|x| !x.own_line && codemap.find_line(Pos::new(x.begin as u32)) == line);
+    let extends = existing.map_or(false, |x| {
+        let last = source[x.begin + 1..end].rsplit('#').next().unwrap_or_default();
+        matches!(directive(last), Some((false, _)))
+    });
+    if extends {
+        Some((end, format!(",{}", name)))
+    } else {
+        Some((end, format!("  # {} disable={}", DIRECTIVE, name)))
+    }
+}
+
+/// Lints disabled by a comment, on lines `first` to `last` inclusive (0-based).
+#[derive(Debug, PartialEq)]
+struct Suppression {
+    names: Vec<String>,
+    first: usize,
+    last: usize,
+}
+
+/// The lints disabled by the comments in a module.
+pub(crate) struct Suppressions(Vec<Suppression>);
+
+impl Suppressions {
+    pub(crate) fn new(module: &AstModule) -> Self {
+        let codemap = &module.codemap;
+        let source = codemap.source();
+        let last_line = codemap.find_line(codemap.full_span().end());
+        let mut res = Vec::new();
+        for comment in comments(module) {
+            // A directive may follow another comment on the same line.
+            for text in source[comment.begin + 1..comment.end].split('#') {
+                let (file, names) = match directive(text) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let names = names
+                    .split(',')
+                    .map(|x| x.trim().to_owned())
+                    .filter(|x| !x.is_empty())
+                    .collect();
+                let line = codemap.find_line(Pos::new(comment.begin as u32));
+                let (first, last) = if file {
+                    (0, last_line)
+                } else if !comment.own_line {
+                    (line, line)
+                } else {
+                    // The block ends before the next line of code indented less than the comment.
+                    let end = (line + 1..=last_line).find(|i| {
+                        let text = codemap.source_line(*i);
+                        let code = text.trim_start();
+                        let indent = text.chars().count() - code.chars().count();
+                        !code.is_empty() && !code.starts_with('#') && indent < comment.column
+                    });
+                    (line, end.map_or(last_line, |x| x - 1))
+                };
+                res.push(Suppression { names, first, last });
+            }
+        }
+        Self(res)
+    }
+
+    /// Whether a comment disables the lint.
+    pub(crate) fn suppresses(&self, lint: &Lint) -> bool {
+        let line = lint.location.resolve_span().begin_line;
+        self.0.iter().any(|x| {
+            x.first <= line && line <= x.last && x.names.iter().any(|n| *n == lint.short_name)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    #[test]
+    fn test_suppression_scopes() {
+        let m = module(
+            r#"
+# starlark-lint: disable-file=a, b
+x = 1  # starlark-lint: disable=c
+def f():
+    y = 1
+    # starlark-lint: disable=d
+
+    # Other comments don't end the block.
+    return y
+z = 2  # Not a directive
+w = 3  # Why  # starlark-lint: disable=e
+"#,
+        );
+        let suppressions = Suppressions::new(&m);
+        let scopes = suppressions.0.map(|x| (x.names.join(","), x.first, x.last));
+        assert_eq!(
+            scopes,
+            vec![
+                ("a,b".to_owned(), 0, 11),
+                ("c".to_owned(), 2, 2),
+                ("d".to_owned(), 5, 8),
+                ("e".to_owned(), 10, 10),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_disable_lint_edit() {
+        let m = module(
+            r#"x = 1
+y = 2  # Why
+z = 3  # starlark-lint: disable=a
+s = '''
+'''
+"#,
+        );
+        let edit = |line| disable_lint_edit(&m, line, "b");
+        let source = m.codemap.source();
+        assert_eq!(edit(0), Some((5, "  # starlark-lint: disable=b".to_owned())));
+        assert_eq!(
+            edit(1),
+            Some((source.find("Why").unwrap() + 3, "  # starlark-lint: disable=b".to_owned()))
+        );
+        assert_eq!(edit(2), Some((source.find("=a").unwrap() + 2, ",b".to_owned())));
+        assert_eq!(edit(3), None);
+        assert_eq!(edit(10), None);
+    }
+
+    #[test]
+    fn test_lint_suppressed() {
+        let m = module(
+            r#"
+def f():
+    x = 1  # starlark-lint: disable=unused-assign
+    w = 1
+def g():
+    # starlark-lint: disable=unused-assign
+    y = 1
+    if True:
+        v = 1
+def h():
+    z = 1
+"#,
+        );
+        let lints = m.lint(None);
+        let lints = lints
+            .iter()
+            .filter(|x| x.short_name == "unused-assign")
+            .map(|x| x.original.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(lints, vec!["w", "z"]);
+    }
+
+    #[test]
+    fn test_lint_config() {
+        let m = module(
+            r#"
+x = 1
+x = 2
+def f():
+    return
+    f()
+"#,
+        );
+        let names = |config: &LintConfig| {
+            m.lint_with_options(&LintOptions {
+                config: Some(config),
+                ..LintOptions::default()
+            })
+            .into_map(|x| (x.short_name, x.severity.to_string()))
+        };
+        let default = names(&LintConfig::default());
+        assert_eq!(
+            default,
+            vec![
+                ("unreachable".to_owned(), "Warning".to_owned()),
+                (
+                    "duplicate-top-level-assign".to_owned(),
+                    "Warning".to_owned()
+                ),
+                ("unused-assign".to_owned(), "Disabled".to_owned()),
+            ]
+        );
+        let config: LintConfig =
+            serde_json::from_str(r#"{"disable": ["unreachable"], "enable": ["unused-assign"]}"#)
+                .unwrap();
+        assert_eq!(
+            names(&config),
+            vec![
+                (
+                    "duplicate-top-level-assign".to_owned(),
+                    "Warning".to_owned()
+                ),
+                ("unused-assign".to_owned(), "Warning".to_owned()),
+            ]
+        );
+        let config: LintConfig = serde_json::from_str(
+            r#"{"enable": ["unused-assign"], "severity": {"unused-assign": "advice"}}"#,
+        )
+        .unwrap();
+        assert_eq!(names(&config)[2].1, "Advice");
+        let config: LintConfig = serde_json::from_str(r#"{"metrics": {"statements": 1}}"#).unwrap();
+        assert_eq!(
+            names(&config)[3],
+            ("too-many-statements".to_owned(), "Advice".to_owned())
+        );
+        let config: LintConfig =
+            serde_json::from_str(r#"{"naming": {"global": "UPPER_CASE"}, "docstrings": true}"#)
+                .unwrap();
+        assert_eq!(
+            names(&config)[3..],
+            [
+                ("naming-convention".to_owned(), "Advice".to_owned()),
+                ("naming-convention".to_owned(), "Advice".to_owned()),
+                ("missing-docstring".to_owned(), "Advice".to_owned()),
+            ]
+        );
+        assert!(serde_json::from_str::<LintConfig>(r#"{"disabled": []}"#).is_err());
+    }
+}
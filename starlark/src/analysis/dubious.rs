@@ -16,20 +16,29 @@
  */
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 
 use gazebo::variants::VariantName;
 use num_bigint::BigInt;
 use thiserror::Error;
 
+use crate::analysis::types::remove_statement;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::codemap::CodeMap;
 use crate::codemap::FileSpan;
 use crate::codemap::Span;
+use crate::syntax::ast::Argument;
+use crate::syntax::ast::Assign;
 use crate::syntax::ast::AstExpr;
 use crate::syntax::ast::AstLiteral;
+use crate::syntax::ast::AstParameter;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::BinOp;
 use crate::syntax::ast::Expr;
+use crate::syntax::ast::Stmt;
 use crate::syntax::lexer::TokenInt;
+use crate::syntax::uniplate::Visit;
 use crate::syntax::AstModule;
 use crate::values::num::Num;
 
@@ -37,11 +46,33 @@ use crate::values::num::Num;
 pub(crate) enum Dubious {
     #[error("Duplicate dictionary key `{0}`, also used at {1}")]
     DuplicateKey(String, FileSpan),
+    #[error(
+        "Default value `{1}` of parameter `{0}` is mutable and shared between calls{}",
+        if *.2 { ", and the function modifies it" } else { "" }
+    )]
+    MutableDefaultArgument(String, String, bool),
+    #[error("Assignment of `{0}` to itself has no effect")]
+    SelfAssignment(String),
+    #[error("Expression `{0}` has no effect")]
+    NoEffect(String),
+    #[error("Message passed to `fail` should be a string, got `{0}`")]
+    FailNonStringMessage(String),
+    #[error("Type check `{0}` compares against \"{1}\", but Starlark calls that type \"{2}\"")]
+    TypeStringComparison(String, String, &'static str),
+    #[error("Comparison `{0}` against `None`")]
+    NoneComparison(String),
 }
 
 impl LintWarning for Dubious {
     fn is_serious(&self) -> bool {
-        true
+        match self {
+            Dubious::MutableDefaultArgument(_, _, modified) => *modified,
+            Dubious::NoEffect(_)
+            | Dubious::FailNonStringMessage(_)
+            | Dubious::TypeStringComparison(..)
+            | Dubious::NoneComparison(_) => false,
+            _ => true,
+        }
     }
 }
 
@@ -110,9 +141,325 @@ fn duplicate_dictionary_key(module: &AstModule, res: &mut Vec<LintT<Dubious>>) {
         .visit_expr(|x| expr(x, &module.codemap, res))
 }
 
+/// Is this expression evaluated to a fresh mutable value, e.g. `[]` or `dict()`.
+fn is_mutable_value(x: &AstExpr) -> bool {
+    match &**x {
+        Expr::List(_)
+        | Expr::Dict(_)
+        | Expr::ListComprehension(..)
+        | Expr::DictComprehension(..) => true,
+        Expr::Call(f, _) => {
+            matches!(&***f, Expr::Identifier(f, _) if f.node == "list" || f.node == "dict")
+        }
+        _ => false,
+    }
+}
+
+/// Methods on `list` and `dict` which modify the receiver.
+const MUTATING_METHODS: &[&str] = &[
+    "append",
+    "clear",
+    "extend",
+    "insert",
+    "pop",
+    "popitem",
+    "remove",
+    "setdefault",
+    "update",
+];
+
+/// Does the expression modify the variable `name`, e.g. `name.append(1)`.
+fn expr_modifies(x: &AstExpr, name: &str) -> bool {
+    if let Expr::Call(f, _) = &**x {
+        if let Expr::Dot(obj, method) = &***f {
+            if matches!(&***obj, Expr::Identifier(obj, _) if obj.node == name)
+                && MUTATING_METHODS.contains(&method.node.as_str())
+            {
+                return true;
+            }
+        }
+    }
+    let mut res = false;
+    x.visit_expr(|x| res = res || expr_modifies(x, name));
+    res
+}
+
+/// Does the statement modify the variable `name`, either by calling a mutating method,
+/// assigning to an index, or using an augmented assignment.
+fn stmt_modifies(x: &AstStmt, name: &str) -> bool {
+    fn lvalue(x: &Assign, name: &str, modify: bool) -> bool {
+        match x {
+            Assign::Tuple(xs) => xs.iter().any(|x| lvalue(x, name, modify)),
+            Assign::ArrayIndirection(box (array, _)) => {
+                matches!(&**array, Expr::Identifier(x, _) if x.node == name)
+            }
+            Assign::Identifier(x) => modify && x.0 == name,
+            Assign::Dot(..) => false,
+        }
+    }
+
+    let here = match &**x {
+        Stmt::Assign(lhs, _) | Stmt::For(lhs, _) => lvalue(lhs, name, false),
+        Stmt::AssignModify(lhs, _, _) => lvalue(lhs, name, true),
+        _ => false,
+    };
+    let mut res = here;
+    x.visit_children(|x| {
+        res = res
+            || match x {
+                Visit::Stmt(x) => stmt_modifies(x, name),
+                Visit::Expr(x) => expr_modifies(x, name),
+            }
+    });
+    res
+}
+
+// Default values are evaluated once, when the `def` or `lambda` is executed,
+// so a mutable default is shared between all calls that don't supply the argument.
+fn mutable_default_argument(module: &AstModule, res: &mut Vec<LintT<Dubious>>) {
+    fn params(
+        codemap: &CodeMap,
+        params: &[AstParameter],
+        modifies: impl Fn(&str) -> bool,
+        res: &mut Vec<LintT<Dubious>>,
+    ) {
+        for p in params {
+            if let (Some(name), _, Some(default)) = p.split() {
+                if is_mutable_value(default) {
+                    res.push(LintT::new(
+                        codemap,
+                        default.span,
+                        Dubious::MutableDefaultArgument(
+                            name.0.clone(),
+                            default.to_string(),
+                            modifies(&name.0),
+                        ),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn expr(x: &AstExpr, codemap: &CodeMap, res: &mut Vec<LintT<Dubious>>) {
+        if let Expr::Lambda(ps, body, _) = &**x {
+            params(codemap, ps, |name| expr_modifies(body, name), res);
+        }
+        x.visit_expr(|x| expr(x, codemap, res));
+    }
+
+    fn stmt(x: &AstStmt, codemap: &CodeMap, res: &mut Vec<LintT<Dubious>>) {
+        if let Stmt::Def(_, ps, _, body, _) = &**x {
+            params(codemap, ps, |name| stmt_modifies(body, name), res);
+        }
+        x.visit_children(|x| match x {
+            Visit::Stmt(x) => stmt(x, codemap, res),
+            Visit::Expr(x) => expr(x, codemap, res),
+        });
+    }
+
+    stmt(&module.statement, &module.codemap, res)
+}
+
+// Statements which can't do anything: `x = x`, and expressions without any calls,
+// which therefore compute a value and throw it away.
+fn useless_statement(module: &AstModule, res: &mut Vec<LintT<Dubious>>) {
+    fn has_call(x: &AstExpr) -> bool {
+        let mut res = matches!(&**x, Expr::Call(..));
+        x.visit_expr(|x| res = res || has_call(x));
+        res
+    }
+
+    fn stmt(
+        x: &AstStmt,
+        alone: bool,
+        top: bool,
+        loaded: &HashSet<&str>,
+        codemap: &CodeMap,
+        res: &mut Vec<LintT<Dubious>>,
+    ) {
+        match &**x {
+            Stmt::Assign(lhs, rhs) => match (&**lhs, &***rhs) {
+                // At the top level, `x = x` is how a loaded symbol is reexported.
+                (Assign::Identifier(x), Expr::Identifier(y, _))
+                    if x.0 == y.node && !(top && loaded.contains(y.node.as_str())) =>
+                {
+                    res.push(
+                        LintT::new(
+                            codemap,
+                            lhs.span.merge(rhs.span),
+                            Dubious::SelfAssignment(y.node.clone()),
+                        )
+                        .with_fix(
+                            codemap,
+                            format!("Remove assignment of `{}` to itself", y.node),
+                            vec![remove_statement(codemap, lhs.span.merge(rhs.span), alone)],
+                        ),
+                    )
+                }
+                _ => {}
+            },
+            Stmt::Expression(e) => {
+                if !has_call(e) && !matches!(&**e, Expr::Literal(AstLiteral::String(_))) {
+                    res.push(LintT::new(
+                        codemap,
+                        e.span,
+                        Dubious::NoEffect(e.to_string()),
+                    ))
+                }
+            }
+            Stmt::Statements(xs) => {
+                for x in xs {
+                    stmt(x, xs.len() == 1, top, loaded, codemap, res)
+                }
+            }
+            Stmt::Def(..) => x.visit_stmt(|x| stmt(x, true, false, loaded, codemap, res)),
+            _ => x.visit_stmt(|x| stmt(x, true, top, loaded, codemap, res)),
+        }
+    }
+
+    let mut loaded = HashSet::new();
+    module.statement.visit_stmt(|x| {
+        if let Stmt::Load(load) = &**x {
+            loaded.extend(load.args.iter().map(|(x, _)| x.0.as_str()));
+        }
+    });
+
+    // The final top-level expression is the value of the module, so is not useless.
+    match &*module.statement {
+        Stmt::Statements(xs) if !xs.is_empty() => {
+            for x in &xs[..xs.len() - 1] {
+                stmt(x, false, true, &loaded, &module.codemap, res);
+            }
+            if !matches!(&*xs[xs.len() - 1], Stmt::Expression(_)) {
+                stmt(
+                    &xs[xs.len() - 1],
+                    false,
+                    true,
+                    &loaded,
+                    &module.codemap,
+                    res,
+                );
+            }
+        }
+        Stmt::Expression(_) => {}
+        _ => stmt(&module.statement, true, true, &loaded, &module.codemap, res),
+    }
+}
+
+// Comparisons which are probably not what was meant: `type(x) == "str"` is never true,
+// since the type is called `"string"` in Starlark, and `x == None` relies on the `==` of `x`,
+// as Starlark has no `is`.
+fn dubious_comparison(module: &AstModule, res: &mut Vec<LintT<Dubious>>) {
+    // Names of types in other languages, for which Starlark has its own name.
+    fn starlark_type_name(name: &str) -> Option<&'static str> {
+        match name {
+            "str" | "unicode" => Some("string"),
+            "int32" | "int64" | "long" | "integer" => Some("int"),
+            "float64" | "double" => Some("float"),
+            "boolean" => Some("bool"),
+            "None" | "none" => Some("NoneType"),
+            "dictionary" => Some("dict"),
+            _ => None,
+        }
+    }
+
+    fn is_type_call(x: &AstExpr) -> bool {
+        match &**x {
+            Expr::Call(f, args) if args.len() == 1 => {
+                matches!(&***f, Expr::Identifier(f, _) if f.node == "type")
+            }
+            _ => false,
+        }
+    }
+
+    fn is_none(x: &AstExpr) -> bool {
+        matches!(&**x, Expr::Identifier(x, _) if x.node == "None")
+    }
+
+    fn expr(x: &AstExpr, codemap: &CodeMap, res: &mut Vec<LintT<Dubious>>) {
+        if let Expr::Op(lhs, op, rhs) = &**x {
+            if *op == BinOp::Equal || *op == BinOp::NotEqual {
+                let source = codemap.source_span(x.span).to_owned();
+                let string = |x: &AstExpr| match &**x {
+                    Expr::Literal(AstLiteral::String(s)) => {
+                        Some((s.span, s.node.clone(), starlark_type_name(&s.node)?))
+                    }
+                    _ => None,
+                };
+                let wrong = match (string(lhs), string(rhs)) {
+                    (Some(x), _) if is_type_call(rhs) => Some(x),
+                    (_, Some(x)) if is_type_call(lhs) => Some(x),
+                    _ => None,
+                };
+                if let Some((span, name, right)) = wrong {
+                    res.push(
+                        LintT::new(
+                            codemap,
+                            x.span,
+                            Dubious::TypeStringComparison(source, name, right),
+                        )
+                        .with_unsafe_fix(
+                            codemap,
+                            format!("Replace with `\"{}\"`", right),
+                            vec![(span, format!("\"{}\"", right))],
+                        ),
+                    );
+                } else if is_none(lhs) != is_none(rhs) {
+                    res.push(LintT::new(codemap, x.span, Dubious::NoneComparison(source)));
+                }
+            }
+        }
+        x.visit_expr(|x| expr(x, codemap, res));
+    }
+
+    module
+        .statement
+        .visit_expr(|x| expr(x, &module.codemap, res))
+}
+
+// `fail` converts its arguments to strings, so `fail(1)` works, but is almost always
+// a mistake for a message describing the failure.
+fn fail_non_string_message(module: &AstModule, res: &mut Vec<LintT<Dubious>>) {
+    fn expr(x: &AstExpr, codemap: &CodeMap, res: &mut Vec<LintT<Dubious>>) {
+        if let Expr::Call(f, args) = &**x {
+            if matches!(&***f, Expr::Identifier(f, _) if f.node == "fail") {
+                let msg = args.iter().find_map(|x| match &**x {
+                    Argument::Positional(x) => Some(x),
+                    _ => None,
+                });
+                if let Some(msg) = msg {
+                    let bad = match &**msg {
+                        Expr::Literal(AstLiteral::String(_)) => false,
+                        Expr::Literal(_) => true,
+                        Expr::Identifier(x, _) => x.node == "None",
+                        Expr::Tuple(_) => true,
+                        _ => is_mutable_value(msg) && !matches!(&**msg, Expr::Call(..)),
+                    };
+                    if bad {
+                        res.push(LintT::new(
+                            codemap,
+                            msg.span,
+                            Dubious::FailNonStringMessage(msg.to_string()),
+                        ))
+                    }
+                }
+            }
+        }
+        x.visit_expr(|x| expr(x, codemap, res));
+    }
+
+    module
+        .statement
+        .visit_expr(|x| expr(x, &module.codemap, res))
+}
+
 pub(crate) fn dubious(module: &AstModule) -> Vec<LintT<Dubious>> {
     let mut res = Vec::new();
     duplicate_dictionary_key(module, &mut res);
+    mutable_default_argument(module, &mut res);
+    useless_statement(module, &mut res);
+    dubious_comparison(module, &mut res);
+    fail_non_string_message(module, &mut res);
     res
 }
 
@@ -131,6 +478,12 @@ mod tests {
         fn about(&self) -> &String {
             match self {
                 Dubious::DuplicateKey(x, _) => x,
+                Dubious::MutableDefaultArgument(x, _, _) => x,
+                Dubious::SelfAssignment(x) => x,
+                Dubious::NoEffect(x) => x,
+                Dubious::FailNonStringMessage(x) => x,
+                Dubious::TypeStringComparison(x, _, _) => x,
+                Dubious::NoneComparison(x) => x,
             }
         }
     }
@@ -161,4 +514,109 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_lint_mutable_default_argument() {
+        let m = module(
+            r#"
+def f(no1 = [], yes1 = None, no2 = {}, yes2 = (), no3 = dict(), *, no4 = [x for x in y]):
+    no2["x"] = 1
+    no3.update({})
+    yes1 = []
+    return lambda no5 = list(), yes3 = 1: no5.append(yes3)
+def g(no6 = []):
+    no6 += [1]
+"#,
+        );
+        let mut res = Vec::new();
+        mutable_default_argument(&m, &mut res);
+        assert_eq!(
+            res.map(|x| (x.problem.about().as_str(), x.problem.is_serious())),
+            &[
+                ("no1", false),
+                ("no2", true),
+                ("no3", true),
+                ("no4", false),
+                ("no5", true),
+                ("no6", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_useless_statement() {
+        let m = module(
+            r#"
+load("foo", "yes1", "no1")
+"""Docstring"""
+yes1 = yes1
+no2 = no2
+x + 1
+print(x)
+def f(no1, yes2):
+    "Docstring"
+    no1 = no1
+    yes2 = no1
+    yes2.foo
+    yes2.append(1)
+    [no1, no2[0]]
+    [no1, no2[f()]]
+x
+"#,
+        );
+        let mut res = Vec::new();
+        useless_statement(&m, &mut res);
+        assert_eq!(
+            res.map(|x| x.problem.about()),
+            &["no2", "(x + 1)", "no1", "yes2.foo", "[no1, no2[0]]"]
+        );
+        let fixes = res.map(|x| x.fixes.map(|x| x.edits.map(|e| e.span.to_string())));
+        assert_eq!(fixes[0], vec![vec!["5:1-6:1"]]);
+        assert_eq!(fixes[1], Vec::<Vec<String>>::new());
+    }
+
+    #[test]
+    fn test_lint_dubious_comparison() {
+        let m = module(
+            r#"
+if type(x) == "list" or "str" != type(y) or type(z) == "my_record" or type(w) == "int64":
+    pass
+if x == None or None != y.z or None == None or x == z:
+    pass
+"#,
+        );
+        let mut res = Vec::new();
+        dubious_comparison(&m, &mut res);
+        assert_eq!(
+            res.map(|x| x.problem.to_string()),
+            &[
+                "Type check `\"str\" != type(y)` compares against \"str\", but Starlark calls that type \"string\"",
+                "Type check `type(w) == \"int64\"` compares against \"int64\", but Starlark calls that type \"int\"",
+                "Comparison `x == None` against `None`",
+                "Comparison `None != y.z` against `None`",
+            ]
+        );
+        assert!(res.iter().all(|x| !x.problem.is_serious()));
+        assert_eq!(res[0].fixes[0].edits[0].replacement, "\"string\"");
+        assert!(!res[0].fixes[0].safe);
+        assert!(res[2].fixes.is_empty());
+    }
+
+    #[test]
+    fn test_lint_fail_non_string_message() {
+        let m = module(
+            r#"
+fail("yes")
+fail(1)
+fail(None)
+fail([x, y])
+fail(msg)
+fail("yes: " + str(x))
+fail(attr = 1)
+"#,
+        );
+        let mut res = Vec::new();
+        fail_non_string_message(&m, &mut res);
+        assert_eq!(res.map(|x| x.problem.about()), &["1", "None", "[x, y]"]);
+    }
 }
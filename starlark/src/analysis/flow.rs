@@ -18,6 +18,7 @@
 use gazebo::variants::VariantName;
 use thiserror::Error;
 
+use crate::analysis::types::remove_statement;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::codemap::CodeMap;
@@ -193,21 +194,35 @@ fn reachable(codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<FlowIssue>>) ->
 // If you have a definition which ends with return, or a loop which ends with continue
 // that is a useless statement that just
 fn redundant(codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<FlowIssue>>) {
-    fn check(is_loop: bool, codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<FlowIssue>>) {
+    fn check(
+        is_loop: bool,
+        alone: bool,
+        codemap: &CodeMap,
+        x: &AstStmt,
+        res: &mut Vec<LintT<FlowIssue>>,
+    ) {
         match &**x {
-            Stmt::Continue if is_loop => {
-                res.push(LintT::new(codemap, x.span, FlowIssue::RedundantContinue))
-            }
-            Stmt::Return(None) if !is_loop => {
-                res.push(LintT::new(codemap, x.span, FlowIssue::RedundantReturn))
-            }
+            Stmt::Continue if is_loop => res.push(
+                LintT::new(codemap, x.span, FlowIssue::RedundantContinue).with_fix(
+                    codemap,
+                    "Remove redundant `continue`",
+                    vec![remove_statement(codemap, x.span, alone)],
+                ),
+            ),
+            Stmt::Return(None) if !is_loop => res.push(
+                LintT::new(codemap, x.span, FlowIssue::RedundantReturn).with_fix(
+                    codemap,
+                    "Remove redundant `return`",
+                    vec![remove_statement(codemap, x.span, alone)],
+                ),
+            ),
             Stmt::Statements(xs) if !xs.is_empty() => {
-                check(is_loop, codemap, xs.last().unwrap(), res)
+                check(is_loop, xs.len() == 1, codemap, xs.last().unwrap(), res)
             }
-            Stmt::If(_, box x) => check(is_loop, codemap, x, res),
+            Stmt::If(_, box x) => check(is_loop, true, codemap, x, res),
             Stmt::IfElse(_, box (x, y)) => {
-                check(is_loop, codemap, x, res);
-                check(is_loop, codemap, y, res);
+                check(is_loop, true, codemap, x, res);
+                check(is_loop, true, codemap, y, res);
             }
             _ => {}
         }
@@ -215,8 +230,8 @@ fn redundant(codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<FlowIssue>>) {
 
     fn f(codemap: &CodeMap, x: &AstStmt, res: &mut Vec<LintT<FlowIssue>>) {
         match &**x {
-            Stmt::For(_, box (_, body)) => check(true, codemap, body, res),
-            Stmt::Def(_, _, _, body, _payload) => check(false, codemap, body, res),
+            Stmt::For(_, box (_, body)) => check(true, true, codemap, body, res),
+            Stmt::Def(_, _, _, body, _payload) => check(false, true, codemap, body, res),
             _ => {}
         }
         // We always want to look inside everything for other types of violation
@@ -443,4 +458,29 @@ load("c", "b")
         misplaced_load(&m.codemap, &m.statement, &mut res);
         assert_eq!(res.len(), 1);
     }
+
+    #[test]
+    fn test_lint_redundant_fixes() {
+        let m = module(
+            r#"
+def test():
+    foo
+    return
+def test2():
+    return
+def test3():
+    for x in xs:
+        foo; continue
+"#,
+        );
+        let mut res = Vec::new();
+        redundant(&m.codemap, &m.statement, &mut res);
+        assert_eq!(
+            res.map(|x| {
+                let edit = &x.fixes[0].edits[0];
+                format!("{} {:?}", edit.span, edit.replacement)
+            }),
+            &["4:1-5:1 \"\"", "6:5-11 \"pass\"", "9:14-22 \"pass\""]
+        );
+    }
 }
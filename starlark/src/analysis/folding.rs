@@ -0,0 +1,202 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Regions of a module an editor can fold away.
+
+use lsp_types::FoldingRange;
+use lsp_types::FoldingRangeKind;
+
+use crate::codemap::CodeMap;
+use crate::codemap::Pos;
+use crate::codemap::Span;
+use crate::syntax::ast::AstLiteral;
+use crate::syntax::ast::AstNoPayload;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::Expr;
+use crate::syntax::ast::Stmt;
+use crate::syntax::uniplate::Visit;
+use crate::syntax::AstModule;
+
+struct Folds<'a> {
+    codemap: &'a CodeMap,
+    res: Vec<FoldingRange>,
+}
+
+impl<'a> Folds<'a> {
+    fn line(&self, pos: Pos) -> u32 {
+        self.codemap.find_line(pos) as u32
+    }
+
+    /// The line of the last non-whitespace character in the span. Blocks extend over the
+    /// blank lines which follow them, which we don't want to fold.
+    fn last_line(&self, span: Span) -> u32 {
+        let text = self.codemap.source_span(span);
+        let len = text.trim_end().len() as u32;
+        self.line(span.begin() + len.saturating_sub(1))
+    }
+
+    fn add(&mut self, start_line: u32, end_line: u32, kind: Option<FoldingRangeKind>) {
+        if start_line < end_line {
+            self.res.push(FoldingRange {
+                start_line,
+                start_character: None,
+                end_line,
+                end_character: None,
+                kind,
+            });
+        }
+    }
+
+    fn span(&mut self, span: Span, kind: Option<FoldingRangeKind>) {
+        self.add(self.line(span.begin()), self.last_line(span), kind)
+    }
+
+    /// The `else` branch has no span of its own, so fold from the line of the `else` keyword.
+    fn else_branch(&mut self, after: Span, branch: &AstStmt) {
+        let between = self
+            .codemap
+            .source_span(Span::new(after.end(), branch.span.begin()));
+        if let Some(i) = between.rfind("else") {
+            let start = self.line(after.end() + i as u32);
+            self.add(start, self.last_line(branch.span), None);
+        }
+    }
+
+    fn statements(&mut self, xs: &[AstStmt]) {
+        // Fold runs of `load` statements together.
+        let mut loads: Option<Span> = None;
+        for x in xs {
+            match (&x.node, loads) {
+                (Stmt::Load(_), None) => loads = Some(x.span),
+                (Stmt::Load(_), Some(span)) => loads = Some(span.merge(x.span)),
+                (_, Some(span)) => {
+                    self.span(span, Some(FoldingRangeKind::Imports));
+                    loads = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(span) = loads {
+            self.span(span, Some(FoldingRangeKind::Imports));
+        }
+    }
+
+    fn visit(&mut self, x: Visit<'_, AstNoPayload>) {
+        match x {
+            Visit::Stmt(x) => match &x.node {
+                Stmt::Statements(xs) => self.statements(xs),
+                Stmt::Def(..) | Stmt::For(..) => self.span(x.span, None),
+                Stmt::If(..) => self.span(x.span, None),
+                Stmt::IfElse(_, box (then_block, else_block)) => {
+                    self.span(Span::new(x.span.begin(), then_block.span.end()), None);
+                    match &else_block.node {
+                        // An `elif`, which gets folded when we visit it.
+                        Stmt::If(..) | Stmt::IfElse(..) => {}
+                        _ => self.else_branch(then_block.span, else_block),
+                    }
+                }
+                Stmt::Expression(e) if matches!(&e.node, Expr::Literal(AstLiteral::String(_))) => {
+                    self.span(x.span, Some(FoldingRangeKind::Comment))
+                }
+                _ => {}
+            },
+            Visit::Expr(x) => match &x.node {
+                Expr::Call(..) | Expr::List(..) | Expr::Dict(..) | Expr::Tuple(..) => {
+                    self.span(x.span, None)
+                }
+                _ => {}
+            },
+        }
+        x.visit_children(|x| self.visit(x));
+    }
+}
+
+impl AstModule {
+    /// The regions of the module which can be folded: blocks, multi-line calls and
+    /// collection literals, docstrings and groups of `load` statements.
+    pub(crate) fn folding_ranges(&self) -> Vec<FoldingRange> {
+        let mut folds = Folds {
+            codemap: &self.codemap,
+            res: Vec::new(),
+        };
+        folds.visit(Visit::Stmt(&self.statement));
+        folds.res.sort_by_key(|x| (x.start_line, x.end_line));
+        folds.res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    fn folds(x: &str) -> Vec<(u32, u32, Option<FoldingRangeKind>)> {
+        module(x)
+            .folding_ranges()
+            .into_map(|x| (x.start_line, x.end_line, x.kind))
+    }
+
+    #[test]
+    fn test_folding_ranges() {
+        let code = r#"
+load("a", "a")
+load(
+    "b",
+    "b",
+)
+
+def f(x):
+    """
+    Docs.
+    """
+    if x:
+        pass
+    elif x == 1:
+        pass
+    else:
+        for y in x:
+
+            print(y)
+
+    return [
+        1,
+        2,
+    ]
+
+g(1, 2)
+"#;
+        assert_eq!(
+            folds(code),
+            vec![
+                (1, 5, Some(FoldingRangeKind::Imports)),
+                (7, 23, None),
+                (8, 10, Some(FoldingRangeKind::Comment)),
+                (11, 12, None),
+                (13, 14, None),
+                (15, 18, None),
+                (16, 18, None),
+                (20, 23, None),
+            ]
+        );
+    }
+}
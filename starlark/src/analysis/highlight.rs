@@ -0,0 +1,102 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Highlight every use of the variable under the cursor.
+
+use lsp_types::DocumentHighlight;
+use lsp_types::DocumentHighlightKind;
+
+use crate::codemap::Pos;
+use crate::syntax::AstModule;
+
+impl AstModule {
+    /// The reads and writes of the variable at `pos`. Variables are resolved through the
+    /// scopes, so a parameter is distinct from a global of the same name.
+    pub(crate) fn document_highlights(&self, pos: Pos) -> Vec<DocumentHighlight> {
+        let references = self.references();
+        let target = match references.iter().find(|x| x.span.contains(pos)) {
+            Some(target) => target,
+            None => return Vec::new(),
+        };
+        let mut res: Vec<_> = references
+            .iter()
+            .filter(|x| x.same_variable(target))
+            .map(|x| (x.identifier_span(&self.codemap), x.write))
+            .collect();
+        // An augmented assignment both reads and writes, which we show as a write.
+        res.sort_by_key(|(span, write)| (span.begin(), !write));
+        res.dedup_by_key(|(span, _)| *span);
+        res.into_iter()
+            .map(|(span, write)| DocumentHighlight {
+                range: self.codemap.resolve_span(span).into(),
+                kind: Some(if write {
+                    DocumentHighlightKind::WRITE
+                } else {
+                    DocumentHighlightKind::READ
+                }),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    fn highlights(code: &str, at: &str) -> Vec<String> {
+        let m = module(code);
+        let pos = Pos::new(code.rfind(at).unwrap() as u32);
+        m.document_highlights(pos).into_map(|x| {
+            format!(
+                "{}:{}-{} {}",
+                x.range.start.line,
+                x.range.start.character,
+                x.range.end.character,
+                if x.kind == Some(DocumentHighlightKind::WRITE) {
+                    "write"
+                } else {
+                    "read"
+                }
+            )
+        })
+    }
+
+    #[test]
+    fn test_document_highlights() {
+        let code = r#"
+load("a", "x")
+def f(x):
+    x += 1
+    return x
+y = x
+"#;
+        // The parameter shadows the loaded symbol.
+        assert_eq!(
+            highlights(code, "x += 1"),
+            vec!["2:6-7 write", "3:4-5 write", "4:11-12 read"]
+        );
+        assert_eq!(highlights(code, "x\n"), vec!["1:11-12 write", "5:4-5 read"]);
+        assert_eq!(highlights(code, "return"), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,199 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Lint runs of `load` statements which aren't in the canonical order: one `load` per module,
+//! sorted by module, each loading its symbols once, sorted by the name they are bound to.
+//! That is the order [`AstModule::format`] sorts them in, but the formatter never merges
+//! statements, so this lint does.
+
+use std::collections::BTreeMap;
+
+use gazebo::variants::VariantName;
+use thiserror::Error;
+
+use crate::analysis::types::LintT;
+use crate::analysis::types::LintWarning;
+use crate::codemap::Span;
+use crate::syntax::ast::AstAssignIdent;
+use crate::syntax::ast::AstLoad;
+use crate::syntax::ast::AstString;
+use crate::syntax::ast::Stmt;
+use crate::syntax::format::comments;
+use crate::syntax::AstModule;
+
+#[derive(Error, Debug, VariantName)]
+pub(crate) enum LoadWarning {
+    #[error("`load` statements should be merged by module, deduplicated and sorted")]
+    UnsortedLoads,
+}
+
+impl LintWarning for LoadWarning {
+    fn is_serious(&self) -> bool {
+        false
+    }
+}
+
+type Symbol = (AstAssignIdent, AstString);
+
+/// The runs of consecutive top-level `load` statements, with the span of each run.
+fn load_runs(module: &AstModule) -> Vec<(Span, Vec<&AstLoad>)> {
+    let xs = match &*module.statement {
+        Stmt::Statements(xs) => xs.as_slice(),
+        _ => std::slice::from_ref(&module.statement),
+    };
+    let mut res: Vec<(Span, Vec<&AstLoad>)> = Vec::new();
+    let mut in_run = false;
+    for x in xs {
+        match &**x {
+            Stmt::Load(load) => match res.last_mut() {
+                Some((span, run)) if in_run => {
+                    *span = span.merge(x.span);
+                    run.push(load);
+                }
+                _ => res.push((x.span, vec![load])),
+            },
+            _ => {
+                in_run = false;
+                continue;
+            }
+        }
+        in_run = true;
+    }
+    res
+}
+
+/// The statements in canonical order, as module and symbols.
+fn canonical<'a>(run: &[&'a AstLoad]) -> Vec<(&'a AstString, Vec<&'a Symbol>)> {
+    let mut modules: BTreeMap<&str, (&AstString, Vec<&Symbol>)> = BTreeMap::new();
+    for load in run {
+        let entry = modules
+            .entry(&load.module.node)
+            .or_insert_with(|| (&load.module, Vec::new()));
+        for x in &load.args {
+            if !entry.1.iter().any(|y| same_symbol(x, y)) {
+                entry.1.push(x);
+            }
+        }
+    }
+    modules
+        .into_values()
+        .map(|(module, mut symbols)| {
+            symbols.sort_by(|a, b| a.0.0.cmp(&b.0.0));
+            (module, symbols)
+        })
+        .collect()
+}
+
+fn same_symbol(x: &Symbol, y: &Symbol) -> bool {
+    x.0.0 == y.0.0 && x.1.node == y.1.node
+}
+
+pub(crate) fn load_warnings(module: &AstModule) -> Vec<LintT<LoadWarning>> {
+    let codemap = &module.codemap;
+    let comments = comments(module);
+    let mut res = Vec::new();
+    for (span, run) in load_runs(module) {
+        let canonical = canonical(&run);
+        let unchanged = run.len() == canonical.len()
+            && run.iter().zip(&canonical).all(|(load, (module, symbols))| {
+                load.module.node == module.node
+                    && load.args.len() == symbols.len()
+                    && load
+                        .args
+                        .iter()
+                        .zip(symbols)
+                        .all(|(x, y)| same_symbol(x, y))
+            });
+        if unchanged {
+            continue;
+        }
+        let lint = LintT::new(codemap, span, LoadWarning::UnsortedLoads);
+        // Rewriting the statements would lose any comments among them.
+        let commented = comments
+            .iter()
+            .any(|c| span.begin().get() as usize <= c.begin && c.begin < span.end().get() as usize);
+        if commented {
+            res.push(lint);
+            continue;
+        }
+        let text = |span: Span| codemap.source_span(span);
+        let replacement = canonical
+            .iter()
+            .map(|(module, symbols)| {
+                let mut res = format!("load({}", text(module.span));
+                for (local, name) in symbols {
+                    res.push_str(", ");
+                    if local.span != name.span {
+                        res.push_str(&local.0);
+                        res.push_str(" = ");
+                    }
+                    res.push_str(text(name.span));
+                }
+                res.push(')');
+                res
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        res.push(lint.with_fix(codemap, "Sort `load` statements", vec![(span, replacement)]));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    fn fixes(x: &str) -> Vec<(String, Vec<String>)> {
+        load_warnings(&module(x)).map(|x| {
+            (
+                x.to_string(),
+                x.fixes.map(|x| x.edits[0].replacement.clone()),
+            )
+        })
+    }
+
+    #[test]
+    fn test_sorted_loads() {
+        assert_eq!(
+            fixes("load('a', 'x', y = 'z')\nload('b', 'w')\nx = 1\nload('a', 'x')\n"),
+            Vec::new()
+        );
+        assert_eq!(
+            fixes("load('b', 'w')\nload('a', 'y', 'x')\nload('b', 'w', v = 'u')\nx = 1\n"),
+            &[(
+                "X:1:1-3:24: `load` statements should be merged by module, deduplicated and sorted"
+                    .to_owned(),
+                vec!["load('a', 'x', 'y')\nload('b', v = 'u', 'w')".to_owned()]
+            )]
+        );
+        assert_eq!(
+            fixes("load('b', 'w')\n# Comment\nload('a', 'x')\n"),
+            &[(
+                "X:1:1-3:15: `load` statements should be merged by module, deduplicated and sorted"
+                    .to_owned(),
+                Vec::new()
+            )]
+        );
+    }
+}
@@ -0,0 +1,360 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Size and complexity metrics for functions, and lints for functions which exceed limits on them.
+
+use std::collections::HashSet;
+
+use gazebo::variants::VariantName;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::analysis::types::LintT;
+use crate::analysis::types::LintWarning;
+use crate::codemap::FileSpan;
+use crate::syntax::ast::AstExpr;
+use crate::syntax::ast::AstParameter;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::BinOp;
+use crate::syntax::ast::Expr;
+use crate::syntax::ast::Parameter;
+use crate::syntax::ast::Stmt;
+use crate::syntax::uniplate::Visit;
+use crate::syntax::AstModule;
+
+/// The metrics of a single `def`. Those of a nested `def` are not included in the function
+/// containing it, which instead counts the `def` as a single statement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionMetrics {
+    /// The name of the function.
+    pub name: String,
+    /// Where the function's name is defined.
+    pub location: FileSpan,
+    /// One more than the number of decision points: `if`, `for`, the clauses of
+    /// comprehensions, conditional expressions, `and` and `or`.
+    pub cyclomatic_complexity: usize,
+    /// The greatest number of `if` and `for` blocks any statement is nested within.
+    pub nesting_depth: usize,
+    /// The number of statements in the body.
+    pub statements: usize,
+    /// The number of parameters, including `*args` and `**kwargs`.
+    pub parameters: usize,
+    /// The number of distinct symbols from `load` statements the body refers to.
+    pub loaded_symbols: usize,
+}
+
+/// The metrics of a whole module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleMetrics {
+    /// The number of statements in the module, including those within functions.
+    pub statements: usize,
+    /// The number of symbols loaded.
+    pub loaded_symbols: usize,
+    /// The metrics of every function, in the order they are defined.
+    pub functions: Vec<FunctionMetrics>,
+}
+
+/// The greatest value of each metric a function may have before it is linted,
+/// part of a [`LintConfig`](crate::errors::LintConfig). By default there are no limits.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricLimits {
+    /// The limit on [`FunctionMetrics::cyclomatic_complexity`].
+    pub cyclomatic_complexity: Option<usize>,
+    /// The limit on [`FunctionMetrics::nesting_depth`].
+    pub nesting_depth: Option<usize>,
+    /// The limit on [`FunctionMetrics::statements`].
+    pub statements: Option<usize>,
+    /// The limit on [`FunctionMetrics::parameters`].
+    pub parameters: Option<usize>,
+    /// The limit on [`FunctionMetrics::loaded_symbols`].
+    pub loaded_symbols: Option<usize>,
+}
+
+impl MetricLimits {
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Error, Debug, VariantName)]
+pub(crate) enum MetricWarning {
+    #[error("Function `{0}` has cyclomatic complexity {1}, more than the limit of {2}")]
+    HighComplexity(String, usize, usize),
+    #[error("Function `{0}` nests blocks {1} deep, more than the limit of {2}")]
+    DeepNesting(String, usize, usize),
+    #[error("Function `{0}` has {1} statements, more than the limit of {2}")]
+    TooManyStatements(String, usize, usize),
+    #[error("Function `{0}` has {1} parameters, more than the limit of {2}")]
+    TooManyParameters(String, usize, usize),
+    #[error("Function `{0}` uses {1} loaded symbols, more than the limit of {2}")]
+    TooManyLoadedSymbols(String, usize, usize),
+}
+
+impl LintWarning for MetricWarning {
+    fn is_serious(&self) -> bool {
+        false
+    }
+}
+
+/// Accumulates the metrics of one function body, stopping at nested `def`s.
+struct Counter<'a> {
+    loads: &'a HashSet<&'a str>,
+    complexity: usize,
+    depth: usize,
+    max_depth: usize,
+    statements: usize,
+    loaded: HashSet<&'a str>,
+}
+
+impl<'a> Counter<'a> {
+    fn expr(&mut self, x: &'a AstExpr) {
+        match &**x {
+            Expr::If(..) => self.complexity += 1,
+            Expr::Op(_, BinOp::And | BinOp::Or, _) => self.complexity += 1,
+            Expr::ListComprehension(_, _, clauses) | Expr::DictComprehension(_, _, clauses) => {
+                // The first `for` clause is held separately from the rest.
+                self.complexity += 1 + clauses.len();
+            }
+            Expr::Identifier(name, _) if self.loads.contains(name.node.as_str()) => {
+                self.loaded.insert(&name.node);
+            }
+            _ => {}
+        }
+        x.visit_expr(|x| self.expr(x));
+    }
+
+    fn stmt(&mut self, x: &'a AstStmt) {
+        match &**x {
+            Stmt::Statements(_) => {}
+            Stmt::Def(_, params, ret, _, _) => {
+                // Only the parts evaluated by this function, not the body.
+                self.statements += 1;
+                params.iter().for_each(|x| x.visit_expr(|x| self.expr(x)));
+                ret.iter().for_each(|x| self.expr(x));
+                return;
+            }
+            _ => self.statements += 1,
+        }
+        let block = matches!(&**x, Stmt::If(..) | Stmt::IfElse(..) | Stmt::For(..));
+        if block {
+            self.complexity += 1;
+            self.depth += 1;
+            self.max_depth = self.max_depth.max(self.depth);
+        }
+        x.visit_children(|x| match x {
+            Visit::Stmt(x) => self.stmt(x),
+            Visit::Expr(x) => self.expr(x),
+        });
+        if block {
+            self.depth -= 1;
+        }
+    }
+}
+
+fn parameter_count(params: &[AstParameter]) -> usize {
+    params
+        .iter()
+        .filter(|x| !matches!(&***x, Parameter::NoArgs))
+        .count()
+}
+
+impl AstModule {
+    /// The loaded symbols, by their local names.
+    fn loaded_names(&self) -> HashSet<&str> {
+        let mut res = HashSet::new();
+        self.statement.visit_stmt(|x| {
+            if let Stmt::Load(load) = &**x {
+                res.extend(load.args.iter().map(|(local, _)| local.0.as_str()));
+            }
+        });
+        res
+    }
+
+    /// Compute size and complexity metrics for the module and each function defined in it.
+    pub fn metrics(&self) -> ModuleMetrics {
+        fn stmt<'a>(
+            module: &'a AstModule,
+            loads: &'a HashSet<&'a str>,
+            x: &'a AstStmt,
+            res: &mut Vec<FunctionMetrics>,
+        ) {
+            if let Stmt::Def(name, params, _, body, _) = &**x {
+                let mut counter = Counter {
+                    loads,
+                    complexity: 1,
+                    depth: 0,
+                    max_depth: 0,
+                    statements: 0,
+                    loaded: HashSet::new(),
+                };
+                counter.stmt(body);
+                res.push(FunctionMetrics {
+                    name: name.0.clone(),
+                    location: module.file_span(name.span),
+                    cyclomatic_complexity: counter.complexity,
+                    nesting_depth: counter.max_depth,
+                    statements: counter.statements,
+                    parameters: parameter_count(params),
+                    loaded_symbols: counter.loaded.len(),
+                });
+            }
+            x.visit_stmt(|x| stmt(module, loads, x, res));
+        }
+
+        fn count_statements(x: &AstStmt) -> usize {
+            let here = if matches!(&**x, Stmt::Statements(_)) {
+                0
+            } else {
+                1
+            };
+            let mut res = here;
+            x.visit_stmt(|x| res += count_statements(x));
+            res
+        }
+
+        let loads = self.loaded_names();
+        let mut functions = Vec::new();
+        stmt(self, &loads, &self.statement, &mut functions);
+        ModuleMetrics {
+            statements: count_statements(&self.statement),
+            loaded_symbols: loads.len(),
+            functions,
+        }
+    }
+}
+
+/// Lint the functions whose metrics exceed the `limits`.
+pub(crate) fn metric_warnings(
+    module: &AstModule,
+    limits: &MetricLimits,
+) -> Vec<LintT<MetricWarning>> {
+    let mut res = Vec::new();
+    for f in module.metrics().functions {
+        let checks: [(
+            Option<usize>,
+            usize,
+            fn(String, usize, usize) -> MetricWarning,
+        ); 5] = [
+            (
+                limits.cyclomatic_complexity,
+                f.cyclomatic_complexity,
+                MetricWarning::HighComplexity,
+            ),
+            (
+                limits.nesting_depth,
+                f.nesting_depth,
+                MetricWarning::DeepNesting,
+            ),
+            (
+                limits.statements,
+                f.statements,
+                MetricWarning::TooManyStatements,
+            ),
+            (
+                limits.parameters,
+                f.parameters,
+                MetricWarning::TooManyParameters,
+            ),
+            (
+                limits.loaded_symbols,
+                f.loaded_symbols,
+                MetricWarning::TooManyLoadedSymbols,
+            ),
+        ];
+        for (limit, value, warning) in checks {
+            if let Some(limit) = limit {
+                if value > limit {
+                    res.push(LintT::new(
+                        &module.codemap,
+                        f.location.span,
+                        warning(f.name.clone(), value, limit),
+                    ));
+                }
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    const CODE: &str = r#"
+load("lib", "a", "b", "c")
+def f(x, y = a, *, z = None, **kwargs):
+    if x and y:
+        for i in z:
+            if i:
+                b(i)
+    elif x or z:
+        pass
+    def g(v):
+        return [c(w) for w in v if w] if v else None
+    return g
+"#;
+
+    #[test]
+    fn test_metrics() {
+        let metrics = module(CODE).metrics();
+        assert_eq!(metrics.statements, 11);
+        assert_eq!(metrics.loaded_symbols, 3);
+        assert_eq!(
+            metrics.functions.map(|x| (
+                x.name.as_str(),
+                x.location.to_string(),
+                x.cyclomatic_complexity,
+                x.nesting_depth,
+                x.statements,
+                x.parameters,
+                x.loaded_symbols
+            )),
+            &[
+                ("f", "X:3:5-6".to_owned(), 7, 3, 8, 4, 1),
+                ("g", "X:10:9-10".to_owned(), 4, 0, 1, 1, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_metric_warnings() {
+        let limits = MetricLimits {
+            cyclomatic_complexity: Some(5),
+            parameters: Some(4),
+            loaded_symbols: Some(0),
+            ..MetricLimits::default()
+        };
+        let res = metric_warnings(&module(CODE), &limits);
+        assert_eq!(
+            res.map(|x| x.to_string()),
+            &[
+                "X:3:5-6: Function `f` has cyclomatic complexity 7, more than the limit of 5",
+                "X:3:5-6: Function `f` uses 1 loaded symbols, more than the limit of 0",
+                "X:10:9-10: Function `g` uses 1 loaded symbols, more than the limit of 0",
+            ]
+        );
+    }
+}
@@ -15,32 +15,83 @@
  * limitations under the License.
  */
 
+use std::collections::HashMap;
+
+pub use calls::Signatures;
+pub(crate) use config::disable_lint_edit;
+pub use config::LintConfig;
+pub use config::LintOptions;
 #[cfg(all(test, not(windows)))]
 pub(crate) use definition::helpers::FixtureWithRanges;
 pub(crate) use definition::DefinitionLocation;
 pub(crate) use definition::LspModule;
+pub use metrics::FunctionMetrics;
+pub use metrics::MetricLimits;
+pub use metrics::ModuleMetrics;
+pub(crate) use references::BindingKind;
+pub use style::Case;
+pub use style::NamingConventions;
+pub(crate) use types::DiagnosticFix;
 pub use types::EvalMessage;
 pub use types::EvalSeverity;
 pub use types::Lint;
+pub use types::LintEdit;
+pub use types::LintFix;
 
+use crate::analysis::config::Suppressions;
 use crate::analysis::types::LintT;
 use crate::syntax::AstModule;
 
 mod bind;
+mod calls;
+mod config;
 mod definition;
 mod dubious;
 mod exported;
 mod flow;
+mod folding;
+mod highlight;
 mod incompatible;
+mod loads;
+mod metrics;
 mod names;
 mod performance;
+mod references;
+mod style;
+mod typecheck;
 mod types;
+mod workspace;
 
 impl AstModule {
     /// Run a static linter over the module. If the complete set of global variables are known
     /// they can be passed as the `globals` argument, resulting in name-resolution lint errors.
     /// The precise checks run by the linter are not considered stable between versions.
+    ///
+    /// Use [`lint_with_options`](AstModule::lint_with_options) to give the signatures of
+    /// known functions, or a [`LintConfig`].
     pub fn lint(&self, globals: Option<&[&str]>) -> Vec<Lint> {
+        self.lint_with_options(&LintOptions {
+            globals,
+            ..LintOptions::default()
+        })
+    }
+
+    /// Run a static linter over the module, as [`lint`](AstModule::lint) does.
+    /// Calls are checked against the parameters of functions defined in the module,
+    /// and of those described by the [`signatures`](LintOptions::signatures). If the
+    /// [`config`](LintOptions::config) asks for it, the types of arguments, return values
+    /// and attributes are checked too, and functions whose [`metrics`](AstModule::metrics)
+    /// exceed its limits, names breaking its naming conventions, and missing or incomplete
+    /// docstrings are reported as advice.
+    ///
+    /// Lints disabled by `# starlark-lint: disable=` comments in the module are dropped,
+    /// and the `config`, if given, chooses which others to report and how severely.
+    pub fn lint_with_options(&self, options: &LintOptions) -> Vec<Lint> {
+        let LintOptions {
+            globals,
+            signatures,
+            config,
+        } = *options;
         let mut res = Vec::new();
         res.extend(flow::flow_issues(self).into_iter().map(LintT::erase));
         res.extend(
@@ -54,7 +105,77 @@ impl AstModule {
                 .into_iter()
                 .map(LintT::erase),
         );
+        res.extend(loads::load_warnings(self).into_iter().map(LintT::erase));
         res.extend(performance::performance(self).into_iter().map(LintT::erase));
+        res.extend(
+            calls::call_warnings(self, signatures)
+                .into_iter()
+                .map(LintT::erase),
+        );
+        if config.map_or(false, |x| x.typecheck) {
+            res.extend(
+                typecheck::type_warnings(self, signatures)
+                    .into_iter()
+                    .map(LintT::erase),
+            );
+        }
+        if let Some(limits) = config.map(|x| &x.metrics).filter(|x| !x.is_empty()) {
+            res.extend(
+                metrics::metric_warnings(self, limits)
+                    .into_iter()
+                    .map(|x| Lint {
+                        severity: EvalSeverity::Advice,
+                        ..x.erase()
+                    }),
+            );
+        }
+        if let Some(config) = config.filter(|x| !x.naming.is_empty() || x.docstrings) {
+            res.extend(
+                style::style_warnings(self, &config.naming, config.docstrings)
+                    .into_iter()
+                    .map(|x| Lint {
+                        severity: EvalSeverity::Advice,
+                        ..x.erase()
+                    }),
+            );
+        }
+        let suppressions = Suppressions::new(self);
+        res.retain_mut(|x| {
+            !suppressions.suppresses(x) && config.map_or(true, |config| config.apply(x))
+        });
+        res
+    }
+
+    /// Lint a set of modules together, following the `load` statements between them.
+    /// `resolve_load` is given the path passed to `load` and the filename of the module
+    /// doing the loading, and returns the filename of the module loaded. Loads which can't
+    /// be resolved, or resolve to a module not in `modules`, are not checked.
+    ///
+    /// Reports cyclic loads, loads of symbols the loaded module doesn't export (which
+    /// includes its own loads if the dialect has
+    /// [`enable_load_reexport`](crate::syntax::Dialect::enable_load_reexport)), loads
+    /// of private symbols, and symbols exported by a loaded module which nothing loads.
+    /// Comments and the `config` apply as they do for
+    /// [`lint_with_options`](AstModule::lint_with_options).
+    pub fn lint_workspace(
+        modules: &[AstModule],
+        resolve_load: impl Fn(&str, &str) -> anyhow::Result<String>,
+        config: Option<&LintConfig>,
+    ) -> Vec<Lint> {
+        let mut res: Vec<Lint> = workspace::workspace_warnings(modules, resolve_load)
+            .into_iter()
+            .map(LintT::erase)
+            .collect();
+        let suppressions: HashMap<&str, Suppressions> = modules
+            .iter()
+            .map(|x| (x.codemap.filename(), Suppressions::new(x)))
+            .collect();
+        res.retain_mut(|x| {
+            let suppressed = suppressions
+                .get(x.location.file.filename())
+                .map_or(false, |s| s.suppresses(x));
+            !suppressed && config.map_or(true, |config| config.apply(x))
+        });
         res
     }
 }
@@ -26,12 +26,15 @@ use crate::analysis::bind;
 use crate::analysis::bind::Assigner;
 use crate::analysis::bind::Bind;
 use crate::analysis::bind::Scope;
+use crate::analysis::types::remove_statement;
 use crate::analysis::types::LintT;
 use crate::analysis::types::LintWarning;
 use crate::codemap::CodeMap;
 use crate::codemap::Span;
 use crate::syntax::ast::Assign;
+use crate::syntax::ast::AstAssignIdent;
 use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::AstString;
 use crate::syntax::ast::Expr;
 use crate::syntax::ast::Stmt;
 use crate::syntax::AstModule;
@@ -52,6 +55,8 @@ pub(crate) enum NameWarning {
     UnderscoreFunction(String),
     #[error("Used ignored variable `{0}`")]
     UsingIgnored(String),
+    #[error("Definition of `{0}` shadows a builtin")]
+    ShadowedBuiltin(String),
 }
 
 impl LintWarning for NameWarning {
@@ -88,10 +93,110 @@ pub(crate) fn name_warnings(
     unassigned_variable(&module.codemap, &scope, &mut res);
     if let Some(globals) = globals {
         undefined_variable(&module.codemap, &scope, globals, &mut res);
+        shadowed_builtin(&module.codemap, &scope, globals, &mut res);
     }
     inappropriate_underscore(&module.codemap, &module.statement, true, &mut res);
     use_ignored(&module.codemap, &scope, None, &mut res);
-    res
+    res.into_map(|x| add_fix(module, &scope, x))
+}
+
+/// Attach a fix to those warnings which have an obvious mechanical one.
+fn add_fix(module: &AstModule, scope: &Scope, x: LintT<NameWarning>) -> LintT<NameWarning> {
+    let codemap = &module.codemap;
+    let span = x.location.span;
+    match &x.problem {
+        NameWarning::UnusedLoad(name) => match unused_load_fix(codemap, &module.statement, span) {
+            Some(edit) => {
+                let title = format!("Remove unused load of `{}`", name);
+                x.with_fix(codemap, title, vec![edit])
+            }
+            None => x,
+        },
+        NameWarning::UnusedArgument(name) if !name.starts_with('_') => {
+            let title = format!("Rename unused argument to `_{}`", name);
+            let edit = (span, format!("_{}", name));
+            // Callers may pass the argument by keyword, possibly from other files.
+            x.with_unsafe_fix(codemap, title, vec![edit])
+        }
+        NameWarning::UnderscoreFunction(name) => {
+            let new_name = name.trim_start_match('_');
+            match rename_fix(scope, span, name, new_name) {
+                Some(spans) if !new_name.is_empty() => {
+                    let title = format!("Rename `{}` to `{}`", name, new_name);
+                    let edits = spans.into_map(|span| (span, new_name.to_owned()));
+                    x.with_unsafe_fix(codemap, title, edits)
+                }
+                _ => x,
+            }
+        }
+        _ => x,
+    }
+}
+
+/// The edit which drops the loaded symbol bound at `span` from its `load`,
+/// or the whole `load` statement if it is the only symbol.
+fn unused_load_fix(codemap: &CodeMap, x: &AstStmt, span: Span) -> Option<(Span, String)> {
+    match &**x {
+        Stmt::Load(load) => {
+            let extent = |(local, name): &(AstAssignIdent, AstString)| local.span.merge(name.span);
+            let i = load.args.iter().position(|(local, _)| local.span == span)?;
+            Some(if load.args.len() == 1 {
+                remove_statement(codemap, x.span, false)
+            } else if i == 0 {
+                let span = Span::new(extent(&load.args[0]).begin(), extent(&load.args[1]).begin());
+                (span, String::new())
+            } else {
+                let span = Span::new(extent(&load.args[i - 1]).end(), extent(&load.args[i]).end());
+                (span, String::new())
+            })
+        }
+        Stmt::Statements(xs) => xs.iter().find_map(|x| unused_load_fix(codemap, x, span)),
+        _ => None,
+    }
+}
+
+/// The spans which need changing to rename the variable `name`, bound at `span`,
+/// to `new_name`. Returns `None` if the rename would clash with another variable.
+fn rename_fix(scope: &Scope, span: Span, name: &str, new_name: &str) -> Option<Vec<Span>> {
+    fn find(scope: &Scope, span: Span) -> Option<&Scope> {
+        scope.inner.iter().find_map(|x| match x {
+            Bind::Set(_, x) if x.span == span => Some(scope),
+            Bind::Scope(inner) => find(inner, span),
+            _ => None,
+        })
+    }
+
+    // Returns false if the new name would be captured by some other binding.
+    fn references(scope: &Scope, name: &str, new_name: &str, res: &mut Vec<Span>) -> bool {
+        for x in &scope.inner {
+            match x {
+                Bind::Set(_, x) if x.0 == name => res.push(x.span),
+                Bind::Get(x) if x.node == name => res.push(x.span),
+                Bind::Scope(inner) if !inner.bound.contains_key(name) => {
+                    if inner.free.contains_key(name) && inner.bound.contains_key(new_name) {
+                        return false;
+                    }
+                    if !references(inner, name, new_name, res) {
+                        return false;
+                    }
+                }
+                _ => {}
+            }
+        }
+        true
+    }
+
+    let scope = find(scope, span)?;
+    if scope.bound.contains_key(new_name) || scope.free.contains_key(new_name) {
+        return None;
+    }
+    let mut res = Vec::new();
+    if !references(scope, name, new_name, &mut res) {
+        return None;
+    }
+    res.sort_by_key(|x| x.begin());
+    res.dedup();
+    Some(res)
 }
 
 fn undefined_variable(
@@ -112,6 +217,35 @@ fn undefined_variable(
     }
 }
 
+fn shadowed_builtin(
+    codemap: &CodeMap,
+    scope: &Scope,
+    globals: &[&str],
+    res: &mut Vec<LintT<NameWarning>>,
+) {
+    fn f(
+        codemap: &CodeMap,
+        scope: &Scope,
+        globals: &HashSet<&str>,
+        res: &mut Vec<LintT<NameWarning>>,
+    ) {
+        for x in &scope.inner {
+            match x {
+                Bind::Set(_, x) if globals.contains(x.0.as_str()) => res.push(LintT::new(
+                    codemap,
+                    x.span,
+                    NameWarning::ShadowedBuiltin(x.0.clone()),
+                )),
+                Bind::Scope(scope) => f(codemap, scope, globals, res),
+                _ => {}
+            }
+        }
+    }
+
+    let globals: HashSet<&str> = globals.iter().copied().collect();
+    f(codemap, scope, &globals, res)
+}
+
 fn duplicate_assign(
     codemap: &CodeMap,
     scope: &Scope,
@@ -296,6 +430,7 @@ mod tests {
                 NameWarning::UsingUndefined(x) => x,
                 NameWarning::UnderscoreFunction(x) => x,
                 NameWarning::UsingIgnored(x) => x,
+                NameWarning::ShadowedBuiltin(x) => x,
             }
         }
     }
@@ -424,6 +559,32 @@ def foo():
         assert_eq!(res, &["no1", "no2"])
     }
 
+    #[test]
+    fn test_lint_shadowed_builtin() {
+        let m = module(
+            r#"
+load("test", "no1", len = "length")
+str = 1
+def foo(list, x):
+    [dict for dict in x]
+    lambda repr: repr
+    def fail(): pass
+    len = 2
+"#,
+        );
+        let mut res = Vec::new();
+        let scope = bind::scope(&m);
+        shadowed_builtin(
+            &m.codemap,
+            &scope,
+            &["len", "str", "list", "dict", "repr", "fail", "print"],
+            &mut res,
+        );
+        let mut res = res.map(|x| x.problem.about());
+        res.sort();
+        assert_eq!(res, &["dict", "fail", "len", "len", "list", "repr", "str"])
+    }
+
     #[test]
     fn test_lint_inappropriate_underscore() {
         let m = module(
@@ -472,4 +633,44 @@ def foo():
         res.sort();
         assert_eq!(res, &["_no1", "_no2", "_no3", "_no4"])
     }
+
+    #[test]
+    fn test_lint_fixes() {
+        let m = module(
+            r#"
+load("test", "no1", "a", no2 = "b")
+load("other", "no3")
+def foo(no4, x):
+    def _no5():
+        return x
+    return _no5()
+foo(a)
+"#,
+        );
+        let mut res: Vec<_> = name_warnings(&m, None)
+            .into_iter()
+            .flat_map(|x| x.fixes)
+            .map(|fix| {
+                format!(
+                    "{}{}: {}",
+                    if fix.safe { "" } else { "(unsafe) " },
+                    fix.title,
+                    fix.edits
+                        .map(|e| format!("{} {:?}", e.span, e.replacement))
+                        .join(", ")
+                )
+            })
+            .collect();
+        res.sort();
+        assert_eq!(
+            res,
+            &[
+                "(unsafe) Rename `_no5` to `no5`: 5:9-13 \"no5\", 7:12-16 \"no5\"",
+                "(unsafe) Rename unused argument to `_no4`: 4:9-12 \"_no4\"",
+                "Remove unused load of `no1`: 2:14-21 \"\"",
+                "Remove unused load of `no2`: 2:24-35 \"\"",
+                "Remove unused load of `no3`: 3:1-4:1 \"\"",
+            ]
+        );
+    }
 }
@@ -43,11 +43,19 @@ fn match_dict_copy(codemap: &CodeMap, x: &AstExpr, res: &mut Vec<LintT<Performan
     match &**x {
         Expr::Call(fun, args) if args.len() == 1 => match (&***fun, &*args[0]) {
             (Expr::Identifier(f, _), Argument::KwArgs(arg)) if f.node == "dict" => {
-                res.push(LintT::new(
-                    codemap,
-                    x.span,
-                    Performance::DictWithoutStarStar(x.to_string(), format!("dict({})", arg.node)),
-                ))
+                let replacement = format!("dict({})", codemap.source_span(arg.span));
+                let title = format!("Replace with `{}`", replacement);
+                res.push(
+                    LintT::new(
+                        codemap,
+                        x.span,
+                        Performance::DictWithoutStarStar(
+                            x.to_string(),
+                            format!("dict({})", arg.node),
+                        ),
+                    )
+                    .with_fix(codemap, title, vec![(x.span, replacement)]),
+                )
             }
             _ => {}
         },
@@ -100,5 +108,6 @@ def foo(extra, **kwargs):
             res.map(|x| x.to_string()),
             &["bad.bzl:3:9-23: Dict copy `dict(**kwargs)` is more efficient as `dict(kwargs)`"]
         );
+        assert_eq!(res[0].fixes[0].edits[0].replacement, "dict(kwargs)");
     }
 }
@@ -0,0 +1,221 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Resolve every identifier in a module to the binding it refers to.
+
+use std::collections::HashSet;
+
+use crate::analysis::bind::scope;
+use crate::analysis::bind::Assigner;
+use crate::analysis::bind::Bind;
+use crate::analysis::bind::Scope;
+use crate::codemap::CodeMap;
+use crate::codemap::Pos;
+use crate::codemap::Span;
+use crate::syntax::AstModule;
+
+/// Where the variable an identifier refers to comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum BindingKind {
+    /// A parameter of a `def` or `lambda`.
+    Parameter,
+    /// A variable assigned inside a `def`, `lambda` or comprehension.
+    Local,
+    /// A variable assigned at the top level of the module.
+    ModuleGlobal,
+    /// A symbol brought in by `load`.
+    Loaded,
+    /// A name the module never binds, so must be provided by the environment.
+    Builtin,
+}
+
+/// An occurrence of an identifier in the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Reference {
+    pub(crate) span: Span,
+    pub(crate) name: String,
+    pub(crate) kind: BindingKind,
+    /// The identifier is being assigned to, rather than read.
+    pub(crate) write: bool,
+    /// The span of the first binding of the variable, which identifies it.
+    /// `None` for builtins, which are identified by name alone.
+    pub(crate) binding: Option<Span>,
+    /// The variable is never read, and is not exported from the module.
+    pub(crate) unused: bool,
+}
+
+impl Reference {
+    /// Whether both references are to the same variable.
+    pub(crate) fn same_variable(&self, other: &Reference) -> bool {
+        self.binding == other.binding && self.name == other.name
+    }
+
+    /// The span of the identifier itself. A `load` without an alias binds the name inside
+    /// the string, in which case this excludes the quotes.
+    pub(crate) fn identifier_span(&self, codemap: &CodeMap) -> Span {
+        let text = codemap.source_span(self.span);
+        if text.starts_with(['"', '\'']) && text.len() >= 2 {
+            Span::new(self.span.begin() + 1, Pos::new(self.span.end().get() - 1))
+        } else {
+            self.span
+        }
+    }
+}
+
+fn resolve(name: &str, scopes: &[&Scope]) -> (BindingKind, Option<Span>) {
+    for (i, scope) in scopes.iter().enumerate().rev() {
+        if let Some((assigner, span)) = scope.bound.get(name) {
+            let kind = match assigner {
+                Assigner::Argument => BindingKind::Parameter,
+                Assigner::Load { .. } => BindingKind::Loaded,
+                Assigner::Assign if i == 0 => BindingKind::ModuleGlobal,
+                Assigner::Assign => BindingKind::Local,
+            };
+            return (kind, Some(*span));
+        }
+    }
+    (BindingKind::Builtin, None)
+}
+
+fn walk<'a>(scope: &'a Scope, scopes: &mut Vec<&'a Scope>, res: &mut Vec<Reference>) {
+    scopes.push(scope);
+    for bind in &scope.inner {
+        let (span, name, write) = match bind {
+            Bind::Set(_, x) => (x.span, &x.0, true),
+            Bind::Get(x) => (x.span, &x.node, false),
+            Bind::Scope(inner) => {
+                walk(inner, scopes, res);
+                continue;
+            }
+            Bind::Flow => continue,
+        };
+        let (kind, binding) = resolve(name, scopes);
+        res.push(Reference {
+            span,
+            name: name.clone(),
+            kind,
+            write,
+            binding,
+            unused: false,
+        });
+    }
+    scopes.pop();
+}
+
+impl AstModule {
+    /// Every identifier in the module, in the order the scopes visit them (not source order).
+    pub(crate) fn references(&self) -> Vec<Reference> {
+        let mut res = Vec::new();
+        walk(&scope(self), &mut Vec::new(), &mut res);
+
+        let read: HashSet<(Option<Span>, &str)> = res
+            .iter()
+            .filter(|x| !x.write)
+            .map(|x| (x.binding, x.name.as_str()))
+            .collect();
+        let unused: Vec<bool> = res
+            .iter()
+            .map(|x| {
+                let exported = x.kind == BindingKind::ModuleGlobal && !x.name.starts_with('_');
+                x.binding.is_some() && !exported && !read.contains(&(x.binding, x.name.as_str()))
+            })
+            .collect();
+        for (x, unused) in res.iter_mut().zip(unused) {
+            x.unused = unused;
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    fn describe(x: &str) -> Vec<String> {
+        let m = module(x);
+        let mut refs = m.references();
+        refs.sort_by_key(|x| x.span.begin());
+        refs.map(|r| {
+            format!(
+                "{} {:?}{}{}",
+                m.codemap.source_span(r.span),
+                r.kind,
+                if r.write { " write" } else { "" },
+                if r.unused { " unused" } else { "" },
+            )
+        })
+    }
+
+    #[test]
+    fn test_references() {
+        let refs = describe(
+            r#"
+load("a", "a", b = "c")
+x = a
+def f(p, q):
+    y = p + x
+    _z = len(y)
+    return [i for i in y]
+"#,
+        );
+        assert_eq!(
+            refs,
+            vec![
+                "\"a\" Loaded write",
+                "b Loaded write unused",
+                "x ModuleGlobal write",
+                "a Loaded",
+                "f ModuleGlobal write",
+                "p Parameter write",
+                "q Parameter write unused",
+                "y Local write",
+                "p Parameter",
+                "x ModuleGlobal",
+                "_z Local write unused",
+                "len Builtin",
+                "y Local",
+                "i Local",
+                "i Local write",
+                "y Local",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_same_variable() {
+        let m = module("x = 1\ndef f(x):\n    return x + len(x)\ny = x + len(x)\n");
+        let mut refs = m.references();
+        refs.sort_by_key(|x| x.span.begin());
+        let same = |i: usize| {
+            refs.iter()
+                .filter(|x| x.same_variable(&refs[i]))
+                .map(|x| m.codemap.resolve_span(x.span).begin_line)
+                .collect::<Vec<_>>()
+        };
+        // The global `x`, the parameter `x` and `len`.
+        assert_eq!(same(0), vec![0, 3, 3]);
+        assert_eq!(same(2), vec![1, 2, 2]);
+        assert_eq!(same(4), vec![2, 3]);
+    }
+}
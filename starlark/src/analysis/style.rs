@@ -0,0 +1,401 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Lints enforcing a style guide: the case names are written in, and docstrings on functions.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Display;
+
+use gazebo::variants::VariantName;
+use serde::Deserialize;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::analysis::types::LintT;
+use crate::analysis::types::LintWarning;
+use crate::codemap::Span;
+use crate::syntax::ast::AstAssign;
+use crate::syntax::ast::AstAssignIdent;
+use crate::syntax::ast::AstExpr;
+use crate::syntax::ast::AstParameter;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::Clause;
+use crate::syntax::ast::Expr;
+use crate::syntax::ast::Stmt;
+use crate::syntax::uniplate::Visit;
+use crate::syntax::AstModule;
+use crate::values::docs::DocString;
+use crate::values::docs::DocStringKind;
+
+/// The case a name must be written in. Leading underscores, which mark a name as
+/// private, are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Case {
+    /// Lower case letters, digits and underscores, e.g. `parse_file`.
+    #[serde(rename = "snake_case")]
+    Snake,
+    /// Upper case letters, digits and underscores, e.g. `MAX_SIZE`.
+    #[serde(rename = "UPPER_CASE")]
+    Upper,
+    /// Starting with an upper case letter, without underscores, e.g. `FileInfo`.
+    #[serde(rename = "CamelCase")]
+    Camel,
+}
+
+impl Case {
+    fn matches(self, name: &str) -> bool {
+        let name = name.trim_start_matches('_');
+        match self {
+            Self::Snake => name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'),
+            Self::Upper => name
+                .chars()
+                .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'),
+            Self::Camel => {
+                name.chars().next().map_or(true, |c| c.is_ascii_uppercase()) && !name.contains('_')
+            }
+        }
+    }
+}
+
+impl Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Snake => "snake_case",
+            Self::Upper => "UPPER_CASE",
+            Self::Camel => "CamelCase",
+        })
+    }
+}
+
+/// The case each kind of name must be written in, part of a
+/// [`LintConfig`](crate::errors::LintConfig). By default no names are checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamingConventions {
+    /// Names of functions defined with `def`.
+    pub function: Option<Case>,
+    /// Parameters of functions and lambdas.
+    pub parameter: Option<Case>,
+    /// Variables assigned at the top level, other than those only giving a loaded symbol
+    /// another name.
+    pub global: Option<Case>,
+    /// Variables assigned within functions, and those bound by `for` loops and comprehensions.
+    pub local: Option<Case>,
+}
+
+impl NamingConventions {
+    pub(crate) fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Error, Debug, VariantName)]
+pub(crate) enum StyleWarning {
+    #[error("{0} `{1}` should be written in {2}")]
+    NamingConvention(&'static str, String, Case),
+    #[error("Public function `{0}` has no docstring")]
+    MissingDocstring(String),
+    #[error("Parameter `{1}` of function `{0}` is not documented in its docstring")]
+    UndocumentedParameter(String, String),
+    #[error("Docstring of function `{0}` documents `{1}`, which is not a parameter")]
+    UnknownDocumentedParameter(String, String),
+}
+
+impl LintWarning for StyleWarning {
+    fn is_serious(&self) -> bool {
+        false
+    }
+}
+
+/// The names documented in the `Args:` section of a function's docstring, and the span of
+/// the docstring, or `None` if it has no docstring.
+fn documented_parameters(body: &AstStmt) -> Option<(Span, HashSet<String>)> {
+    let raw = DocString::extract_raw_starlark_docstring(body)?;
+    let span = match &**body {
+        Stmt::Statements(xs) => xs.first()?.span,
+        _ => return None,
+    };
+    let names = match DocString::from_docstring(DocStringKind::Starlark, &raw) {
+        Some(ds) => {
+            let (_, sections) =
+                ds.parse_and_remove_sections(DocStringKind::Starlark, &["arguments", "args"]);
+            match sections.get("arguments").or_else(|| sections.get("args")) {
+                Some(args) => DocString::parse_params(DocStringKind::Starlark, args)
+                    .into_keys()
+                    .map(|x| x.trim_start_matches('*').to_owned())
+                    .collect(),
+                None => HashSet::new(),
+            }
+        }
+        None => HashSet::new(),
+    };
+    Some((span, names))
+}
+
+struct Style<'a> {
+    module: &'a AstModule,
+    naming: &'a NamingConventions,
+    docstrings: bool,
+    loads: HashSet<&'a str>,
+    res: Vec<LintT<StyleWarning>>,
+}
+
+impl<'a> Style<'a> {
+    fn name(&mut self, case: Option<Case>, kind: &'static str, name: &AstAssignIdent) {
+        if let Some(case) = case {
+            if !case.matches(&name.0) {
+                self.res.push(LintT::new(
+                    &self.module.codemap,
+                    name.span,
+                    StyleWarning::NamingConvention(kind, name.0.clone(), case),
+                ));
+            }
+        }
+    }
+
+    fn assign(&mut self, top: bool, x: &AstAssign) {
+        let (case, kind) = if top {
+            (self.naming.global, "Global variable")
+        } else {
+            (self.naming.local, "Local variable")
+        };
+        x.visit_lvalue(|x| self.name(case, kind, x));
+    }
+
+    fn parameters(&mut self, params: &[AstParameter]) {
+        for p in params {
+            if let (Some(name), _, _) = p.split() {
+                self.name(self.naming.parameter, "Parameter", name);
+            }
+        }
+    }
+
+    fn docstring(
+        &mut self,
+        top: bool,
+        name: &AstAssignIdent,
+        params: &[AstParameter],
+        body: &AstStmt,
+    ) {
+        let codemap = &self.module.codemap;
+        let (span, documented) = match documented_parameters(body) {
+            Some(x) => x,
+            None => {
+                if top && !name.0.starts_with('_') {
+                    self.res.push(LintT::new(
+                        codemap,
+                        name.span,
+                        StyleWarning::MissingDocstring(name.0.clone()),
+                    ));
+                }
+                return;
+            }
+        };
+        let mut actual = HashSet::new();
+        for p in params {
+            if let (Some(p), _, _) = p.split() {
+                actual.insert(p.0.as_str());
+                if !documented.contains(&p.0) {
+                    self.res.push(LintT::new(
+                        codemap,
+                        p.span,
+                        StyleWarning::UndocumentedParameter(name.0.clone(), p.0.clone()),
+                    ));
+                }
+            }
+        }
+        let mut unknown: Vec<&String> = documented
+            .iter()
+            .filter(|x| !actual.contains(x.as_str()))
+            .collect();
+        unknown.sort();
+        for x in unknown {
+            self.res.push(LintT::new(
+                codemap,
+                span,
+                StyleWarning::UnknownDocumentedParameter(name.0.clone(), x.clone()),
+            ));
+        }
+    }
+
+    fn expr(&mut self, x: &AstExpr) {
+        match &**x {
+            Expr::Lambda(params, ..) => self.parameters(params),
+            Expr::ListComprehension(_, for_, clauses)
+            | Expr::DictComprehension(_, for_, clauses) => {
+                self.assign(false, &for_.var);
+                for c in clauses {
+                    if let Clause::For(c) = c {
+                        self.assign(false, &c.var);
+                    }
+                }
+            }
+            _ => {}
+        }
+        x.visit_expr(|x| self.expr(x));
+    }
+
+    /// `top` is whether the statement is outside any function.
+    fn stmt(&mut self, top: bool, x: &AstStmt) {
+        match &**x {
+            Stmt::Def(name, params, _, body, _) => {
+                self.name(self.naming.function, "Function", name);
+                self.parameters(params);
+                if self.docstrings {
+                    self.docstring(top, name, params, body);
+                }
+                x.visit_children(|x| match x {
+                    Visit::Stmt(x) => self.stmt(false, x),
+                    Visit::Expr(x) => self.expr(x),
+                });
+                return;
+            }
+            Stmt::Assign(lhs, rhs) => {
+                // `foo = _foo`, where `_foo` is loaded, only makes a loaded symbol public.
+                let reexport = top
+                    && matches!(&***rhs, Expr::Identifier(name, _) if self.loads.contains(name.node.as_str()));
+                if !reexport {
+                    self.assign(top, lhs);
+                }
+            }
+            Stmt::AssignModify(lhs, ..) => self.assign(top, lhs),
+            Stmt::For(var, _) => self.assign(false, var),
+            _ => {}
+        }
+        x.visit_children(|x| match x {
+            Visit::Stmt(x) => self.stmt(top, x),
+            Visit::Expr(x) => self.expr(x),
+        });
+    }
+}
+
+/// Lint names not written in the case the `naming` conventions require and, if `docstrings`
+/// is set, public top-level functions without docstrings and functions whose docstrings
+/// don't document exactly their parameters.
+pub(crate) fn style_warnings(
+    module: &AstModule,
+    naming: &NamingConventions,
+    docstrings: bool,
+) -> Vec<LintT<StyleWarning>> {
+    let mut loads = HashSet::new();
+    module.statement.visit_stmt(|x| {
+        if let Stmt::Load(load) = &**x {
+            loads.extend(load.args.iter().map(|(local, _)| local.0.as_str()));
+        }
+    });
+    let mut style = Style {
+        module,
+        naming,
+        docstrings,
+        loads,
+        res: Vec::new(),
+    };
+    style.stmt(true, &module.statement);
+    style.res
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    #[test]
+    fn test_case() {
+        assert!(Case::Snake.matches("_parse_file2"));
+        assert!(!Case::Snake.matches("parseFile"));
+        assert!(Case::Upper.matches("MAX_SIZE"));
+        assert!(!Case::Upper.matches("Max"));
+        assert!(Case::Camel.matches("_FileInfo"));
+        assert!(!Case::Camel.matches("File_Info"));
+    }
+
+    #[test]
+    fn test_naming_conventions() {
+        let m = module(
+            r#"
+load("lib", _rule = "rule")
+rule = _rule
+maxSize = 1
+MAX_SIZE = 2
+def parseFile(Path, good = lambda X: X):
+    Result = [y for Y in Path for y in Y]
+    for I in Result:
+        pass
+    return Result
+"#,
+        );
+        let naming = NamingConventions {
+            function: Some(Case::Snake),
+            parameter: Some(Case::Snake),
+            global: Some(Case::Upper),
+            local: Some(Case::Snake),
+        };
+        assert_eq!(
+            style_warnings(&m, &naming, false).map(|x| x.to_string()),
+            &[
+                "X:4:1-8: Global variable `maxSize` should be written in UPPER_CASE",
+                "X:6:5-14: Function `parseFile` should be written in snake_case",
+                "X:6:15-19: Parameter `Path` should be written in snake_case",
+                "X:6:35-36: Parameter `X` should be written in snake_case",
+                "X:7:5-11: Local variable `Result` should be written in snake_case",
+                "X:7:21-22: Local variable `Y` should be written in snake_case",
+                "X:8:9-10: Local variable `I` should be written in snake_case",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_docstrings() {
+        let m = module(
+            r#"
+def undocumented(x):
+    return x
+def _private(x):
+    return x
+def f(x, *args, y = None, **kwargs):
+    """Do something.
+
+    Args:
+        x: The thing.
+        *args: More things.
+        z: Not a parameter.
+    """
+    def nested():
+        pass
+    return nested
+"#,
+        );
+        assert_eq!(
+            style_warnings(&m, &NamingConventions::default(), true).map(|x| x.to_string()),
+            &[
+                "X:2:5-17: Public function `undocumented` has no docstring",
+                "X:6:17-18: Parameter `y` of function `f` is not documented in its docstring",
+                "X:6:29-35: Parameter `kwargs` of function `f` is not documented in its docstring",
+                "X:7:5-13:8: Docstring of function `f` documents `z`, which is not a parameter",
+            ]
+        );
+    }
+}
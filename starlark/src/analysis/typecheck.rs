@@ -0,0 +1,739 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A static type checker, which infers the types of expressions from literals, type
+//! annotations and the documented types of native functions, and reports arguments,
+//! return values and attributes which can't possibly be right.
+//!
+//! The checker only reports definite mismatches: a value which might have one of several
+//! types is only reported if none of them fit. Anything it can't work out is given
+//! [`Ty::Any`], which fits everywhere.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Display;
+
+use gazebo::prelude::*;
+use gazebo::variants::VariantName;
+use itertools::Itertools;
+use once_cell::sync::Lazy;
+use thiserror::Error;
+
+use crate::analysis::calls::Callees;
+use crate::analysis::calls::Signature;
+use crate::analysis::calls::Signatures;
+use crate::analysis::types::LintT;
+use crate::analysis::types::LintWarning;
+use crate::codemap::CodeMap;
+use crate::environment::Methods;
+use crate::errors::did_you_mean::did_you_mean;
+use crate::syntax::ast::Argument;
+use crate::syntax::ast::Assign;
+use crate::syntax::ast::AstArgument;
+use crate::syntax::ast::AstAssign;
+use crate::syntax::ast::AstExpr;
+use crate::syntax::ast::AstLiteral;
+use crate::syntax::ast::AstParameter;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::AstString;
+use crate::syntax::ast::BinOp;
+use crate::syntax::ast::Clause;
+use crate::syntax::ast::Expr;
+use crate::syntax::ast::ForClause;
+use crate::syntax::ast::Parameter;
+use crate::syntax::ast::Stmt;
+use crate::syntax::uniplate::Visit;
+use crate::syntax::AstModule;
+use crate::syntax::Dialect;
+use crate::values::dict::dict_methods;
+use crate::values::docs;
+use crate::values::docs::DocItem;
+use crate::values::list::list_methods;
+use crate::values::string::StarlarkStr;
+use crate::values::StarlarkValue;
+
+#[derive(Error, Debug, VariantName)]
+pub(crate) enum TypeWarning {
+    #[error("Argument `{1}` of `{0}` should be `{2}`, but got `{3}`")]
+    IncompatibleArgument(String, String, Ty, Ty),
+    #[error("Function `{0}` should return `{1}`, but returns `{2}`")]
+    IncompatibleReturn(String, Ty, Ty),
+    #[error(
+        "Type `{0}` has no attribute `{1}`{}",
+        .2.as_ref().map_or_else(String::new, |x| format!(", did you mean `{}`?", x))
+    )]
+    UnknownAttribute(Ty, String, Option<String>),
+}
+
+impl LintWarning for TypeWarning {
+    fn is_serious(&self) -> bool {
+        true
+    }
+}
+
+/// The static type of a value, following the syntax of type annotations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Ty {
+    /// Anything at all, or we don't know.
+    Any,
+    None,
+    /// A type by the name of its values' `type()`, e.g. `string` or `list`.
+    Name(String),
+    List(Box<Ty>),
+    Dict(Box<(Ty, Ty)>),
+    Tuple(Vec<Ty>),
+    /// One of several types, never nested, containing `Any` or fewer than two types.
+    Union(Vec<Ty>),
+}
+
+impl Default for Ty {
+    fn default() -> Self {
+        Ty::Any
+    }
+}
+
+impl Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Ty::Any => f.write_str("\"\""),
+            Ty::None => f.write_str("None"),
+            Ty::Name(x) => f.write_str(x),
+            Ty::List(x) => write!(f, "[{}]", x),
+            Ty::Dict(box (k, v)) => write!(f, "{{{}: {}}}", k, v),
+            Ty::Tuple(xs) if xs.len() == 1 => write!(f, "({},)", xs[0]),
+            Ty::Tuple(xs) => write!(f, "({})", xs.iter().map(|x| x.to_string()).join(", ")),
+            Ty::Union(xs) => f.write_str(&xs.iter().map(|x| x.to_string()).join(" | ")),
+        }
+    }
+}
+
+impl Ty {
+    fn name(x: &str) -> Self {
+        Ty::Name(x.to_owned())
+    }
+
+    /// The smallest type containing both.
+    fn union(self, other: Ty) -> Ty {
+        let mut res = Vec::new();
+        for x in [self, other] {
+            match x {
+                Ty::Any => return Ty::Any,
+                Ty::Union(xs) => res.extend(xs),
+                x => res.push(x),
+            }
+        }
+        let mut unique = Vec::new();
+        for x in res {
+            if !unique.contains(&x) {
+                unique.push(x);
+            }
+        }
+        if unique.len() == 1 {
+            unique.pop().unwrap()
+        } else {
+            Ty::Union(unique)
+        }
+    }
+
+    fn unions(xs: impl IntoIterator<Item = Ty>) -> Option<Ty> {
+        xs.into_iter().reduce(Ty::union)
+    }
+
+    /// The type described by a type annotation, as checked at runtime by `TypeCompiled`.
+    pub(crate) fn from_expr(x: &AstExpr) -> Ty {
+        match &**x {
+            Expr::Literal(AstLiteral::String(x)) => {
+                if x.is_empty() || x.starts_with('_') {
+                    Ty::Any
+                } else {
+                    Ty::name(x)
+                }
+            }
+            Expr::Identifier(x, _) if x.node == "None" => Ty::None,
+            // `str.type` is `"string"`, the others are the same as the function.
+            Expr::Dot(box x, attr) if attr.node == "type" => match &**x {
+                Expr::Identifier(x, _) if x.node == "str" => Ty::name("string"),
+                Expr::Identifier(x, _) => Ty::name(&x.node),
+                _ => Ty::Any,
+            },
+            Expr::List(xs) => match xs.as_slice() {
+                [] => Ty::Any,
+                [x] => Ty::List(box Ty::from_expr(x)),
+                xs => Ty::unions(xs.iter().map(Ty::from_expr)).unwrap_or_default(),
+            },
+            Expr::Dict(xs) if xs.len() == 1 => {
+                Ty::Dict(box (Ty::from_expr(&xs[0].0), Ty::from_expr(&xs[0].1)))
+            }
+            Expr::Tuple(xs) => Ty::Tuple(xs.map(Ty::from_expr)),
+            _ => Ty::Any,
+        }
+    }
+
+    /// The type documented for a native function parameter or result. These are only
+    /// approximate, as native functions unpack their arguments in various ways,
+    /// in particular accepting a tuple where a list is documented.
+    pub(crate) fn from_docs(x: Option<&docs::Type>) -> Ty {
+        fn lenient(x: Ty) -> Ty {
+            match x {
+                Ty::List(x) => Ty::List(box lenient(*x)).union(Ty::name("tuple")),
+                Ty::Dict(box (k, v)) => Ty::Dict(box (lenient(k), lenient(v))),
+                Ty::Tuple(xs) => Ty::Tuple(xs.into_map(lenient)),
+                Ty::Union(xs) => Ty::unions(xs.into_iter().map(lenient)).unwrap_or_default(),
+                x => x,
+            }
+        }
+
+        let x = match x {
+            Some(x) => x,
+            None => return Ty::Any,
+        };
+        match AstModule::parse("type", x.raw_type.clone(), &Dialect::Extended) {
+            Ok(module) => match &*module.statement {
+                Stmt::Expression(x) => lenient(Ty::from_expr(x)),
+                Stmt::Statements(xs) => match xs.as_slice() {
+                    [x] => match &**x {
+                        Stmt::Expression(x) => lenient(Ty::from_expr(x)),
+                        _ => Ty::Any,
+                    },
+                    _ => Ty::Any,
+                },
+                _ => Ty::Any,
+            },
+            Err(_) => Ty::Any,
+        }
+    }
+
+    /// Whether a value of type `self` might be acceptable where `expected` is wanted.
+    fn fits(&self, expected: &Ty) -> bool {
+        match (self, expected) {
+            (Ty::Any, _) | (_, Ty::Any) => true,
+            (x, Ty::Union(ys)) => ys.iter().any(|y| x.fits(y)),
+            (Ty::Union(xs), y) => xs.iter().any(|x| x.fits(y)),
+            (Ty::None, Ty::None) => true,
+            (Ty::Name(x), Ty::Name(y)) => x == y,
+            (Ty::List(x), Ty::List(y)) => x.fits(y),
+            (Ty::Dict(box (k1, v1)), Ty::Dict(box (k2, v2))) => k1.fits(k2) && v1.fits(v2),
+            (Ty::Tuple(xs), Ty::Tuple(ys)) => {
+                xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| x.fits(y))
+            }
+            (Ty::List(_), Ty::Name(x)) | (Ty::Name(x), Ty::List(_)) => x == "list",
+            (Ty::Dict(_), Ty::Name(x)) | (Ty::Name(x), Ty::Dict(_)) => x == "dict",
+            (Ty::Tuple(_), Ty::Name(x)) | (Ty::Name(x), Ty::Tuple(_)) => x == "tuple",
+            _ => false,
+        }
+    }
+
+    /// The name of the type whose attributes we know, if the type is one of those.
+    fn type_name(&self) -> Option<&str> {
+        match self {
+            Ty::None => Some("NoneType"),
+            Ty::Name(x) => Some(x),
+            Ty::List(_) => Some("list"),
+            Ty::Dict(_) => Some("dict"),
+            Ty::Tuple(_) => Some("tuple"),
+            _ => None,
+        }
+    }
+}
+
+/// The methods of the builtin types, for those types where we know all the attributes.
+static METHODS: Lazy<HashMap<&'static str, HashMap<String, Signature>>> = Lazy::new(|| {
+    fn methods(methods: Option<&'static Methods>) -> HashMap<String, Signature> {
+        let mut res = HashMap::new();
+        for (name, value) in methods.into_iter().flat_map(|x| x.members()) {
+            if let Some(DocItem::Function(f)) = value.to_value().documentation() {
+                res.insert(name.to_owned(), Signature::from_docs(&f));
+            }
+        }
+        res
+    }
+
+    let mut res = HashMap::new();
+    res.insert("string", methods(StarlarkStr::get_methods()));
+    res.insert("list", methods(list_methods()));
+    res.insert("dict", methods(dict_methods()));
+    for x in ["int", "float", "bool", "tuple", "NoneType"] {
+        res.insert(x, HashMap::new());
+    }
+    res
+});
+
+/// What a name may be bound to in a scope.
+enum Binding<'a> {
+    /// Assigned the value of an expression.
+    Expr(&'a AstExpr),
+    Type(Ty),
+}
+
+struct Scope<'a> {
+    /// Unique among the scopes of a module, for caching.
+    id: usize,
+    names: HashMap<&'a str, Vec<Binding<'a>>>,
+}
+
+impl<'a> Scope<'a> {
+    fn bind(&mut self, name: &'a str, binding: Binding<'a>) {
+        self.names.entry(name).or_default().push(binding)
+    }
+
+    fn bind_lvalue(&mut self, x: &'a AstAssign) {
+        x.visit_lvalue(|x| self.bind(&x.0, Binding::Type(Ty::Any)))
+    }
+
+    /// Bind the names assigned by statements in this scope, not including nested `def`s.
+    fn bind_stmt(&mut self, x: &'a AstStmt) {
+        match &**x {
+            Stmt::Assign(lhs, rhs) => match &**lhs {
+                Assign::Identifier(x) => self.bind(&x.0, Binding::Expr(rhs)),
+                _ => self.bind_lvalue(lhs),
+            },
+            Stmt::AssignModify(lhs, _, _) => self.bind_lvalue(lhs),
+            Stmt::For(var, box (_, body)) => {
+                self.bind_lvalue(var);
+                self.bind_stmt(body);
+            }
+            Stmt::Def(name, ..) => self.bind(&name.0, Binding::Type(Ty::name("function"))),
+            Stmt::Load(load) => {
+                for (x, _) in &load.args {
+                    self.bind(&x.0, Binding::Type(Ty::Any))
+                }
+            }
+            _ => x.visit_stmt(|x| self.bind_stmt(x)),
+        }
+    }
+
+    fn bind_params(&mut self, params: &'a [AstParameter]) {
+        for p in params {
+            let (name, typ) = match &p.node {
+                Parameter::Normal(name, typ) => {
+                    (name, typ.as_ref().map_or(Ty::Any, |x| Ty::from_expr(x)))
+                }
+                Parameter::WithDefaultValue(name, typ, default) => {
+                    let typ = typ.as_ref().map_or(Ty::Any, |x| Ty::from_expr(x));
+                    match &***default {
+                        Expr::Identifier(x, _) if x.node == "None" => (name, typ.union(Ty::None)),
+                        _ => (name, typ),
+                    }
+                }
+                Parameter::NoArgs => continue,
+                Parameter::Args(name, _) => (name, Ty::name("tuple")),
+                Parameter::KwArgs(name, _) => (name, Ty::name("dict")),
+            };
+            self.bind(&name.0, Binding::Type(typ))
+        }
+    }
+}
+
+struct Checker<'a> {
+    codemap: &'a CodeMap,
+    callees: Callees<'a>,
+    scopes: Vec<Scope<'a>>,
+    next_id: usize,
+    /// The types of names already inferred, by scope id.
+    cache: RefCell<HashMap<(usize, &'a str), Ty>>,
+    /// Names whose type is being inferred, to cut off recursion.
+    inferring: RefCell<HashSet<(usize, &'a str)>>,
+    res: Vec<LintT<TypeWarning>>,
+}
+
+impl<'a> Checker<'a> {
+    fn push(&mut self) -> &mut Scope<'a> {
+        self.next_id += 1;
+        self.scopes.push(Scope {
+            id: self.next_id,
+            names: HashMap::new(),
+        });
+        self.scopes.last_mut().unwrap()
+    }
+
+    /// The type of the name, as seen from the innermost `depth` scopes.
+    fn lookup(&self, name: &'a str, depth: usize) -> Ty {
+        let i = match (0..depth)
+            .rev()
+            .find(|i| self.scopes[*i].names.contains_key(name))
+        {
+            Some(i) => i,
+            None => {
+                return match name {
+                    "True" | "False" => Ty::name("bool"),
+                    "None" => Ty::None,
+                    _ => Ty::Any,
+                };
+            }
+        };
+        let scope = &self.scopes[i];
+        let key = (scope.id, name);
+        if let Some(x) = self.cache.borrow().get(&key) {
+            return x.clone();
+        }
+        if !self.inferring.borrow_mut().insert(key) {
+            return Ty::Any;
+        }
+        let res = Ty::unions(scope.names[name].iter().map(|x| match x {
+            Binding::Expr(x) => self.infer(x, i + 1),
+            Binding::Type(x) => x.clone(),
+        }))
+        .unwrap_or_default();
+        self.inferring.borrow_mut().remove(&key);
+        self.cache.borrow_mut().insert(key, res.clone());
+        res
+    }
+
+    /// Find the signature of a called function, with a name for it.
+    fn callee(&self, f: &'a AstExpr, depth: usize) -> Option<(String, &Signature)> {
+        match &**f {
+            Expr::Identifier(name, _) => {
+                // Local variables aren't functions we know about.
+                if self.scopes[1..depth]
+                    .iter()
+                    .any(|x| x.names.contains_key(name.as_str()))
+                {
+                    return None;
+                }
+                Some((name.node.clone(), self.callees.resolve(&name.node)?))
+            }
+            Expr::Dot(x, attr) => {
+                let typ = self.infer(x, depth);
+                let name = typ.type_name()?;
+                let sig = METHODS.get(name)?.get(&attr.node)?;
+                Some((format!("{}.{}", name, attr.node), sig))
+            }
+            _ => None,
+        }
+    }
+
+    fn infer(&self, x: &'a AstExpr, depth: usize) -> Ty {
+        let number = |x: &Ty| matches!(x, Ty::Name(x) if x == "int" || x == "float");
+        match &**x {
+            Expr::Literal(AstLiteral::Int(_)) => Ty::name("int"),
+            Expr::Literal(AstLiteral::Float(_)) => Ty::name("float"),
+            Expr::Literal(AstLiteral::String(_)) => Ty::name("string"),
+            Expr::Identifier(name, _) => self.lookup(name, depth),
+            Expr::List(xs) => Ty::List(
+                box Ty::unions(xs.iter().map(|x| self.infer(x, depth))).unwrap_or_default(),
+            ),
+            Expr::Dict(xs) => Ty::Dict(box (
+                Ty::unions(xs.iter().map(|x| self.infer(&x.0, depth))).unwrap_or_default(),
+                Ty::unions(xs.iter().map(|x| self.infer(&x.1, depth))).unwrap_or_default(),
+            )),
+            Expr::Tuple(xs) => Ty::Tuple(xs.map(|x| self.infer(x, depth))),
+            Expr::ListComprehension(..) => Ty::List(box Ty::Any),
+            Expr::DictComprehension(..) => Ty::Dict(box (Ty::Any, Ty::Any)),
+            Expr::Lambda(..) => Ty::name("function"),
+            Expr::Not(_) => Ty::name("bool"),
+            Expr::Minus(x) | Expr::Plus(x) => {
+                let x = self.infer(x, depth);
+                if number(&x) { x } else { Ty::Any }
+            }
+            Expr::If(box (_, a, b)) => self.infer(a, depth).union(self.infer(b, depth)),
+            Expr::Call(f, _) => self
+                .callee(f, depth)
+                .map_or(Ty::Any, |(_, x)| x.ret.clone()),
+            Expr::ArrayIndirection(box (x, _)) => match self.infer(x, depth) {
+                Ty::List(x) => *x,
+                Ty::Dict(box (_, v)) => v,
+                Ty::Name(x) if x == "string" => Ty::name("string"),
+                _ => Ty::Any,
+            },
+            Expr::Slice(x, ..) => match self.infer(x, depth) {
+                x @ (Ty::List(_) | Ty::Name(_))
+                    if x.type_name()
+                        .map_or(false, |x| x == "string" || x == "list") =>
+                {
+                    x
+                }
+                _ => Ty::Any,
+            },
+            Expr::Op(a, op, b) => {
+                let (a, b) = (self.infer(a, depth), self.infer(b, depth));
+                match op {
+                    BinOp::Equal
+                    | BinOp::NotEqual
+                    | BinOp::Less
+                    | BinOp::Greater
+                    | BinOp::LessOrEqual
+                    | BinOp::GreaterOrEqual
+                    | BinOp::In
+                    | BinOp::NotIn => Ty::name("bool"),
+                    BinOp::And | BinOp::Or => a.union(b),
+                    BinOp::Percent if a == Ty::name("string") => a,
+                    BinOp::Add
+                    | BinOp::Subtract
+                    | BinOp::Multiply
+                    | BinOp::FloorDivide
+                    | BinOp::Percent
+                        if number(&a) && number(&b) =>
+                    {
+                        if a == b {
+                            a
+                        } else {
+                            Ty::name("float")
+                        }
+                    }
+                    BinOp::Divide if number(&a) && number(&b) => Ty::name("float"),
+                    BinOp::Add => match (a, b) {
+                        (Ty::List(a), Ty::List(b)) => Ty::List(box a.union(*b)),
+                        (a, b) if a == b && a == Ty::name("string") => a,
+                        _ => Ty::Any,
+                    },
+                    _ => Ty::Any,
+                }
+            }
+            _ => Ty::Any,
+        }
+    }
+
+    fn check_call(&mut self, f: &'a AstExpr, args: &'a [AstArgument]) {
+        let depth = self.scopes.len();
+        let (name, sig) = match self.callee(f, depth) {
+            Some(x) => x,
+            None => return,
+        };
+        let mut position = 0;
+        let mut problems = Vec::new();
+        for x in args {
+            let param = sig.param(x, position);
+            if matches!(x.node, Argument::Positional(_)) {
+                position += 1;
+            }
+            if let Some(param) = param {
+                let typ = self.infer(x.expr(), depth);
+                let none = param.none_default && typ == Ty::None;
+                if !none && !typ.fits(&param.typ) {
+                    problems.push((
+                        x.span,
+                        TypeWarning::IncompatibleArgument(
+                            name.clone(),
+                            param.name.clone(),
+                            param.typ.clone(),
+                            typ,
+                        ),
+                    ));
+                }
+            }
+        }
+        for (span, x) in problems {
+            self.res.push(LintT::new(self.codemap, span, x));
+        }
+    }
+
+    fn check_attribute(&mut self, x: &'a AstExpr, attr: &'a AstString) {
+        let typ = self.infer(x, self.scopes.len());
+        let methods = match typ.type_name().and_then(|x| METHODS.get(x)) {
+            Some(x) => x,
+            None => return,
+        };
+        if !methods.contains_key(attr.as_str()) {
+            let suggestion =
+                did_you_mean(attr, methods.keys().map(|x| x.as_str())).map(str::to_owned);
+            self.res.push(LintT::new(
+                self.codemap,
+                attr.span,
+                TypeWarning::UnknownAttribute(typ, attr.node.clone(), suggestion),
+            ));
+        }
+    }
+
+    fn comprehension(
+        &mut self,
+        for_: &'a ForClause,
+        clauses: &'a [Clause],
+        body: impl FnOnce(&mut Self),
+    ) {
+        self.expr(&for_.over);
+        let scope = self.push();
+        scope.bind_lvalue(&for_.var);
+        for x in clauses {
+            if let Clause::For(x) = x {
+                scope.bind_lvalue(&x.var);
+            }
+        }
+        for x in clauses {
+            match x {
+                Clause::For(x) => self.expr(&x.over),
+                Clause::If(x) => self.expr(x),
+            }
+        }
+        body(self);
+        self.scopes.pop();
+    }
+
+    fn expr(&mut self, x: &'a AstExpr) {
+        match &**x {
+            Expr::Call(f, args) => self.check_call(f, args),
+            Expr::Dot(x, attr) => self.check_attribute(x, attr),
+            _ => {}
+        }
+        match &**x {
+            Expr::Lambda(params, body, _) => {
+                for p in params {
+                    if let (_, _, Some(default)) = p.split() {
+                        self.expr(default);
+                    }
+                }
+                self.push().bind_params(params);
+                self.expr(body);
+                self.scopes.pop();
+            }
+            Expr::ListComprehension(x, for_, clauses) => {
+                self.comprehension(for_, clauses, |s| s.expr(x))
+            }
+            Expr::DictComprehension(box (k, v), for_, clauses) => {
+                self.comprehension(for_, clauses, |s| {
+                    s.expr(k);
+                    s.expr(v);
+                })
+            }
+            _ => x.visit_expr(|x| self.expr(x)),
+        }
+    }
+
+    /// Check a statement, in a function with the given name and declared return type.
+    fn stmt(&mut self, x: &'a AstStmt, ret: Option<(&str, &Ty)>) {
+        match &**x {
+            Stmt::Def(name, params, typ, body, _) => {
+                for p in params {
+                    if let (_, _, Some(default)) = p.split() {
+                        self.expr(default);
+                    }
+                }
+                let scope = self.push();
+                scope.bind_params(params);
+                scope.bind_stmt(body);
+                let typ = typ.as_ref().map(|x| Ty::from_expr(x));
+                self.stmt(body, typ.as_ref().map(|x| (name.0.as_str(), x)));
+                self.scopes.pop();
+            }
+            // A `return` without a value is reported by the flow checks instead.
+            Stmt::Return(Some(value)) => {
+                self.expr(value);
+                if let Some((name, expected)) = ret {
+                    let typ = self.infer(value, self.scopes.len());
+                    if !typ.fits(expected) {
+                        self.res.push(LintT::new(
+                            self.codemap,
+                            x.span,
+                            TypeWarning::IncompatibleReturn(name.to_owned(), expected.clone(), typ),
+                        ));
+                    }
+                }
+            }
+            _ => x.visit_children(|x| match x {
+                Visit::Stmt(x) => self.stmt(x, ret),
+                Visit::Expr(x) => self.expr(x),
+            }),
+        }
+    }
+}
+
+pub(crate) fn type_warnings(
+    module: &AstModule,
+    signatures: Option<&Signatures>,
+) -> Vec<LintT<TypeWarning>> {
+    let empty = Signatures::default();
+    let mut checker = Checker {
+        codemap: &module.codemap,
+        callees: Callees::new(module, signatures.unwrap_or(&empty)),
+        scopes: Vec::new(),
+        next_id: 0,
+        cache: RefCell::new(HashMap::new()),
+        inferring: RefCell::new(HashSet::new()),
+        res: Vec::new(),
+    };
+    checker.push().bind_stmt(&module.statement);
+    checker.stmt(&module.statement, None);
+    checker.res
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::environment::Globals;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    fn ty(x: &str) -> Ty {
+        Ty::from_docs(Some(&docs::Type {
+            raw_type: x.to_owned(),
+        }))
+    }
+
+    #[test]
+    fn test_ty() {
+        assert_eq!(ty("int.type"), Ty::name("int"));
+        assert_eq!(ty("\"_a\""), Ty::Any);
+        assert_eq!(ty("[None, str.type]").to_string(), "None | string");
+        assert_eq!(
+            ty("{str.type: [int.type]}").to_string(),
+            "{string: [int] | tuple}"
+        );
+        assert!(Ty::name("int").union(Ty::None).fits(&Ty::name("int")));
+        assert!(!Ty::List(box Ty::name("int")).fits(&Ty::List(box Ty::name("string"))));
+        assert!(Ty::Tuple(vec![Ty::Any]).fits(&ty("[str.type]")));
+    }
+
+    #[test]
+    fn test_typecheck() {
+        let m = module(
+            r#"
+def add(x: int.type, y: int.type = 1) -> int.type:
+    if x > 10:
+        return "big"
+    return x + y
+def join(xs: [str.type], sep: str.type = None) -> str.type:
+    return (sep or ",").join(xs)
+add("a")
+add(1, y = None)
+join([1, 2], sep = None)
+join(range(3))
+s = "abc"
+s.uper()
+s.count(1)
+len(s).bit_length()
+def f(s):
+    s.foo()
+    add(s)
+[s.upper() for s in [1]]
+x = 1
+x = "a"
+add(x)
+"#,
+        );
+        let mut signatures = Signatures::new();
+        signatures.add_globals(&Globals::standard().member_documentation());
+        let res = type_warnings(&m, Some(&signatures));
+        assert_eq!(
+            res.map(|x| x.to_string()),
+            &[
+                "X:4:9-21: Function `add` should return `int`, but returns `string`",
+                "X:8:5-8: Argument `x` of `add` should be `int`, but got `string`",
+                "X:9:8-16: Argument `y` of `add` should be `int`, but got `None`",
+                "X:10:6-12: Argument `xs` of `join` should be `[string]`, but got `[int]`",
+                "X:11:6-14: Argument `xs` of `join` should be `[string]`, but got `range`",
+                "X:13:3-7: Type `string` has no attribute `uper`, did you mean `upper`?",
+                "X:14:9-10: Argument `needle` of `string.count` should be `string`, but got `int`",
+                "X:15:8-18: Type `int` has no attribute `bit_length`",
+            ]
+        );
+    }
+}
@@ -24,6 +24,8 @@ use lsp_types::Diagnostic;
 use lsp_types::DiagnosticSeverity;
 use lsp_types::NumberOrString;
 use lsp_types::Range;
+use lsp_types::TextEdit;
+use serde::Deserialize;
 use serde::Serialize;
 
 use crate::codemap::CodeMap;
@@ -42,6 +44,29 @@ pub(crate) struct LintT<T> {
     pub location: FileSpan,
     pub original: String,
     pub problem: T,
+    pub fixes: Vec<LintFix>,
+}
+
+/// A single textual replacement, part of a [`LintFix`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintEdit {
+    /// The region of the file to replace.
+    pub span: ResolvedSpan,
+    /// The text to put in its place, empty to delete.
+    pub replacement: String,
+}
+
+/// A machine-applicable fix for a [`Lint`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFix {
+    /// A short description of the fix, e.g. `Remove unused load of "x"`.
+    pub title: String,
+    /// The edits that make up this fix. They never overlap.
+    pub edits: Vec<LintEdit>,
+    /// Is the fix safe to apply without anyone looking at it, e.g. by `starlark --fix`.
+    /// Fixes which rename a parameter or function are not, since they may be used from
+    /// files the linter cannot see, nor are fixes which guess at what was meant.
+    pub safe: bool,
 }
 
 /// A lint produced by [`AstModule::lint`](crate::syntax::AstModule::lint).
@@ -54,10 +79,15 @@ pub struct Lint {
     /// Is this code highly-likely to be wrong, rather
     /// than merely stylistically non-ideal.
     pub serious: bool,
+    /// How severely to report the lint. Serious lints are warnings, and the rest are disabled,
+    /// unless a [`LintConfig`](crate::errors::LintConfig) says otherwise.
+    pub severity: EvalSeverity,
     /// A description of the underlying problem.
     pub problem: String,
     /// The source code at [`location`](Lint::location).
     pub original: String,
+    /// Ways to fix this lint automatically, possibly empty.
+    pub fixes: Vec<LintFix>,
 }
 
 impl Display for Lint {
@@ -79,22 +109,86 @@ impl<T: LintWarning> LintT<T> {
             original: location.file.source_span(span).to_owned(),
             location,
             problem,
+            fixes: Vec::new(),
         }
     }
 
+    /// Attach a fix, made up of edits within the same file as the lint, which is safe to
+    /// apply automatically.
+    pub(crate) fn with_fix(
+        self,
+        codemap: &CodeMap,
+        title: impl Into<String>,
+        edits: Vec<(Span, String)>,
+    ) -> Self {
+        self.push_fix(codemap, title.into(), edits, true)
+    }
+
+    /// Attach a fix which should only be applied once someone has looked at it,
+    /// see [`LintFix::safe`].
+    pub(crate) fn with_unsafe_fix(
+        self,
+        codemap: &CodeMap,
+        title: impl Into<String>,
+        edits: Vec<(Span, String)>,
+    ) -> Self {
+        self.push_fix(codemap, title.into(), edits, false)
+    }
+
+    fn push_fix(
+        mut self,
+        codemap: &CodeMap,
+        title: String,
+        edits: Vec<(Span, String)>,
+        safe: bool,
+    ) -> Self {
+        self.fixes.push(LintFix {
+            title,
+            edits: edits.into_map(|(span, replacement)| LintEdit {
+                span: codemap.resolve_span(span),
+                replacement,
+            }),
+            safe,
+        });
+        self
+    }
+
     pub(crate) fn erase(self) -> Lint {
         Lint {
             location: self.location,
             short_name: kebab(self.problem.variant_name()),
             serious: self.problem.is_serious(),
+            severity: if self.problem.is_serious() {
+                EvalSeverity::Warning
+            } else {
+                // Start with all non-serious errors disabled, and ramp up from there
+                EvalSeverity::Disabled
+            },
             problem: self.problem.to_string(),
             original: self.original,
+            fixes: self.fixes,
         }
     }
 }
 
+/// The edit which removes the statement at `span`. If the statement is the only thing on
+/// its lines they are deleted entirely, otherwise (or if the statement is the only one in
+/// its block, so cannot be dropped) it is replaced with `pass`.
+pub(crate) fn remove_statement(codemap: &CodeMap, span: Span, alone: bool) -> (Span, String) {
+    let lines = codemap
+        .line_span(codemap.find_line(span.begin()))
+        .merge(codemap.line_span(codemap.find_line(span.end())));
+    let before = codemap.source_span(Span::new(lines.begin(), span.begin()));
+    let after = codemap.source_span(Span::new(span.end(), lines.end()));
+    if !alone && before.trim().is_empty() && after.trim().is_empty() {
+        (lines, String::new())
+    } else {
+        (span, "pass".to_owned())
+    }
+}
+
 /// A standardised set of severities.
-#[derive(Debug, Serialize, Dupe, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, Dupe, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum EvalSeverity {
     /// An error while the program was being parsed.
@@ -138,6 +232,8 @@ pub struct EvalMessage {
     pub span: Option<ResolvedSpan>,
     /// How severed the problem is.
     pub severity: EvalSeverity,
+    /// Is the problem highly likely to be a bug, see [`Lint::serious`]. Always true for errors.
+    pub serious: bool,
     /// The general name of the issue.
     pub name: String,
     /// The details of the issue, generally displayed to the user.
@@ -146,6 +242,8 @@ pub struct EvalMessage {
     pub full_error_with_span: Option<String>,
     /// The text referred to by `.span`
     pub original: Option<String>,
+    /// Fixes that can be applied to resolve this problem.
+    pub fixes: Vec<LintFix>,
 }
 
 impl Display for EvalMessage {
@@ -175,20 +273,24 @@ impl EvalMessage {
                     path: span.filename().to_owned(),
                     span: Some(resolved_span),
                     severity: EvalSeverity::Error,
+                    serious: true,
                     name: "error".to_owned(),
                     description: format!("{:#}", message),
                     full_error_with_span: Some(d.to_string()),
                     original: Some(original),
+                    fixes: Vec::new(),
                 }
             }
             _ => Self {
                 path: file.to_owned(),
                 span: None,
                 severity: EvalSeverity::Error,
+                serious: true,
                 name: "error".to_owned(),
                 description: format!("{:#}", x),
                 full_error_with_span: None,
                 original: None,
+                fixes: Vec::new(),
             },
         }
     }
@@ -199,16 +301,32 @@ impl From<Lint> for EvalMessage {
         Self {
             path: x.location.filename().to_owned(),
             span: Some(x.location.resolve_span()),
-            severity: if x.serious {
-                EvalSeverity::Warning
-            } else {
-                // Start with all non-serious errors disabled, and ramp up from there
-                EvalSeverity::Disabled
-            },
+            severity: x.severity,
+            serious: x.serious,
             name: x.short_name,
             description: x.problem,
             full_error_with_span: None,
             original: Some(x.original),
+            fixes: x.fixes,
+        }
+    }
+}
+
+/// The form in which a [`LintFix`] travels in [`Diagnostic::data`], so that the LSP server
+/// can turn it into a code action when the client asks for one.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DiagnosticFix {
+    pub title: String,
+    pub edits: Vec<TextEdit>,
+}
+
+impl From<LintFix> for DiagnosticFix {
+    fn from(x: LintFix) -> Self {
+        Self {
+            title: x.title,
+            edits: x
+                .edits
+                .into_map(|e| TextEdit::new(e.span.into(), e.replacement)),
         }
     }
 }
@@ -219,7 +337,7 @@ impl From<EvalMessage> for Diagnostic {
             Some(s) => s.into(),
             _ => Range::default(),
         };
-        Diagnostic::new(
+        let mut diagnostic = Diagnostic::new(
             range,
             Some(x.severity.into()),
             Some(NumberOrString::String(x.name)),
@@ -227,7 +345,12 @@ impl From<EvalMessage> for Diagnostic {
             x.description,
             None,
             None,
-        )
+        );
+        if !x.fixes.is_empty() {
+            let fixes = x.fixes.into_map(DiagnosticFix::from);
+            diagnostic.data = Some(serde_json::to_value(fixes).unwrap());
+        }
+        diagnostic
     }
 }
 
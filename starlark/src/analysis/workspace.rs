@@ -0,0 +1,352 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Lints which need to see several files at once, following the `load` statements between them.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::iter;
+
+use gazebo::prelude::*;
+use gazebo::variants::VariantName;
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::analysis::types::LintT;
+use crate::analysis::types::LintWarning;
+use crate::errors::did_you_mean::did_you_mean;
+use crate::syntax::ast::AstLoad;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::Stmt;
+use crate::syntax::AstModule;
+
+#[derive(Error, Debug, VariantName)]
+pub(crate) enum WorkspaceWarning {
+    #[error("Load of `{0}` forms a cycle: {1}")]
+    LoadCycle(String, String),
+    #[error(
+        "Module `{1}` does not export `{0}`{}",
+        .2.as_ref().map_or_else(String::new, |x| format!(", did you mean `{}`?", x))
+    )]
+    UnresolvedLoadedSymbol(String, String, Option<String>),
+    #[error("Load of private symbol `{0}` from `{1}`")]
+    PrivateLoad(String, String),
+    #[error("Exported symbol `{0}` is not loaded by any file")]
+    UnusedExport(String),
+}
+
+impl LintWarning for WorkspaceWarning {
+    fn is_serious(&self) -> bool {
+        match self {
+            Self::UnusedExport(..) => false,
+            _ => true,
+        }
+    }
+}
+
+/// The `load` statements of a module, which must all be at the top level.
+fn module_loads(module: &AstModule) -> Vec<&AstLoad> {
+    fn f<'a>(x: &'a AstStmt, res: &mut Vec<&'a AstLoad>) {
+        match &**x {
+            Stmt::Load(load) => res.push(load),
+            Stmt::Statements(xs) => xs.iter().for_each(|x| f(x, res)),
+            _ => {}
+        }
+    }
+
+    let mut res = Vec::new();
+    f(&module.statement, &mut res);
+    res
+}
+
+/// The modules being analysed, with each load resolved to the index of the module it loads,
+/// if that module is one of them.
+struct Workspace<'a> {
+    modules: &'a [AstModule],
+    loads: Vec<Vec<(&'a AstLoad, Option<usize>)>>,
+}
+
+impl<'a> Workspace<'a> {
+    fn new(
+        modules: &'a [AstModule],
+        resolve_load: impl Fn(&str, &str) -> anyhow::Result<String>,
+    ) -> Self {
+        let index: HashMap<&str, usize> = modules
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (x.codemap.filename(), i))
+            .collect();
+        let loads = modules.map(|module| {
+            module_loads(module).into_map(|load| {
+                let target = resolve_load(&load.module.node, module.codemap.filename())
+                    .ok()
+                    .and_then(|x| index.get(x.as_str()).copied());
+                (load, target)
+            })
+        });
+        Self { modules, loads }
+    }
+
+    /// The symbols a module exports: its public top-level definitions, and,
+    /// if its dialect reexports loaded symbols, its public loads.
+    fn exports(&self, i: usize) -> HashSet<&'a str> {
+        let module = &self.modules[i];
+        let mut res: HashSet<&str> = module
+            .exported_symbols()
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect();
+        if module.dialect.enable_load_reexport {
+            for (load, _) in &self.loads[i] {
+                res.extend(
+                    load.args
+                        .iter()
+                        .map(|(local, _)| local.0.as_str())
+                        .filter(|x| !x.starts_with('_')),
+                );
+            }
+        }
+        res
+    }
+
+    fn load_cycles(&self, res: &mut Vec<LintT<WorkspaceWarning>>) {
+        #[derive(Clone, Copy, Dupe, PartialEq, Eq)]
+        enum State {
+            Unvisited,
+            Active,
+            Finished,
+        }
+
+        // Depth first search, reporting each edge back to a module on the stack,
+        // at the load which closes the cycle.
+        fn visit(
+            ws: &Workspace,
+            i: usize,
+            state: &mut Vec<State>,
+            stack: &mut Vec<usize>,
+            res: &mut Vec<LintT<WorkspaceWarning>>,
+        ) {
+            state[i] = State::Active;
+            stack.push(i);
+            for (load, target) in &ws.loads[i] {
+                let j = match target {
+                    Some(j) => *j,
+                    None => continue,
+                };
+                match state[j] {
+                    State::Unvisited => visit(ws, j, state, stack, res),
+                    State::Active => {
+                        let start = stack.iter().position(|x| *x == j).unwrap();
+                        let cycle = stack[start..]
+                            .iter()
+                            .chain(iter::once(&j))
+                            .map(|x| ws.modules[*x].codemap.filename())
+                            .join(" -> ");
+                        res.push(LintT::new(
+                            &ws.modules[i].codemap,
+                            load.module.span,
+                            WorkspaceWarning::LoadCycle(load.module.node.clone(), cycle),
+                        ));
+                    }
+                    State::Finished => {}
+                }
+            }
+            stack.pop();
+            state[i] = State::Finished;
+        }
+
+        let mut state = vec![State::Unvisited; self.modules.len()];
+        let mut stack = Vec::new();
+        for i in 0..self.modules.len() {
+            if state[i] == State::Unvisited {
+                visit(self, i, &mut state, &mut stack, res);
+            }
+        }
+    }
+
+    fn loaded_symbols(&self, res: &mut Vec<LintT<WorkspaceWarning>>) {
+        let exports = (0..self.modules.len())
+            .map(|i| self.exports(i))
+            .collect::<Vec<_>>();
+        for (module, loads) in self.modules.iter().zip(&self.loads) {
+            let codemap = &module.codemap;
+            for (load, target) in loads {
+                for (_, name) in &load.args {
+                    if name.starts_with('_') {
+                        res.push(LintT::new(
+                            codemap,
+                            name.span,
+                            WorkspaceWarning::PrivateLoad(
+                                name.node.clone(),
+                                load.module.node.clone(),
+                            ),
+                        ));
+                    } else if let Some(exports) = target.map(|j| &exports[j]) {
+                        if !exports.contains(name.node.as_str()) {
+                            let suggestion =
+                                did_you_mean(&name.node, exports.iter().copied().sorted())
+                                    .map(str::to_owned);
+                            let mut lint = LintT::new(
+                                codemap,
+                                name.span,
+                                WorkspaceWarning::UnresolvedLoadedSymbol(
+                                    name.node.clone(),
+                                    load.module.node.clone(),
+                                    suggestion.clone(),
+                                ),
+                            );
+                            if let Some(suggestion) = suggestion {
+                                let title =
+                                    format!("Replace `{}` with `{}`", name.node, suggestion);
+                                lint = lint.with_unsafe_fix(
+                                    codemap,
+                                    title,
+                                    vec![(name.span, format!("\"{}\"", suggestion))],
+                                );
+                            }
+                            res.push(lint);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Only modules which something loads are libraries, whose exports are meant to be used.
+    // The symbols defined by other modules, e.g. build files, are their results.
+    fn unused_exports(&self, res: &mut Vec<LintT<WorkspaceWarning>>) {
+        let mut loaded: Vec<Option<HashSet<&str>>> = vec![None; self.modules.len()];
+        for loads in &self.loads {
+            for (load, target) in loads {
+                if let Some(j) = target {
+                    loaded[*j]
+                        .get_or_insert_with(HashSet::new)
+                        .extend(load.args.iter().map(|(_, name)| name.node.as_str()));
+                }
+            }
+        }
+        for (module, loaded) in self.modules.iter().zip(&loaded) {
+            let loaded = match loaded {
+                Some(loaded) => loaded,
+                None => continue,
+            };
+            for (span, name) in module.exported_symbols() {
+                if !loaded.contains(name) {
+                    res.push(LintT::new(
+                        &module.codemap,
+                        span.span,
+                        WorkspaceWarning::UnusedExport(name.to_owned()),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Lint the `modules` together. `resolve_load` maps the path given to a `load` in the file
+/// with the given name to the filename of the module it loads.
+pub(crate) fn workspace_warnings(
+    modules: &[AstModule],
+    resolve_load: impl Fn(&str, &str) -> anyhow::Result<String>,
+) -> Vec<LintT<WorkspaceWarning>> {
+    let ws = Workspace::new(modules, resolve_load);
+    let mut res = Vec::new();
+    ws.load_cycles(&mut res);
+    ws.loaded_symbols(&mut res);
+    ws.unused_exports(&mut res);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn modules(files: &[(&str, &str)], dialect: &Dialect) -> Vec<AstModule> {
+        files.map(|(name, x)| AstModule::parse(name, (*x).to_owned(), dialect).unwrap())
+    }
+
+    fn warnings(modules: &[AstModule]) -> Vec<String> {
+        workspace_warnings(modules, |path, _| Ok(path.to_owned()))
+            .map(|x| format!("{}: {}", x.problem.variant_name(), x))
+    }
+
+    #[test]
+    fn test_lint_workspace() {
+        let ms = modules(
+            &[
+                (
+                    "a.star",
+                    r#"
+load("b.star", "b", "_private", "mising")
+load("unknown.star", "x")
+a = b + x
+"#,
+                ),
+                (
+                    "b.star",
+                    r#"
+load("c.star", "c")
+b = c
+missing = 1
+_private = 2
+"#,
+                ),
+                (
+                    "c.star",
+                    r#"
+load("b.star", "b")
+c = 1
+unused = 2
+"#,
+                ),
+            ],
+            &Dialect::Standard,
+        );
+        assert_eq!(
+            warnings(&ms),
+            &[
+                "LoadCycle: c.star:2:6-14: Load of `b.star` forms a cycle: b.star -> c.star -> b.star",
+                "PrivateLoad: a.star:2:21-31: Load of private symbol `_private` from `b.star`",
+                "UnresolvedLoadedSymbol: a.star:2:33-41: Module `b.star` does not export `mising`, did you mean `missing`?",
+                "UnusedExport: b.star:4:1-8: Exported symbol `missing` is not loaded by any file",
+                "UnusedExport: c.star:4:1-7: Exported symbol `unused` is not loaded by any file",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_workspace_reexport() {
+        let files = [
+            ("a.star", "load('b.star', 'c')\n"),
+            ("b.star", "load('c.star', 'c')\n"),
+            ("c.star", "c = 1\n"),
+        ];
+        assert_eq!(
+            warnings(&modules(&files, &Dialect::Extended)),
+            Vec::<String>::new()
+        );
+        let mut no_reexport = Dialect::Extended;
+        no_reexport.enable_load_reexport = false;
+        assert_eq!(
+            warnings(&modules(&files, &no_reexport)),
+            &["UnresolvedLoadedSymbol: a.star:1:16-19: Module `b.star` does not export `c`"]
+        );
+    }
+}
@@ -15,30 +15,48 @@
  * limitations under the License.
  */
 
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+
 use debugserver_types::*;
 use gazebo::prelude::*;
 use serde::Serialize;
 use serde_json::Value;
 
-use crate::dap::library::stream::log;
-use crate::dap::library::stream::send;
+use crate::debug::dap::stream::send;
 
-#[derive(Debug, Clone, Dupe)]
+/// Sends messages to the client, from whichever thread has something to say.
+#[derive(Clone, Dupe)]
 pub(crate) struct Client {
-    _private: (),
+    output: Arc<Mutex<Box<dyn Write + Send>>>,
+    log: Arc<dyn Fn(&str) + Send + Sync>,
 }
 
 impl Client {
-    pub(crate) fn new() -> Self {
-        Self { _private: () }
+    pub(crate) fn new(output: Box<dyn Write + Send>, log: Arc<dyn Fn(&str) + Send + Sync>) -> Self {
+        Self {
+            output: Arc::new(Mutex::new(output)),
+            log,
+        }
     }
 
     pub(crate) fn log(&self, x: &str) {
-        log(x)
+        (self.log)(x)
+    }
+
+    pub(crate) fn send(&self, x: impl Serialize) -> anyhow::Result<()> {
+        let s = serde_json::to_string(&x)?;
+        self.log(&format!("SEND: {}", s));
+        send(&mut **self.output.lock().unwrap(), &s)
     }
 
     fn event(&self, x: impl Serialize) {
-        send(serde_json::to_value(&x).unwrap())
+        // Events are sent from the evaluating thread, which has nobody to report to
+        // if the client has gone away.
+        if let Err(e) = self.send(x) {
+            self.log(&format!("Failed to send event: {:#}", e));
+        }
     }
 
     pub(crate) fn event_stopped(&self, body: StoppedEventBody) {
@@ -50,6 +68,24 @@ impl Client {
         })
     }
 
+    pub(crate) fn event_thread(&self, body: ThreadEventBody) {
+        self.event(ThreadEvent {
+            type_: "event".to_owned(),
+            seq: 0,
+            event: "thread".to_owned(),
+            body,
+        })
+    }
+
+    pub(crate) fn event_capabilities(&self, body: CapabilitiesEventBody) {
+        self.event(CapabilitiesEvent {
+            type_: "event".to_owned(),
+            seq: 0,
+            event: "capabilities".to_owned(),
+            body,
+        })
+    }
+
     pub(crate) fn event_initialized(&self, body: Option<Value>) {
         self.event(InitializedEvent {
             type_: "event".to_owned(),
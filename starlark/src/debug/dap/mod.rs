@@ -0,0 +1,1571 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! A debugger for Starlark programs, which talks to IDEs using the Debug Adapter Protocol
+//! <https://microsoft.github.io/debug-adapter-protocol/specification>.
+//!
+//! The program embedding Starlark provides a [`DapContext`], which evaluates the code being
+//! debugged in its own environment, and calls [`server`] to talk to the client.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+
+use debugserver_types::*;
+use gazebo::prelude::*;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::codemap::FileSpan;
+use crate::codemap::FileSpanRef;
+use crate::codemap::ResolvedSpan;
+use crate::debug::dap::events::Client;
+use crate::debug::dap::replay::trace_path;
+use crate::debug::dap::replay::Replay;
+use crate::debug::dap::requests::dispatch;
+use crate::debug::dap::requests::DebugServer;
+use crate::debug::dap::stream::read;
+use crate::debug::dap::variables::preview;
+use crate::debug::dap::variables::set_variable;
+use crate::debug::dap::variables::variables;
+use crate::debug::dap::variables::ScopeKind;
+use crate::debug::dap::variables::VariablePath;
+use crate::debug::dap::variables::VariableReferences;
+use crate::environment::Globals;
+use crate::errors::Diagnostic;
+use crate::eval::Evaluator;
+use crate::stdlib::PrintHandler;
+use crate::syntax::AstModule;
+use crate::syntax::Dialect;
+
+mod events;
+mod replay;
+mod requests;
+mod stream;
+pub(crate) mod variables;
+
+/// Exception breakpoint filter which pauses on every error.
+const FILTER_ALL_ERRORS: &str = "all";
+/// Exception breakpoint filter which pauses on calls to `fail`.
+const FILTER_FAIL: &str = "fail";
+
+/// What the debugger needs from the program embedding it.
+pub trait DapContext: Send + Sync + 'static {
+    /// Evaluate the program described by the arguments of the `launch` request, returning
+    /// a description of its result to show the user. The program, and every module it loads,
+    /// must be evaluated with [`Debuggee::eval_module`], or [`DebuggeeThread::eval_module`]
+    /// for modules evaluated concurrently, so the debugger can pause in them.
+    ///
+    /// Called on a different thread to the one answering requests.
+    fn launch(&self, args: &Map<String, Value>, debuggee: &Debuggee) -> anyhow::Result<String>;
+
+    /// Parse a file, to find the statements breakpoints can be set on.
+    fn parse_file(&self, path: &Path) -> anyhow::Result<AstModule> {
+        AstModule::parse_file(path, &Dialect::Extended)
+    }
+
+    /// Record a message sent or received, or other progress of the debugger.
+    /// Useful when debugging the debugger itself, as clients tend to hide problems.
+    /// By default, nothing is recorded.
+    fn log(&self, _message: &str) {}
+}
+
+type Injected = Box<dyn Fn(FileSpanRef, &mut Evaluator) -> Next + Send>;
+
+struct Backend<C: DapContext> {
+    client: Client,
+    context: Arc<C>,
+    // The arguments of the `launch` request, which is run once the client has configured us.
+    launch: Mutex<Option<Map<String, Value>>>,
+
+    // These breakpoints must all match statements as per before_stmt.
+    // Those values for which we abort the execution. We store resolved spans,
+    // since the file is parsed again when executed, and `FileSpan` compares
+    // by the identity of the parsed file.
+    breakpoints: Arc<Mutex<HashMap<String, HashMap<ResolvedSpan, BreakpointConfig>>>>,
+    // The exception breakpoint filters which are enabled, which pause where an error is raised.
+    exception_filters: Arc<Mutex<HashSet<String>>>,
+    // The variable references we have given out while paused.
+    variables: Mutex<VariableReferences>,
+    // The threads of the program, which we send requests to while they are paused.
+    threads: Arc<Mutex<Threads>>,
+}
+
+/// When to pause at a breakpoint, as given by the `SourceBreakpoint`.
+#[derive(Debug)]
+struct BreakpointConfig {
+    /// Only pause when this expression is true.
+    condition: Option<String>,
+    /// Only pause when the number of hits satisfies this.
+    hit_condition: Option<HitCondition>,
+    /// Output this message, interpolating `{expr}`, instead of pausing.
+    log_message: Option<String>,
+    /// How many times the statement was reached with the condition true.
+    hits: usize,
+}
+
+/// A `hitCondition` such as `3`, `>= 3` or `% 3`. A bare number pauses from that hit onwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HitCondition {
+    Equal(usize),
+    Greater(usize),
+    GreaterOrEqual(usize),
+    Less(usize),
+    LessOrEqual(usize),
+    Multiple(usize),
+}
+
+impl HitCondition {
+    fn parse(x: &str) -> anyhow::Result<Self> {
+        let x = x.trim();
+        let ops: [(&str, fn(usize) -> Self); 7] = [
+            (">=", Self::GreaterOrEqual),
+            ("<=", Self::LessOrEqual),
+            ("==", Self::Equal),
+            (">", Self::Greater),
+            ("<", Self::Less),
+            ("%", Self::Multiple),
+            ("", Self::GreaterOrEqual),
+        ];
+        let (op, n) = ops
+            .iter()
+            .find_map(|(prefix, op)| Some((op, x.strip_prefix(prefix)?)))
+            .unwrap();
+        match n.trim().parse() {
+            Ok(0) if x.starts_with('%') => {
+                Err(anyhow::anyhow!("Hit condition `{}` divides by zero", x))
+            }
+            Ok(n) => Ok(op(n)),
+            Err(_) => Err(anyhow::anyhow!(
+                "Hit condition `{}` should be a number, optionally preceded by one of `>=`, `<=`, `==`, `>`, `<` or `%`",
+                x
+            )),
+        }
+    }
+
+    fn matches(self, hits: usize) -> bool {
+        match self {
+            Self::Equal(n) => hits == n,
+            Self::Greater(n) => hits > n,
+            Self::GreaterOrEqual(n) => hits >= n,
+            Self::Less(n) => hits < n,
+            Self::LessOrEqual(n) => hits <= n,
+            Self::Multiple(n) => hits % n == 0,
+        }
+    }
+}
+
+/// Replace each `{expr}` in a log message with the result of `eval(expr)`.
+/// Use `{{` and `}}` for literal braces.
+fn interpolate(message: &str, mut eval: impl FnMut(&str) -> String) -> String {
+    let mut res = String::new();
+    let mut rest = message;
+    while let Some(i) = rest.find(['{', '}']) {
+        res.push_str(&rest[..i]);
+        let brace = &rest[i..i + 1];
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix(brace) {
+            res.push_str(brace);
+            rest = after;
+        } else if brace == "{" {
+            match rest.find('}') {
+                Some(j) => {
+                    res.push_str(&eval(&rest[..j]));
+                    rest = &rest[j + 1..];
+                }
+                None => {
+                    res.push('{');
+                }
+            }
+        } else {
+            res.push('}');
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+/// Where to evaluate an expression.
+#[derive(Debug, Clone, Copy)]
+enum EvalIn {
+    /// The scope of the paused statement.
+    Paused,
+    /// The `n`-th frame from the top of the stack.
+    Frame(usize),
+    /// The `n`-th frame from the top of the stack, keeping any variables defined.
+    Repl(usize),
+}
+
+/// Evaluate `expr`. We don't want to trigger breakpoints during an evaluate,
+/// not least because we don't allow reentrant evaluate, so this must only be called
+/// within [`ThreadState::evaluating`].
+fn evaluate<'v, T>(
+    eval: &mut Evaluator<'v, '_>,
+    expr: &str,
+    scope: EvalIn,
+    f: impl FnOnce(crate::values::Value<'v>) -> T,
+) -> anyhow::Result<T> {
+    let ast = AstModule::parse("interactive", expr.to_owned(), &Dialect::Extended);
+    ast.and_then(|ast| match scope {
+        EvalIn::Paused => eval.eval_statements(ast),
+        EvalIn::Frame(n) => eval.eval_statements_at_frame(ast, n),
+        EvalIn::Repl(n) => eval.eval_statements_at_frame_for_repl(ast, n),
+    })
+    .map(f)
+}
+
+/// An `output` event, shown at `location` if known.
+fn output_event(output: String, category: &str, location: Option<FileSpan>) -> OutputEventBody {
+    let mut res = OutputEventBody {
+        output,
+        category: Some(category.to_owned()),
+        column: None,
+        data: None,
+        line: None,
+        source: None,
+        variables_reference: None,
+    };
+    if let Some(location) = location {
+        let span = location.resolve_span();
+        res.line = Some(span.begin_line as i64 + 1);
+        res.column = Some(span.begin_column as i64 + 1);
+        res.source = Some(Source {
+            path: Some(location.filename().to_owned()),
+            ..Source::default()
+        });
+    }
+    res
+}
+
+/// How far to run before pausing again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    /// Pause at the next statement, even if it is in a function being called.
+    Into,
+    /// Pause at the next statement in the current function or one of its callers.
+    Over,
+    /// Pause at the next statement in a caller of the current function.
+    Out,
+}
+
+impl Step {
+    /// Whether to pause at a statement `depth` frames deep, having started stepping
+    /// `from` frames deep.
+    fn stops(self, from: usize, depth: usize) -> bool {
+        match self {
+            Step::Into => true,
+            Step::Over => depth <= from,
+            Step::Out => depth < from,
+        }
+    }
+}
+
+enum Next {
+    Continue,
+    Step(Step),
+    RemainPaused,
+}
+
+/// Frame ids encode the thread, as the client refers to a frame by its id alone.
+const FRAMES_PER_THREAD: i64 = 1 << 16;
+
+/// The id of the `n`-th frame from the top of the stack of a thread.
+fn frame_id(thread: i64, n: usize) -> i64 {
+    thread * FRAMES_PER_THREAD + n as i64
+}
+
+/// The thread, and the `n` of the frame, a frame id refers to.
+fn from_frame_id(id: i64) -> (i64, usize) {
+    (id / FRAMES_PER_THREAD, (id % FRAMES_PER_THREAD) as usize)
+}
+
+/// A thread of the program, which runs what we send it while it is paused.
+struct ThreadHandle {
+    name: String,
+    sender: Sender<Injected>,
+    paused: bool,
+}
+
+/// The threads of the program, one for each evaluation in progress.
+#[derive(Default)]
+struct Threads {
+    threads: BTreeMap<i64, ThreadHandle>,
+    next_id: i64,
+    /// The thread which paused most recently, for requests which don't say which thread.
+    last_paused: Option<i64>,
+}
+
+/// What the threads of the running program share with the server.
+struct Shared {
+    client: Client,
+    breakpoints: Arc<Mutex<HashMap<String, HashMap<ResolvedSpan, BreakpointConfig>>>>,
+    exception_filters: Arc<Mutex<HashSet<String>>>,
+    threads: Arc<Mutex<Threads>>,
+}
+
+/// The state of a thread of the running program, shared by the `before_stmt` callbacks
+/// of the modules it evaluates, and every module they load.
+struct ThreadState {
+    shared: Arc<Shared>,
+    id: i64,
+    receiver: Mutex<Receiver<Injected>>,
+    /// How many modules are currently being evaluated. Each load runs in its own
+    /// evaluator, so counts as one frame deeper than the module which loads it.
+    modules: AtomicUsize,
+    /// The step in progress, and the depth it started from.
+    stepping: Mutex<Option<(Step, usize)>>,
+    /// Set while this thread evaluates expressions, for the client or for a breakpoint,
+    /// during which it doesn't pause (>= 1 means disable). Other threads still pause.
+    evaluating: AtomicUsize,
+}
+
+/// The program being debugged, which [`DapContext::launch`] evaluates modules through.
+pub struct Debuggee {
+    shared: Arc<Shared>,
+    main: DebuggeeThread,
+}
+
+impl Debuggee {
+    fn new(shared: Shared) -> Self {
+        let shared = Arc::new(shared);
+        let main = DebuggeeThread::new(shared.dupe(), "main");
+        Self { shared, main }
+    }
+
+    /// Evaluate a module with the debugger attached, so it pauses at breakpoints in the
+    /// module, and in the functions it calls which were evaluated the same way.
+    /// Anything the module prints is sent to the client, rather than the print handler
+    /// of the evaluator.
+    ///
+    /// The module is evaluated on the main thread, so modules evaluated at the same time
+    /// as this one, other than the ones it loads, must use a [`thread`](Debuggee::thread).
+    pub fn eval_module<'v, 'a>(
+        &'a self,
+        ast: AstModule,
+        globals: &Globals,
+        eval: &mut Evaluator<'v, 'a>,
+    ) -> anyhow::Result<crate::values::Value<'v>> {
+        self.main.eval_module(ast, globals, eval)
+    }
+
+    /// Start a thread for evaluating modules at the same time as other threads, such as
+    /// loads the program performs in parallel. The client shows each thread separately,
+    /// and they pause independently. The thread ends when it is dropped.
+    pub fn thread(&self, name: &str) -> DebuggeeThread {
+        DebuggeeThread::new(self.shared.dupe(), name)
+    }
+}
+
+/// A thread of the program being debugged, created by [`Debuggee::thread`].
+pub struct DebuggeeThread {
+    state: Arc<ThreadState>,
+    // Boxed, so that a reference lives as long as the thread, whatever the evaluator.
+    before_stmt: Box<dyn for<'v, 'a> Fn(FileSpanRef, &mut Evaluator<'v, 'a>) + Send + Sync>,
+    on_error:
+        Box<dyn for<'v, 'a> Fn(&anyhow::Error, FileSpanRef, &mut Evaluator<'v, 'a>) + Send + Sync>,
+}
+
+impl DebuggeeThread {
+    fn new(shared: Arc<Shared>, name: &str) -> Self {
+        let (sender, receiver) = channel();
+        let id = {
+            let mut threads = shared.threads.lock().unwrap();
+            let id = threads.next_id;
+            threads.next_id += 1;
+            threads.threads.insert(
+                id,
+                ThreadHandle {
+                    name: name.to_owned(),
+                    sender,
+                    paused: false,
+                },
+            );
+            id
+        };
+        shared.client.event_thread(ThreadEventBody {
+            reason: "started".to_owned(),
+            thread_id: id,
+        });
+        let state = Arc::new(ThreadState {
+            shared,
+            id,
+            receiver: Mutex::new(receiver),
+            modules: AtomicUsize::new(0),
+            stepping: Mutex::new(None),
+            evaluating: AtomicUsize::new(0),
+        });
+        let before_stmt = {
+            let state = state.dupe();
+            box move |span_loc: FileSpanRef, eval: &mut Evaluator| state.before_stmt(span_loc, eval)
+        };
+        let on_error = {
+            let state = state.dupe();
+            box move |e: &anyhow::Error, span_loc: FileSpanRef, eval: &mut Evaluator| {
+                state.on_error(e, span_loc, eval)
+            }
+        };
+        Self {
+            state,
+            before_stmt,
+            on_error,
+        }
+    }
+
+    /// Evaluate a module on this thread, as [`Debuggee::eval_module`] does on the main thread.
+    pub fn eval_module<'v, 'a>(
+        &'a self,
+        ast: AstModule,
+        globals: &Globals,
+        eval: &mut Evaluator<'v, 'a>,
+    ) -> anyhow::Result<crate::values::Value<'v>> {
+        // Attach once, even if the evaluator is used for several modules.
+        if eval.on_error(&self.on_error) {
+            eval.before_stmt(&self.before_stmt);
+            eval.set_print_handler(&*self.state);
+        }
+        let modules = &self.state.modules;
+        modules.fetch_add(1, Ordering::SeqCst);
+        let res = eval.eval_module(ast, globals);
+        modules.fetch_sub(1, Ordering::SeqCst);
+        res
+    }
+}
+
+impl Drop for DebuggeeThread {
+    fn drop(&mut self) {
+        let shared = &self.state.shared;
+        shared
+            .threads
+            .lock()
+            .unwrap()
+            .threads
+            .remove(&self.state.id);
+        shared.client.event_thread(ThreadEventBody {
+            reason: "exited".to_owned(),
+            thread_id: self.state.id,
+        });
+    }
+}
+
+impl PrintHandler for ThreadState {
+    fn println(&self, text: &str) -> anyhow::Result<()> {
+        self.println_at(text, None)
+    }
+
+    fn println_at(&self, text: &str, location: Option<FileSpan>) -> anyhow::Result<()> {
+        self.shared
+            .client
+            .event_output(output_event(format!("{}\n", text), "stdout", location));
+        Ok(())
+    }
+}
+
+impl ThreadState {
+    fn depth(&self, eval: &Evaluator) -> usize {
+        self.modules.load(Ordering::SeqCst).saturating_sub(1)
+            + eval.call_stack().into_frames().len()
+    }
+
+    /// Run `f`, which evaluates expressions, without pausing this thread.
+    fn evaluating<T>(&self, f: impl FnOnce() -> T) -> T {
+        self.evaluating.fetch_add(1, Ordering::SeqCst);
+        let res = f();
+        self.evaluating.fetch_sub(1, Ordering::SeqCst);
+        res
+    }
+
+    fn before_stmt(&self, span_loc: FileSpanRef, eval: &mut Evaluator) {
+        if self.evaluating.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+        let breakpoint = self.breakpoint(span_loc, eval);
+        let reason = if breakpoint {
+            "breakpoint"
+        } else {
+            let stepping = *self.stepping.lock().unwrap();
+            match stepping {
+                Some((step, from)) if step.stops(from, self.depth(eval)) => "step",
+                _ => return,
+            }
+        };
+        self.pause(reason, None, span_loc, eval);
+    }
+
+    fn on_error(&self, e: &anyhow::Error, span_loc: FileSpanRef, eval: &mut Evaluator) {
+        if self.evaluating.load(Ordering::SeqCst) > 0 {
+            return;
+        }
+        let diagnostic = e.downcast_ref::<Diagnostic>();
+        // The error is raised by the statement calling `fail`, so `fail` is the innermost
+        // frame of the call stack recorded on the error.
+        let is_fail = diagnostic.map_or(false, |d| {
+            d.call_stack
+                .clone()
+                .into_frames()
+                .last()
+                .map_or(false, |x| x.name == "fail")
+        });
+        let stop = {
+            let filters = self.shared.exception_filters.lock().unwrap();
+            filters.contains(FILTER_ALL_ERRORS) || (is_fail && filters.contains(FILTER_FAIL))
+        };
+        if stop {
+            let message = match diagnostic {
+                Some(d) => format!("{:#}", d.message),
+                None => format!("{:#}", e),
+            };
+            self.pause("exception", Some(message), span_loc, eval);
+        }
+    }
+
+    /// Tell the client we have paused, then run what it asks until it tells us to resume.
+    fn pause(
+        &self,
+        reason: &str,
+        text: Option<String>,
+        span_loc: FileSpanRef,
+        eval: &mut Evaluator,
+    ) {
+        *self.stepping.lock().unwrap() = None;
+        self.set_paused(true);
+        self.shared.client.event_stopped(StoppedEventBody {
+            reason: reason.to_owned(),
+            thread_id: Some(self.id),
+            description: Some("Hello".to_owned()),
+            all_threads_stopped: Some(false),
+            preserve_focus_hint: None,
+            text,
+        });
+        loop {
+            let msg = self.receiver.lock().unwrap().recv().unwrap();
+            // The client's requests evaluate expressions on this thread.
+            match self.evaluating(|| msg(span_loc, eval)) {
+                Next::Continue => break,
+                Next::Step(step) => {
+                    *self.stepping.lock().unwrap() = Some((step, self.depth(eval)));
+                    break;
+                }
+                Next::RemainPaused => continue,
+            }
+        }
+        self.set_paused(false);
+    }
+
+    fn set_paused(&self, paused: bool) {
+        let mut threads = self.shared.threads.lock().unwrap();
+        if let Some(thread) = threads.threads.get_mut(&self.id) {
+            thread.paused = paused;
+        }
+        if paused {
+            threads.last_paused = Some(self.id);
+        }
+    }
+
+    /// Whether there is a breakpoint at this statement which we should pause at.
+    /// Logpoints output their message instead.
+    fn breakpoint(&self, span_loc: FileSpanRef, eval: &mut Evaluator) -> bool {
+        let span = span_loc.resolve_span();
+        // Copy the configuration out, rather than holding the lock while evaluating the
+        // condition and message, which run user code that may reach other breakpoints.
+        let config = self
+            .shared
+            .breakpoints
+            .lock()
+            .unwrap()
+            .get(span_loc.filename())
+            .and_then(|x| x.get(&span))
+            .map(|config| {
+                (
+                    config.condition.clone(),
+                    config.hit_condition,
+                    config.log_message.clone(),
+                )
+            });
+        let (condition, hit_condition, log_message) = match config {
+            Some(config) => config,
+            None => return false,
+        };
+        if let Some(condition) = &condition {
+            match self.evaluating(|| evaluate(eval, condition, EvalIn::Paused, |x| x.to_bool())) {
+                Ok(true) => {}
+                Ok(false) => return false,
+                Err(e) => {
+                    // Pause, so the user can see the condition is wrong.
+                    self.output(
+                        format!("Breakpoint condition `{}` failed: {:#}\n", condition, e),
+                        span_loc,
+                    );
+                    return true;
+                }
+            }
+        }
+        let hits = self
+            .shared
+            .breakpoints
+            .lock()
+            .unwrap()
+            .get_mut(span_loc.filename())
+            .and_then(|x| x.get_mut(&span))
+            .map(|config| {
+                config.hits += 1;
+                config.hits
+            });
+        let hits = match hits {
+            Some(hits) => hits,
+            // The client removed the breakpoint while we evaluated its condition.
+            None => return false,
+        };
+        if let Some(hit_condition) = hit_condition {
+            if !hit_condition.matches(hits) {
+                return false;
+            }
+        }
+        match &log_message {
+            None => true,
+            Some(message) => {
+                let mut output = interpolate(message, |expr| {
+                    match self.evaluating(|| evaluate(eval, expr, EvalIn::Paused, |x| x.to_str())) {
+                        Ok(x) => x,
+                        // Diagnostics span several lines, which would clutter the log.
+                        Err(e) => {
+                            format!("<{}>", format!("{:#}", e).lines().next().unwrap_or("error"))
+                        }
+                    }
+                });
+                output.push('\n');
+                self.output(output, span_loc);
+                false
+            }
+        }
+    }
+
+    fn output(&self, output: String, span_loc: FileSpanRef) {
+        self.shared.client.event_output(output_event(
+            output,
+            "console",
+            Some(span_loc.to_file_span()),
+        ));
+    }
+}
+
+impl<C: DapContext> Backend<C> {
+    /// Run `f` on a paused thread, and wait for the result.
+    fn inject<T: 'static + Send>(
+        &self,
+        thread: i64,
+        f: Box<dyn Fn(FileSpanRef, &mut Evaluator) -> (Next, T) + Send>,
+    ) -> anyhow::Result<T> {
+        let (sender, receiver) = channel();
+        let handle = self
+            .threads
+            .lock()
+            .unwrap()
+            .threads
+            .get(&thread)
+            .map(|x| (x.paused, x.sender.clone()));
+        match handle {
+            Some((true, thread_sender)) => thread_sender
+                .send(box move |span, eval| {
+                    let (next, res) = f(span, eval);
+                    // If the server has gone away, there's nobody to tell.
+                    let _ = sender.send(res);
+                    next
+                })
+                .map_err(|_| anyhow::anyhow!("Thread {} has exited", thread))?,
+            Some(_) => return Err(anyhow::anyhow!("Thread {} is not paused", thread)),
+            None => return Err(anyhow::anyhow!("No thread {}", thread)),
+        }
+        receiver
+            .recv()
+            .map_err(|_| anyhow::anyhow!("Thread {} has exited", thread))
+    }
+
+    fn inject_continue(&self, thread: i64) -> anyhow::Result<()> {
+        self.forget_variables(thread);
+        self.inject(thread, box |_, _| (Next::Continue, ()))
+    }
+
+    fn inject_step(&self, thread: i64, step: Step) -> anyhow::Result<()> {
+        self.forget_variables(thread);
+        self.inject(thread, box move |_, _| (Next::Step(step), ()))
+    }
+
+    /// Forget the variable references we have given out, as the values may change once
+    /// `thread` resumes. References into other threads which remain paused must still work,
+    /// so in that case we keep them all.
+    fn forget_variables(&self, thread: i64) {
+        let others_paused = self
+            .threads
+            .lock()
+            .unwrap()
+            .threads
+            .iter()
+            .any(|(id, x)| *id != thread && x.paused);
+        if !others_paused {
+            self.variables.lock().unwrap().clear();
+        }
+    }
+
+    fn with_ctx<T: 'static + Send>(
+        &self,
+        thread: i64,
+        f: Box<dyn Fn(FileSpanRef, &mut Evaluator) -> anyhow::Result<T> + Send>,
+    ) -> anyhow::Result<T> {
+        self.inject(thread, box move |span, eval| {
+            (Next::RemainPaused, f(span, eval))
+        })?
+    }
+
+    /// The thread for requests which don't say, which is the one which paused most recently.
+    fn default_thread(&self) -> i64 {
+        self.threads.lock().unwrap().last_paused.unwrap_or_default()
+    }
+
+    fn execute(&self, args: Map<String, Value>) {
+        let client = self.client.dupe();
+        let context = self.context.dupe();
+        let breakpoints = self.breakpoints.dupe();
+        let exception_filters = self.exception_filters.dupe();
+        let threads = self.threads.dupe();
+
+        thread::spawn(move || {
+            let debuggee = Debuggee::new(Shared {
+                client: client.dupe(),
+                breakpoints,
+                exception_filters,
+                threads,
+            });
+            // No way to pass back success/failure to the caller
+            client.log("EVALUATION START");
+            let res = context.launch(&args, &debuggee);
+            drop(debuggee);
+            client.log("EVALUATION FINISHED");
+            client.event_output(match &res {
+                Ok(v) => output_event(v.to_owned(), "console", None),
+                // Show where the error was raised, which the client can link to.
+                Err(e) => {
+                    let span = e.downcast_ref::<Diagnostic>().and_then(|d| d.span.dupe());
+                    output_event(format!("{:#}\n", e), "stderr", span)
+                }
+            });
+            client.event_exited(ExitedEventBody {
+                exit_code: if res.is_ok() { 0 } else { 1 },
+            });
+            client.event_terminated(None);
+        });
+    }
+}
+
+fn breakpoint(verified: bool) -> Breakpoint {
+    Breakpoint {
+        column: None,
+        end_column: None,
+        end_line: None,
+        id: None,
+        line: None,
+        message: None,
+        source: None,
+        verified,
+    }
+}
+
+impl<C: DapContext> DebugServer for Backend<C> {
+    fn initialize(&self, _: InitializeRequestArguments) -> anyhow::Result<Option<Capabilities>> {
+        self.client.event_initialized(None);
+        Ok(Some(Capabilities {
+            supports_configuration_done_request: Some(true),
+            supports_conditional_breakpoints: Some(true),
+            supports_hit_conditional_breakpoints: Some(true),
+            supports_log_points: Some(true),
+            exception_breakpoint_filters: Some(vec![
+                ExceptionBreakpointsFilter {
+                    filter: FILTER_ALL_ERRORS.to_owned(),
+                    label: "All errors".to_owned(),
+                    default: Some(false),
+                },
+                ExceptionBreakpointsFilter {
+                    filter: FILTER_FAIL.to_owned(),
+                    label: "fail() only".to_owned(),
+                    default: Some(false),
+                },
+            ]),
+            supports_evaluate_for_hovers: Some(true),
+            supports_set_variable: Some(true),
+            supports_set_expression: Some(true),
+            supports_step_in_targets_request: Some(true),
+            ..Capabilities::default()
+        }))
+    }
+
+    fn set_breakpoints(
+        &self,
+        x: SetBreakpointsArguments,
+    ) -> anyhow::Result<SetBreakpointsResponseBody> {
+        let breakpoints = x.breakpoints.unwrap_or_default();
+        let source = x.source.path.unwrap();
+
+        if breakpoints.is_empty() {
+            self.breakpoints.lock().unwrap().remove(&source);
+            Ok(SetBreakpointsResponseBody {
+                breakpoints: Vec::new(),
+            })
+        } else {
+            match self.context.parse_file(Path::new(&source)) {
+                Err(_) => {
+                    self.breakpoints.lock().unwrap().remove(&source);
+                    Ok(SetBreakpointsResponseBody {
+                        breakpoints: vec![breakpoint(false); breakpoints.len()],
+                    })
+                }
+                Ok(ast) => {
+                    let poss: HashMap<usize, ResolvedSpan> = ast
+                        .stmt_locations()
+                        .iter()
+                        .map(|span| {
+                            let span = span.resolve_span();
+                            (span.begin_line, span)
+                        })
+                        .collect();
+                    let mut configs = HashMap::new();
+                    let list = breakpoints.into_map(|x| {
+                        // Lines are 1-based, so a line of 0 (or less) can never be verified.
+                        let line = usize::try_from(x.line).ok().and_then(|x| x.checked_sub(1));
+                        let span = match line.and_then(|line| poss.get(&line)) {
+                            Some(span) => *span,
+                            None => return breakpoint(false),
+                        };
+                        let hit_condition =
+                            match x.hit_condition.as_deref().map(HitCondition::parse) {
+                                Some(Err(e)) => {
+                                    return Breakpoint {
+                                        message: Some(format!("{:#}", e)),
+                                        ..breakpoint(false)
+                                    };
+                                }
+                                hit_condition => hit_condition.transpose().unwrap(),
+                            };
+                        let non_empty = |x: Option<String>| x.filter(|x| !x.trim().is_empty());
+                        configs.insert(
+                            span,
+                            BreakpointConfig {
+                                condition: non_empty(x.condition),
+                                hit_condition,
+                                log_message: non_empty(x.log_message),
+                                hits: 0,
+                            },
+                        );
+                        breakpoint(true)
+                    });
+                    self.breakpoints.lock().unwrap().insert(source, configs);
+                    Ok(SetBreakpointsResponseBody { breakpoints: list })
+                }
+            }
+        }
+    }
+
+    fn set_exception_breakpoints(&self, x: SetExceptionBreakpointsArguments) -> anyhow::Result<()> {
+        *self.exception_filters.lock().unwrap() = x.filters.into_iter().collect();
+        Ok(())
+    }
+
+    fn launch(&self, _: LaunchRequestArguments, args: Map<String, Value>) -> anyhow::Result<()> {
+        // The context knows what the arguments mean, so checks them when it launches.
+        *self.launch.lock().unwrap() = Some(args);
+        Ok(())
+    }
+
+    fn threads(&self) -> anyhow::Result<ThreadsResponseBody> {
+        let threads = self.threads.lock().unwrap();
+        Ok(ThreadsResponseBody {
+            threads: threads
+                .threads
+                .iter()
+                .map(|(id, x)| Thread {
+                    id: *id,
+                    name: x.name.clone(),
+                })
+                .collect(),
+        })
+    }
+
+    fn configuration_done(&self) -> anyhow::Result<()> {
+        if let Some(args) = self.launch.lock().unwrap().take() {
+            self.execute(args);
+        }
+        Ok(())
+    }
+
+    fn stack_trace(&self, x: StackTraceArguments) -> anyhow::Result<StackTraceResponseBody> {
+        fn convert_frame(id: i64, name: String, location: Option<FileSpan>) -> StackFrame {
+            let mut s = StackFrame {
+                id,
+                name,
+                column: 0,
+                line: 0,
+                end_column: None,
+                end_line: None,
+                module_id: None,
+                presentation_hint: None,
+                source: None,
+            };
+            if let Some(loc) = location {
+                let span = loc.resolve_span();
+                s.line = span.begin_line as i64 + 1;
+                s.column = span.begin_column as i64 + 1;
+                s.end_line = Some(span.end_line as i64 + 1);
+                s.end_column = Some(span.end_column as i64 + 1);
+                s.source = Some(Source {
+                    path: Some(loc.filename().to_owned()),
+                    ..Source::default()
+                })
+            }
+            s
+        }
+
+        // Our model of a Frame and the debugger model are a bit different.
+        // We record the location of the call, but DAP wants the location we are at.
+        // We also have them in the wrong order
+        let thread = x.thread_id;
+        self.with_ctx(thread, box move |span, eval| {
+            let frames = eval.call_stack().into_frames();
+            let mut next = Some(span.to_file_span());
+            let mut res = Vec::with_capacity(frames.len() + 1);
+            for (i, x) in frames.iter().rev().enumerate() {
+                res.push(convert_frame(frame_id(thread, i), x.name.clone(), next));
+                next = x.location.dupe();
+            }
+            // Frame ids count from the top, so encode the `n` the evaluator uses to inspect them.
+            let root = frame_id(thread, res.len());
+            res.push(convert_frame(root, "Root".to_owned(), next));
+            Ok(StackTraceResponseBody {
+                total_frames: Some(res.len() as i64),
+                stack_frames: res,
+            })
+        })
+    }
+
+    fn scopes(&self, x: ScopesArguments) -> anyhow::Result<ScopesResponseBody> {
+        let (thread, frame) = from_frame_id(x.frame_id);
+        let scopes = self.with_ctx(thread, box move |_, eval| {
+            // The module itself is the bottom frame, and has no locals of its own.
+            let kinds = if frame == eval.call_stack().into_frames().len() {
+                vec![ScopeKind::Module, ScopeKind::Globals]
+            } else {
+                vec![ScopeKind::Locals, ScopeKind::Module, ScopeKind::Globals]
+            };
+            Ok(kinds
+                .into_iter()
+                .filter_map(|kind| Some((kind, kind.variables(frame, eval)?.len())))
+                .collect::<Vec<_>>())
+        })?;
+        let mut refs = self.variables.lock().unwrap();
+        Ok(ScopesResponseBody {
+            scopes: scopes.into_map(|(kind, len)| Scope {
+                name: kind.name().to_owned(),
+                named_variables: Some(len as i64),
+                variables_reference: refs.reference(VariablePath::scope(kind, thread, frame)),
+                expensive: kind == ScopeKind::Globals,
+                column: None,
+                end_column: None,
+                end_line: None,
+                indexed_variables: None,
+                line: None,
+                source: None,
+            }),
+        })
+    }
+
+    fn variables(&self, x: VariablesArguments) -> anyhow::Result<VariablesResponseBody> {
+        let path = self
+            .variables
+            .lock()
+            .unwrap()
+            .path(x.variables_reference)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow::anyhow!("Unknown variable reference {}", x.variables_reference)
+            })?;
+        let vars = self.with_ctx(path.thread(), box move |_, eval| variables(&path, &x, eval))?;
+        let mut refs = self.variables.lock().unwrap();
+        Ok(VariablesResponseBody {
+            variables: vars.into_map(|(mut var, path)| {
+                if let Some(path) = path {
+                    var.variables_reference = refs.reference(path);
+                }
+                var
+            }),
+        })
+    }
+
+    fn set_variable(&self, x: SetVariableArguments) -> anyhow::Result<SetVariableResponseBody> {
+        let path = self
+            .variables
+            .lock()
+            .unwrap()
+            .path(x.variables_reference)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow::anyhow!("Unknown variable reference {}", x.variables_reference)
+            })?;
+        let (var, path) = self.with_ctx(path.thread(), box move |_, eval| {
+            // The new value is evaluated in the frame the variable belongs to.
+            let frame = EvalIn::Frame(path.frame());
+            let value = evaluate(eval, &x.value, frame, |v| v)?;
+            set_variable(&path, &x.name, value, eval)
+        })?;
+        let reference = path.map(|path| self.variables.lock().unwrap().reference(path));
+        Ok(SetVariableResponseBody {
+            value: var.value,
+            type_: var.type_,
+            variables_reference: reference.map(|x| x as f64),
+            indexed_variables: var.indexed_variables.map(|x| x as f64),
+            named_variables: var.named_variables.map(|x| x as f64),
+        })
+    }
+
+    fn continue_(&self, x: ContinueArguments) -> anyhow::Result<ContinueResponseBody> {
+        self.inject_continue(x.thread_id)?;
+        Ok(ContinueResponseBody {
+            all_threads_continued: Some(false),
+        })
+    }
+
+    fn next(&self, x: NextArguments) -> anyhow::Result<()> {
+        self.inject_step(x.thread_id, Step::Over)
+    }
+
+    fn step_in(&self, x: StepInArguments) -> anyhow::Result<()> {
+        self.inject_step(x.thread_id, Step::Into)
+    }
+
+    fn step_out(&self, x: StepOutArguments) -> anyhow::Result<()> {
+        self.inject_step(x.thread_id, Step::Out)
+    }
+
+    fn evaluate(&self, x: EvaluateArguments) -> anyhow::Result<EvaluateResponseBody> {
+        let frame = x.frame_id.map(from_frame_id);
+        let thread = frame.map_or_else(|| self.default_thread(), |(thread, _)| thread);
+        self.with_ctx(thread, box move |_, eval| {
+            let frame = frame.map(|(_, n)| n);
+            let repl = x.context.as_deref() == Some("repl");
+            let scope = match frame {
+                _ if repl => EvalIn::Repl(frame.unwrap_or_default()),
+                None => EvalIn::Paused,
+                Some(n) => EvalIn::Frame(n),
+            };
+            let s = match evaluate(eval, &x.expression, scope, |v| {
+                // Statements such as assignments have no interesting result.
+                if repl && v.is_none() {
+                    String::new()
+                } else {
+                    v.to_string()
+                }
+            }) {
+                Err(e) => format!("{:#}", e),
+                Ok(v) => v,
+            };
+            Ok(EvaluateResponseBody {
+                indexed_variables: None,
+                named_variables: None,
+                presentation_hint: None,
+                result: s,
+                type_: None,
+                variables_reference: 0.0,
+            })
+        })
+    }
+
+    fn set_expression(
+        &self,
+        x: SetExpressionArguments,
+    ) -> anyhow::Result<SetExpressionResponseBody> {
+        let (thread, frame) = match x.frame_id {
+            Some(id) => from_frame_id(id),
+            None => (self.default_thread(), 0),
+        };
+        self.with_ctx(thread, box move |_, eval| {
+            let scope = EvalIn::Frame(frame);
+            let is_name = x
+                .expression
+                .chars()
+                .all(|c| c == '_' || c.is_alphanumeric())
+                && !x.expression.starts_with(|c: char| c.is_ascii_digit());
+            if is_name {
+                // Assigning by evaluation only changes module variables while evaluating,
+                // so assign variables directly.
+                let value = evaluate(eval, &x.value, scope, |v| v)?;
+                let locals = eval.local_variables_at_frame(frame).unwrap_or_default();
+                if locals.contains_key(&x.expression) {
+                    eval.set_local_variable_at_frame(frame, &x.expression, value)?;
+                } else {
+                    eval.set_module_variable_at_frame(frame, &x.expression, value)?;
+                }
+            } else {
+                // Other expressions, such as `xs[0]` or `x.field`, assign into a value.
+                let assign = format!("{} = {}", x.expression, x.value);
+                evaluate(eval, &assign, scope, |_| ())?;
+            }
+            let value = evaluate(eval, &x.expression, scope, |v| v)?;
+            Ok(SetExpressionResponseBody {
+                value: preview(value),
+                type_: Some(value.get_type().to_owned()),
+                presentation_hint: None,
+                variables_reference: None,
+                indexed_variables: None,
+                named_variables: None,
+            })
+        })
+    }
+}
+
+/// Serve the Debug Adapter Protocol, reading requests from `input` and writing responses
+/// and events to `output`, until the client disconnects or the input ends.
+///
+/// The program is evaluated on a separate thread, so the context must be shareable between threads.
+///
+/// If the `launch` request has a `trace` argument, the trace it names, as written by
+/// [`TraceRecorder`](crate::debug::trace::TraceRecorder), is replayed instead, without
+/// calling the context. Relative paths in the trace are resolved against the `root`
+/// argument, or else the directory of the trace.
+pub fn server(
+    context: impl DapContext,
+    input: impl Read,
+    output: impl Write + Send + 'static,
+) -> anyhow::Result<()> {
+    let context = Arc::new(context);
+    let log = {
+        let context = context.dupe();
+        Arc::new(move |x: &str| context.log(x))
+    };
+    let client = Client::new(box output, log);
+    let backend = Backend {
+        client: client.dupe(),
+        context,
+        launch: Default::default(),
+        breakpoints: Default::default(),
+        exception_filters: Default::default(),
+        variables: Default::default(),
+        threads: Default::default(),
+    };
+
+    client.log("DEBUG ADAPTER STARTING");
+    let mut input = BufReader::new(input);
+    let mut replay = None;
+    while let Some((s, recv)) = read(&mut input as &mut dyn BufRead)? {
+        client.log(&format!("RECV: {}", s));
+        let r: Request = serde_json::from_value(recv)?;
+        // A launch which names a trace replays it, rather than running the program.
+        if r.command == "launch" {
+            let args = r.arguments.as_ref().and_then(|x| x.as_object());
+            if args.and_then(trace_path).is_some() {
+                replay = Some(Replay::new(&backend));
+            }
+        }
+        let resp = match &replay {
+            Some(replay) => dispatch(replay, &r),
+            None => dispatch(&backend, &r),
+        };
+        client.send(resp)?;
+        if let Some(replay) = &replay {
+            replay.flush();
+        }
+
+        if r.command == "disconnect" {
+            break;
+        }
+    }
+    client.log("DEBUG ADAPTER STOPPING");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::debug::dap::stream::send;
+    use crate::environment::GlobalsBuilder;
+    use crate::environment::Module;
+    use crate::{self as starlark};
+
+    /// A program which only exists in memory, using a builtin the host provides.
+    const PROGRAM: &str = "x = double(2)\nprint('x is', x)\ny = x + 1\nfail('y is', y)\n";
+
+    #[starlark_module]
+    fn host_globals(builder: &mut GlobalsBuilder) {
+        fn double(x: i32) -> anyhow::Result<i32> {
+            Ok(x * 2)
+        }
+    }
+
+    struct TestContext;
+
+    impl DapContext for TestContext {
+        fn launch(&self, _: &Map<String, Value>, debuggee: &Debuggee) -> anyhow::Result<String> {
+            let globals = GlobalsBuilder::extended().with(host_globals).build();
+            let module = Module::new();
+            let mut eval = Evaluator::new(&module);
+            let ast = self.parse_file(Path::new("test.star"))?;
+            Ok(debuggee.eval_module(ast, &globals, &mut eval)?.to_string())
+        }
+
+        fn parse_file(&self, path: &Path) -> anyhow::Result<AstModule> {
+            let path = path.to_str().unwrap();
+            AstModule::parse(path, PROGRAM.to_owned(), &Dialect::Extended)
+        }
+    }
+
+    /// Evaluates `main.star` on the main thread, while a worker thread evaluates `worker.star`.
+    struct ThreadsContext;
+
+    impl DapContext for ThreadsContext {
+        fn launch(&self, _: &Map<String, Value>, debuggee: &Debuggee) -> anyhow::Result<String> {
+            let worker = debuggee.thread("worker");
+            let ast = self.parse_file(Path::new("worker.star"))?;
+            let worker = thread::spawn(move || {
+                let module = Module::new();
+                let mut eval = Evaluator::new(&module);
+                let res = worker.eval_module(ast, &Globals::standard(), &mut eval);
+                res.map(|x| x.to_string())
+            });
+            let module = Module::new();
+            let mut eval = Evaluator::new(&module);
+            let ast = self.parse_file(Path::new("main.star"))?;
+            let main = debuggee.eval_module(ast, &Globals::standard(), &mut eval)?;
+            Ok(format!("{} {}", main, worker.join().unwrap()?))
+        }
+
+        fn parse_file(&self, path: &Path) -> anyhow::Result<AstModule> {
+            let path = path.to_str().unwrap();
+            let program = format!("name = {:?}\nname\n", path);
+            AstModule::parse(path, program, &Dialect::Extended)
+        }
+    }
+
+    /// Serve a client over a socket, returning functions to send requests, and to read
+    /// messages until one matches.
+    #[cfg(unix)]
+    fn connect(
+        context: impl DapContext,
+    ) -> (
+        impl FnMut(&str, Value),
+        impl FnMut(&dyn Fn(&Value) -> bool) -> Value,
+        thread::JoinHandle<anyhow::Result<()>>,
+    ) {
+        use std::os::unix::net::UnixStream;
+
+        let (client, server_end) = UnixStream::pair().unwrap();
+        let server_thread = {
+            let output = server_end.try_clone().unwrap();
+            thread::spawn(move || server(context, server_end, output))
+        };
+        let mut input = BufReader::new(client.try_clone().unwrap());
+        let mut output = client;
+        let mut seq = 0;
+        let request = move |command: &str, arguments: Value| {
+            seq += 1;
+            let x =
+                json!({"seq": seq, "type": "request", "command": command, "arguments": arguments});
+            send(&mut output, &x.to_string()).unwrap();
+        };
+        let next = move |pred: &dyn Fn(&Value) -> bool| loop {
+            let (_, x) = read(&mut input).unwrap().unwrap();
+            if pred(&x) {
+                return x;
+            }
+        };
+        (request, next, server_thread)
+    }
+
+    #[test]
+    fn test_hit_condition() {
+        let matches = |x: &str| {
+            let cond = HitCondition::parse(x).unwrap();
+            (1..=6)
+                .filter(|hits| cond.matches(*hits))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(matches("4"), vec![4, 5, 6]);
+        assert_eq!(matches(" == 4"), vec![4]);
+        assert_eq!(matches(">4"), vec![5, 6]);
+        assert_eq!(matches("<= 2"), vec![1, 2]);
+        assert_eq!(matches("%3"), vec![3, 6]);
+        assert!(HitCondition::parse("% 0").is_err());
+        assert!(HitCondition::parse("x > 3").is_err());
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let eval = |x: &str| x.to_uppercase();
+        assert_eq!(interpolate("x = {x}, {y}!", eval), "x = X, Y!");
+        assert_eq!(interpolate("{{x}} {x", eval), "{x} {x");
+    }
+
+    #[test]
+    fn test_step_stops() {
+        // Stepping from inside a function called from the top level.
+        let stops = |step: Step| [0, 1, 2].map(|depth| step.stops(1, depth));
+        assert_eq!(stops(Step::Into), [true, true, true]);
+        assert_eq!(stops(Step::Over), [true, true, false]);
+        assert_eq!(stops(Step::Out), [true, false, false]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_server() {
+        let (mut request, mut next, server_thread) = connect(TestContext);
+        let event = |name: &'static str| move |x: &Value| x["event"] == name;
+        let response = |name: &'static str| move |x: &Value| x["command"] == name;
+        request("initialize", json!({"adapterID": "test"}));
+        request(
+            "setBreakpoints",
+            json!({"source": {"path": "test.star"}, "breakpoints": [
+                {"line": 2, "logMessage": "logged {double(x)}", "condition": "x == 4"},
+                {"line": 3},
+                {"line": 0},
+            ]}),
+        );
+        let breakpoints = next(&response("setBreakpoints"));
+        assert_eq!(breakpoints["body"]["breakpoints"][0]["verified"], true);
+        assert_eq!(breakpoints["body"]["breakpoints"][2]["verified"], false);
+        request("launch", json!({}));
+        request("configurationDone", json!({}));
+
+        let logged = next(&event("output"));
+        assert_eq!(logged["body"]["output"], "logged 8\n");
+        assert_eq!(logged["body"]["category"], "console");
+        let printed = next(&event("output"));
+        assert_eq!(printed["body"]["output"], "x is 4\n");
+        assert_eq!(printed["body"]["category"], "stdout");
+        assert_eq!(printed["body"]["line"], 2);
+        let stopped = next(&event("stopped"));
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+
+        // Variables defined in the console remain defined.
+        request("evaluate", json!({"expression": "x", "frameId": 0}));
+        assert_eq!(next(&response("evaluate"))["body"]["result"], "4");
+        request(
+            "evaluate",
+            json!({"expression": "z = x * 10", "frameId": 0, "context": "repl"}),
+        );
+        assert_eq!(next(&response("evaluate"))["body"]["result"], "");
+        request(
+            "evaluate",
+            json!({"expression": "z + 1", "frameId": 0, "context": "repl"}),
+        );
+        assert_eq!(next(&response("evaluate"))["body"]["result"], "41");
+
+        // Assigning variables while paused changes what the program computes.
+        request("scopes", json!({"frameId": 0}));
+        let scopes = next(&response("scopes"));
+        assert_eq!(scopes["body"]["scopes"][0]["name"], "Module");
+        let module = scopes["body"]["scopes"][0]["variablesReference"].clone();
+        request(
+            "setVariable",
+            json!({"variablesReference": module, "name": "x", "value": "x + 3"}),
+        );
+        let set = next(&response("setVariable"));
+        assert_eq!(set["body"]["value"], "7");
+        assert_eq!(set["body"]["type"], "int");
+        request(
+            "setExpression",
+            json!({"expression": "x", "value": "x - 1", "frameId": 0}),
+        );
+        assert_eq!(next(&response("setExpression"))["body"]["value"], "6");
+        request(
+            "setVariable",
+            json!({"variablesReference": module, "name": "missing", "value": "1"}),
+        );
+        assert_eq!(next(&response("setVariable"))["success"], false);
+
+        request("continue", json!({"threadId": 0}));
+        let error = next(&event("output"));
+        assert!(error["body"]["output"].as_str().unwrap().contains("y is 7"));
+        assert_eq!(error["body"]["category"], "stderr");
+        assert_eq!(error["body"]["source"]["path"], "test.star");
+        assert_eq!(error["body"]["line"], 4);
+        assert_eq!(next(&event("exited"))["body"]["exitCode"], 1);
+        next(&event("terminated"));
+        request("disconnect", json!({}));
+        next(&response("disconnect"));
+        server_thread.join().unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_threads() {
+        let (mut request, mut next, server_thread) = connect(ThreadsContext);
+        let event = |name: &'static str| move |x: &Value| x["event"] == name;
+        let response = |name: &'static str| move |x: &Value| x["command"] == name;
+
+        request("initialize", json!({"adapterID": "test"}));
+        for path in ["main.star", "worker.star"] {
+            request(
+                "setBreakpoints",
+                json!({"source": {"path": path}, "breakpoints": [{"line": 2}]}),
+            );
+        }
+        request("launch", json!({}));
+        request("configurationDone", json!({}));
+
+        // Both threads pause, in either order.
+        let mut stopped = vec![
+            next(&event("stopped"))["body"]["threadId"]
+                .as_i64()
+                .unwrap(),
+            next(&event("stopped"))["body"]["threadId"]
+                .as_i64()
+                .unwrap(),
+        ];
+        stopped.sort_unstable();
+        assert_eq!(stopped, vec![0, 1]);
+        request("threads", json!({}));
+        let threads = next(&response("threads"));
+        assert_eq!(
+            threads["body"]["threads"],
+            json!([{"id": 0, "name": "main"}, {"id": 1, "name": "worker"}])
+        );
+
+        // Each thread is inspected separately, through the frame ids of its stack.
+        request("stackTrace", json!({"threadId": 1}));
+        let frames = next(&response("stackTrace"));
+        let frame = frames["body"]["stackFrames"][0]["id"].clone();
+        assert_eq!(frame, frame_id(1, 0));
+        request("evaluate", json!({"expression": "name", "frameId": frame}));
+        assert_eq!(
+            next(&response("evaluate"))["body"]["result"],
+            "\"worker.star\""
+        );
+        request("evaluate", json!({"expression": "name", "frameId": 0}));
+        assert_eq!(
+            next(&response("evaluate"))["body"]["result"],
+            "\"main.star\""
+        );
+
+        // Resuming one thread leaves the other paused.
+        request("continue", json!({"threadId": 1}));
+        let exited = next(&event("thread"));
+        assert_eq!(exited["body"], json!({"reason": "exited", "threadId": 1}));
+        request("evaluate", json!({"expression": "name", "frameId": frame}));
+        assert_eq!(next(&response("evaluate"))["success"], false);
+        request("evaluate", json!({"expression": "name", "frameId": 0}));
+        assert_eq!(
+            next(&response("evaluate"))["body"]["result"],
+            "\"main.star\""
+        );
+
+        request("continue", json!({"threadId": 0}));
+        let output = next(&event("output"));
+        assert_eq!(output["body"]["output"], "\"main.star\" \"worker.star\"");
+        assert_eq!(next(&event("exited"))["body"]["exitCode"], 0);
+        request("disconnect", json!({}));
+        next(&response("disconnect"));
+        server_thread.join().unwrap().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_replay() {
+        use std::env;
+        use std::fs;
+        use std::fs::File;
+
+        use crate::debug::trace::TraceRecorder;
+
+        let dir = env::temp_dir();
+        let trace = dir.join(format!("starlark-test-replay-{}.jsonl", std::process::id()));
+        let recorder = TraceRecorder::new(File::create(&trace).unwrap(), true);
+        {
+            let globals = GlobalsBuilder::extended().with(host_globals).build();
+            let module = Module::new();
+            let mut eval = Evaluator::new(&module);
+            let ast = TestContext.parse_file(Path::new("test.star")).unwrap();
+            assert!(recorder.eval_module(ast, &globals, &mut eval).is_err());
+        }
+        recorder.finish().unwrap();
+
+        let (mut request, mut next, server_thread) = connect(TestContext);
+        let event = |name: &'static str| move |x: &Value| x["event"] == name;
+        let response = |name: &'static str| move |x: &Value| x["command"] == name;
+        let source = dir.join("test.star").to_str().unwrap().to_owned();
+        request("initialize", json!({"adapterID": "test"}));
+        request("launch", json!({ "trace": trace }));
+        let capabilities = next(&event("capabilities"));
+        assert_eq!(
+            capabilities["body"]["capabilities"]["supportsStepBack"],
+            true
+        );
+        request(
+            "setBreakpoints",
+            json!({"source": {"path": source}, "breakpoints": [{"line": 3}]}),
+        );
+        request("configurationDone", json!({}));
+        assert_eq!(next(&event("stopped"))["body"]["reason"], "entry");
+        // The line of the top frame, which is always in the recorded file.
+        fn line(
+            request: &mut impl FnMut(&str, Value),
+            next: &mut impl FnMut(&dyn Fn(&Value) -> bool) -> Value,
+            source: &str,
+        ) -> Value {
+            request("stackTrace", json!({"threadId": 0}));
+            let frames = next(&|x: &Value| x["command"] == "stackTrace");
+            assert_eq!(frames["body"]["stackFrames"][0]["source"]["path"], source);
+            frames["body"]["stackFrames"][0]["line"].clone()
+        }
+        assert_eq!(line(&mut request, &mut next, &source), 1);
+
+        request("continue", json!({"threadId": 0}));
+        assert_eq!(next(&event("stopped"))["body"]["reason"], "breakpoint");
+        assert_eq!(line(&mut request, &mut next, &source), 3);
+        request("evaluate", json!({"expression": "x", "frameId": 0}));
+        assert_eq!(next(&response("evaluate"))["body"]["result"], "4");
+        request("evaluate", json!({"expression": "x + 1", "frameId": 0}));
+        assert_eq!(next(&response("evaluate"))["success"], false);
+
+        request("continue", json!({"threadId": 0}));
+        let stopped = next(&event("stopped"));
+        assert_eq!(stopped["body"]["reason"], "exception");
+        assert!(stopped["body"]["text"].as_str().unwrap().contains("y is 5"));
+        assert_eq!(line(&mut request, &mut next, &source), 4);
+
+        // Going backwards stops at the breakpoint, then runs back to the start.
+        request("stepBack", json!({"threadId": 0}));
+        assert_eq!(next(&event("stopped"))["body"]["reason"], "breakpoint");
+        assert_eq!(line(&mut request, &mut next, &source), 3);
+        request("reverseContinue", json!({"threadId": 0}));
+        assert_eq!(next(&event("stopped"))["body"]["reason"], "entry");
+        assert_eq!(line(&mut request, &mut next, &source), 1);
+
+        request("disconnect", json!({}));
+        next(&response("disconnect"));
+        server_thread.join().unwrap().unwrap();
+        fs::remove_file(&trace).unwrap();
+    }
+}
@@ -0,0 +1,386 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Replay a trace written by [`TraceRecorder`](crate::debug::trace::TraceRecorder), stepping
+//! forwards and backwards through the statements it recorded. Nothing is evaluated, so only
+//! the recorded previews of locals can be inspected.
+
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use debugserver_types::*;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::debug::dap::frame_id;
+use crate::debug::dap::from_frame_id;
+use crate::debug::dap::requests::DebugServer;
+use crate::debug::dap::Backend;
+use crate::debug::dap::DapContext;
+use crate::debug::dap::Step;
+use crate::debug::trace::Trace;
+
+/// The only thread of a replay.
+const REPLAY_THREAD: i64 = 0;
+
+/// The trace file named by the arguments of a `launch` request, if it asks for a replay.
+pub(crate) fn trace_path(args: &Map<String, Value>) -> Option<&str> {
+    args.get("trace")?.as_str()
+}
+
+#[derive(Default)]
+struct ReplayState {
+    trace: Trace,
+    /// The files of the trace, resolved so they match the paths the client uses.
+    files: Vec<String>,
+    /// The step we are paused at.
+    position: usize,
+}
+
+/// Answers requests by moving through a trace, sharing breakpoints with the backend,
+/// which also answers the requests which don't depend on the program.
+pub(super) struct Replay<'a, C: DapContext> {
+    backend: &'a Backend<C>,
+    state: Mutex<ReplayState>,
+    /// The `stopped` event for the request being answered, which the protocol says
+    /// comes after the response.
+    stopped: Mutex<Option<StoppedEventBody>>,
+}
+
+impl<'a, C: DapContext> Replay<'a, C> {
+    pub(super) fn new(backend: &'a Backend<C>) -> Self {
+        Self {
+            backend,
+            state: Default::default(),
+            stopped: Default::default(),
+        }
+    }
+
+    /// Send the `stopped` event of the request which was just answered, if it moved.
+    pub(super) fn flush(&self) {
+        if let Some(body) = self.stopped.lock().unwrap().take() {
+            self.backend.client.event_stopped(body);
+        }
+    }
+
+    /// Whether the step `n` has a breakpoint which should pause. Conditions can't be
+    /// evaluated without the program, so are ignored, and logpoints never pause.
+    fn breakpoint(&self, state: &ReplayState, n: usize) -> bool {
+        let step = &state.trace.steps[n];
+        let breakpoints = self.backend.breakpoints.lock().unwrap();
+        breakpoints
+            .get(&state.files[step.file])
+            .and_then(|x| x.get(&step.span))
+            .map_or(false, |x| x.log_message.is_none())
+    }
+
+    /// Move to the first step `pred` accepts, searching forwards or backwards, or to the
+    /// end of the trace if none do, and tell the client.
+    fn run(&self, forwards: bool, pred: impl Fn(&ReplayState, usize) -> bool) {
+        let mut state = self.state.lock().unwrap();
+        let len = state.trace.steps.len();
+        let from = state.position;
+        let found = if forwards {
+            (from + 1..len).find(|n| pred(&state, *n))
+        } else {
+            (0..from).rev().find(|n| pred(&state, *n))
+        };
+        match found {
+            Some(n) => self.pause(&mut state, n, "step", None),
+            None if forwards => self.pause(&mut state, len - 1, "step", Some("End of trace")),
+            None => self.pause(&mut state, 0, "entry", Some("Start of trace")),
+        }
+    }
+
+    /// Move to step `n` and tell the client once we have responded, with the reason being an error or breakpoint
+    /// at the step, or else `reason`.
+    fn pause(&self, state: &mut ReplayState, n: usize, reason: &str, description: Option<&str>) {
+        let text = state.trace.steps[n].error.clone();
+        let reason = if text.is_some() {
+            "exception"
+        } else if self.breakpoint(state, n) {
+            "breakpoint"
+        } else {
+            reason
+        };
+        state.position = n;
+        *self.stopped.lock().unwrap() = Some(StoppedEventBody {
+            reason: reason.to_owned(),
+            thread_id: Some(REPLAY_THREAD),
+            description: description.map(|x| x.to_owned()),
+            all_threads_stopped: Some(true),
+            preserve_focus_hint: None,
+            text,
+        });
+    }
+
+    /// Step in a direction, stopping early at errors and breakpoints.
+    fn step(&self, forwards: bool, step: Step) {
+        let from = {
+            let state = self.state.lock().unwrap();
+            state.trace.steps[state.position].depth
+        };
+        self.run(forwards, |state, n| {
+            let x = &state.trace.steps[n];
+            step.stops(from, x.depth) || x.error.is_some() || self.breakpoint(state, n)
+        })
+    }
+
+    /// Run in a direction until an error or breakpoint.
+    fn continue_to(&self, forwards: bool) {
+        self.run(forwards, |state, n| {
+            state.trace.steps[n].error.is_some() || self.breakpoint(state, n)
+        })
+    }
+
+    /// The step the `n`-th frame from the top is at.
+    fn frame_step(state: &ReplayState, n: usize) -> anyhow::Result<usize> {
+        state
+            .trace
+            .stack(state.position)
+            .get(n)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("No frame {}", n))
+    }
+}
+
+/// The path of a file in a trace, relative to `root` if it isn't absolute.
+fn resolve(root: &Path, path: &str) -> String {
+    let path = root.join(path);
+    fs::canonicalize(&path)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+impl<C: DapContext> DebugServer for Replay<'_, C> {
+    fn initialize(&self, x: InitializeRequestArguments) -> anyhow::Result<Option<Capabilities>> {
+        self.backend.initialize(x)
+    }
+
+    fn set_breakpoints(
+        &self,
+        x: SetBreakpointsArguments,
+    ) -> anyhow::Result<SetBreakpointsResponseBody> {
+        self.backend.set_breakpoints(x)
+    }
+
+    fn set_exception_breakpoints(&self, x: SetExceptionBreakpointsArguments) -> anyhow::Result<()> {
+        self.backend.set_exception_breakpoints(x)
+    }
+
+    fn launch(&self, _: LaunchRequestArguments, args: Map<String, Value>) -> anyhow::Result<()> {
+        let path = PathBuf::from(trace_path(&args).unwrap());
+        let trace = Trace::read(&path)?;
+        // Traces are often recorded elsewhere, so relative paths are resolved against the
+        // `root` the user gives, or where the trace is.
+        let root = match args.get("root") {
+            None => path.parent().map(|x| x.to_owned()).unwrap_or_default(),
+            Some(Value::String(root)) => PathBuf::from(root),
+            Some(root) => {
+                return Err(anyhow::anyhow!(
+                    "Expected `root` to be a string, got {}",
+                    root
+                ));
+            }
+        };
+        let files = trace.files.iter().map(|x| resolve(&root, x)).collect();
+        *self.state.lock().unwrap() = ReplayState {
+            trace,
+            files,
+            position: 0,
+        };
+        self.backend
+            .client
+            .event_capabilities(CapabilitiesEventBody {
+                capabilities: Capabilities {
+                    supports_step_back: Some(true),
+                    ..Capabilities::default()
+                },
+            });
+        Ok(())
+    }
+
+    fn threads(&self) -> anyhow::Result<ThreadsResponseBody> {
+        Ok(ThreadsResponseBody {
+            threads: vec![Thread {
+                id: REPLAY_THREAD,
+                name: "replay".to_owned(),
+            }],
+        })
+    }
+
+    fn configuration_done(&self) -> anyhow::Result<()> {
+        let client = &self.backend.client;
+        if self.state.lock().unwrap().trace.steps.is_empty() {
+            client.event_exited(ExitedEventBody { exit_code: 0 });
+            client.event_terminated(None);
+            return Ok(());
+        }
+        client.event_thread(ThreadEventBody {
+            reason: "started".to_owned(),
+            thread_id: REPLAY_THREAD,
+        });
+        self.pause(&mut self.state.lock().unwrap(), 0, "entry", None);
+        Ok(())
+    }
+
+    fn stack_trace(&self, _: StackTraceArguments) -> anyhow::Result<StackTraceResponseBody> {
+        let state = self.state.lock().unwrap();
+        let stack = state.trace.stack(state.position);
+        let frames = stack
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let step = &state.trace.steps[*n];
+                let span = step.span;
+                StackFrame {
+                    id: frame_id(REPLAY_THREAD, i),
+                    name: state.trace.frames[step.frame].name.clone(),
+                    line: span.begin_line as i64 + 1,
+                    column: span.begin_column as i64 + 1,
+                    end_line: Some(span.end_line as i64 + 1),
+                    end_column: Some(span.end_column as i64 + 1),
+                    module_id: None,
+                    presentation_hint: None,
+                    source: Some(Source {
+                        path: Some(state.files[step.file].clone()),
+                        ..Source::default()
+                    }),
+                }
+            })
+            .collect::<Vec<_>>();
+        Ok(StackTraceResponseBody {
+            total_frames: Some(frames.len() as i64),
+            stack_frames: frames,
+        })
+    }
+
+    fn scopes(&self, x: ScopesArguments) -> anyhow::Result<ScopesResponseBody> {
+        let (_, frame) = from_frame_id(x.frame_id);
+        let state = self.state.lock().unwrap();
+        let n = Self::frame_step(&state, frame)?;
+        Ok(ScopesResponseBody {
+            scopes: vec![Scope {
+                name: "Recorded locals".to_owned(),
+                named_variables: Some(state.trace.locals(n).len() as i64),
+                // Each frame has one scope, so refer to it by the frame.
+                variables_reference: frame as i64 + 1,
+                expensive: false,
+                column: None,
+                end_column: None,
+                end_line: None,
+                indexed_variables: None,
+                line: None,
+                source: None,
+            }],
+        })
+    }
+
+    fn variables(&self, x: VariablesArguments) -> anyhow::Result<VariablesResponseBody> {
+        let state = self.state.lock().unwrap();
+        let n = Self::frame_step(&state, x.variables_reference as usize - 1)?;
+        Ok(VariablesResponseBody {
+            variables: state
+                .trace
+                .locals(n)
+                .into_iter()
+                .map(|(name, value)| Variable {
+                    name: name.to_owned(),
+                    value: value.to_owned(),
+                    type_: None,
+                    evaluate_name: Some(name.to_owned()),
+                    indexed_variables: None,
+                    named_variables: None,
+                    presentation_hint: None,
+                    variables_reference: 0,
+                })
+                .collect(),
+        })
+    }
+
+    fn set_variable(&self, _: SetVariableArguments) -> anyhow::Result<SetVariableResponseBody> {
+        Err(anyhow::anyhow!("Variables can't be changed in a replay"))
+    }
+
+    fn continue_(&self, _: ContinueArguments) -> anyhow::Result<ContinueResponseBody> {
+        self.continue_to(true);
+        Ok(ContinueResponseBody {
+            all_threads_continued: Some(true),
+        })
+    }
+
+    fn next(&self, _: NextArguments) -> anyhow::Result<()> {
+        self.step(true, Step::Over);
+        Ok(())
+    }
+
+    fn step_in(&self, _: StepInArguments) -> anyhow::Result<()> {
+        self.step(true, Step::Into);
+        Ok(())
+    }
+
+    fn step_out(&self, _: StepOutArguments) -> anyhow::Result<()> {
+        self.step(true, Step::Out);
+        Ok(())
+    }
+
+    fn step_back(&self, _: StepBackArguments) -> anyhow::Result<()> {
+        self.step(false, Step::Over);
+        Ok(())
+    }
+
+    fn reverse_continue(&self, _: ReverseContinueArguments) -> anyhow::Result<()> {
+        self.continue_to(false);
+        Ok(())
+    }
+
+    fn evaluate(&self, x: EvaluateArguments) -> anyhow::Result<EvaluateResponseBody> {
+        let frame = x.frame_id.map_or(0, |x| from_frame_id(x).1);
+        let state = self.state.lock().unwrap();
+        let n = Self::frame_step(&state, frame)?;
+        let expr = x.expression.trim();
+        let result = state
+            .trace
+            .locals(n)
+            .get(expr)
+            .map(|x| (*x).to_owned())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Only recorded locals can be inspected in a replay, not `{}`",
+                    expr
+                )
+            })?;
+        Ok(EvaluateResponseBody {
+            indexed_variables: None,
+            named_variables: None,
+            presentation_hint: None,
+            result,
+            type_: None,
+            variables_reference: 0.0,
+        })
+    }
+
+    fn set_expression(
+        &self,
+        _: SetExpressionArguments,
+    ) -> anyhow::Result<SetExpressionResponseBody> {
+        Err(anyhow::anyhow!("Expressions can't be changed in a replay"))
+    }
+}
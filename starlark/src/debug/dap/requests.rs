@@ -34,16 +34,36 @@ pub(crate) trait DebugServer {
     fn stack_trace(&self, x: StackTraceArguments) -> anyhow::Result<StackTraceResponseBody>;
     fn scopes(&self, x: ScopesArguments) -> anyhow::Result<ScopesResponseBody>;
     fn variables(&self, x: VariablesArguments) -> anyhow::Result<VariablesResponseBody>;
+    fn set_variable(&self, x: SetVariableArguments) -> anyhow::Result<SetVariableResponseBody>;
     fn continue_(&self, x: ContinueArguments) -> anyhow::Result<ContinueResponseBody>;
+    fn next(&self, x: NextArguments) -> anyhow::Result<()>;
+    fn step_in(&self, x: StepInArguments) -> anyhow::Result<()>;
+    fn step_out(&self, x: StepOutArguments) -> anyhow::Result<()>;
+    fn step_back(&self, _x: StepBackArguments) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!("Can only step back when replaying a trace"))
+    }
+    fn reverse_continue(&self, _x: ReverseContinueArguments) -> anyhow::Result<()> {
+        Err(anyhow::anyhow!(
+            "Can only continue backwards when replaying a trace"
+        ))
+    }
     fn evaluate(&self, x: EvaluateArguments) -> anyhow::Result<EvaluateResponseBody>;
+    fn set_expression(
+        &self,
+        x: SetExpressionArguments,
+    ) -> anyhow::Result<SetExpressionResponseBody>;
     fn disconnect(&self, _x: DisconnectArguments) -> anyhow::Result<()> {
         Ok(())
     }
 }
 
 pub(crate) fn dispatch(server: &impl DebugServer, r: &Request) -> Response {
-    fn arg<T: for<'a> Deserialize<'a>>(r: &Request) -> T {
-        serde_json::from_value(r.arguments.clone().unwrap()).unwrap()
+    fn arg<T: for<'a> Deserialize<'a>>(r: &Request) -> anyhow::Result<T> {
+        // Arguments are optional for requests whose arguments are all optional.
+        let x = r.arguments.clone();
+        Ok(serde_json::from_value(
+            x.unwrap_or_else(|| Value::Object(Map::new())),
+        )?)
     }
 
     fn arg_extra(r: &Request) -> Map<String, Value> {
@@ -74,18 +94,27 @@ pub(crate) fn dispatch(server: &impl DebugServer, r: &Request) -> Response {
     }
 
     match r.command.as_str() {
-        "initialize" => ret(r, server.initialize(arg(r))),
-        "setBreakpoints" => ret_some(r, server.set_breakpoints(arg(r))),
-        "setExceptionBreakpoints" => ret_none(r, server.set_exception_breakpoints(arg(r))),
-        "launch" => ret_none(r, server.launch(arg(r), arg_extra(r))),
+        "initialize" => ret(r, arg(r).and_then(|x| server.initialize(x))),
+        "setBreakpoints" => ret_some(r, arg(r).and_then(|x| server.set_breakpoints(x))),
+        "setExceptionBreakpoints" => {
+            ret_none(r, arg(r).and_then(|x| server.set_exception_breakpoints(x)))
+        }
+        "launch" => ret_none(r, arg(r).and_then(|x| server.launch(x, arg_extra(r)))),
         "threads" => ret_some(r, server.threads()),
         "configurationDone" => ret_none(r, server.configuration_done()),
-        "stackTrace" => ret_some(r, server.stack_trace(arg(r))),
-        "scopes" => ret_some(r, server.scopes(arg(r))),
-        "variables" => ret_some(r, server.variables(arg(r))),
-        "continue" => ret_some(r, server.continue_(arg(r))),
-        "evaluate" => ret_some(r, server.evaluate(arg(r))),
-        "disconnect" => ret_none(r, server.disconnect(arg(r))),
+        "stackTrace" => ret_some(r, arg(r).and_then(|x| server.stack_trace(x))),
+        "scopes" => ret_some(r, arg(r).and_then(|x| server.scopes(x))),
+        "variables" => ret_some(r, arg(r).and_then(|x| server.variables(x))),
+        "setVariable" => ret_some(r, arg(r).and_then(|x| server.set_variable(x))),
+        "continue" => ret_some(r, arg(r).and_then(|x| server.continue_(x))),
+        "next" => ret_none(r, arg(r).and_then(|x| server.next(x))),
+        "stepIn" => ret_none(r, arg(r).and_then(|x| server.step_in(x))),
+        "stepOut" => ret_none(r, arg(r).and_then(|x| server.step_out(x))),
+        "stepBack" => ret_none(r, arg(r).and_then(|x| server.step_back(x))),
+        "reverseContinue" => ret_none(r, arg(r).and_then(|x| server.reverse_continue(x))),
+        "evaluate" => ret_some(r, arg(r).and_then(|x| server.evaluate(x))),
+        "setExpression" => ret_some(r, arg(r).and_then(|x| server.set_expression(x))),
+        "disconnect" => ret_none(r, arg(r).and_then(|x| server.disconnect(x))),
         _ => ret_none(r, Err(anyhow::anyhow!("Unknown command: {}", r.command))),
     }
 }
@@ -0,0 +1,59 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Lowest level stream communication as JSON, where each message is preceded by
+//! a `Content-Length` header.
+
+use std::io::BufRead;
+use std::io::Write;
+
+use serde_json::Value;
+
+#[derive(Debug, thiserror::Error)]
+enum StreamError {
+    #[error("DAP message has no `Content-Length` header")]
+    MissingContentLength,
+}
+
+pub(crate) fn send(output: &mut dyn Write, x: &str) -> anyhow::Result<()> {
+    write!(output, "Content-Length: {}\r\n\r\n{}", x.len(), x)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Read the next message, returning `None` at the end of the input.
+pub(crate) fn read(input: &mut dyn BufRead) -> anyhow::Result<Option<(String, Value)>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(x) = line.strip_prefix("Content-Length:") {
+            len = Some(x.trim().parse::<usize>()?);
+        }
+    }
+    let mut res = vec![0u8; len.ok_or(StreamError::MissingContentLength)?];
+    input.read_exact(&mut res)?;
+    let s = String::from_utf8_lossy(&res).into_owned();
+    let value = serde_json::from_str(&s)?;
+    Ok(Some((s, value)))
+}
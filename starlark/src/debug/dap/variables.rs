@@ -0,0 +1,528 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Expand values into their children, so the client can show them as a tree.
+//!
+//! Values only live as long as the evaluator, so rather than holding onto them we hand
+//! out references to the path from a variable in scope to the value, and follow the path
+//! again each time the client asks for the children.
+
+use std::collections::HashMap;
+
+use debugserver_types::Variable;
+use debugserver_types::VariablesArguments;
+
+use crate::collections::SmallMap;
+use crate::eval::Evaluator;
+use crate::values::dict::Dict;
+use crate::values::dict::DictRef;
+use crate::values::function::FUNCTION_TYPE;
+use crate::values::list::List;
+use crate::values::tuple::Tuple;
+use crate::values::Value;
+
+/// How many characters of a value to show before eliding the rest.
+const PREVIEW_LENGTH: usize = 80;
+/// Values with less room than this are elided entirely, rather than showing a prefix.
+const MIN_PREVIEW_LENGTH: usize = 10;
+
+/// How to get from a value to one of its children.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum PathStep {
+    /// An element of a list or tuple.
+    Index(usize),
+    /// The value of the nth entry of a dict.
+    Entry(usize),
+    /// An attribute, such as a field of a struct.
+    Attr(String),
+}
+
+/// Which variables of a frame a scope shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum ScopeKind {
+    Locals,
+    /// The variables of the module the frame is executing.
+    Module,
+    /// The builtins available to the frame.
+    Globals,
+}
+
+impl ScopeKind {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ScopeKind::Locals => "Locals",
+            ScopeKind::Module => "Module",
+            ScopeKind::Globals => "Globals",
+        }
+    }
+
+    /// The variables in this scope of the `frame`-th frame from the top, or `None`
+    /// if there is no such frame, or it was inlined.
+    pub(crate) fn variables<'v>(
+        self,
+        frame: usize,
+        eval: &Evaluator<'v, '_>,
+    ) -> Option<SmallMap<String, Value<'v>>> {
+        match self {
+            ScopeKind::Locals => eval.local_variables_at_frame(frame),
+            ScopeKind::Module => eval.module_variables_at_frame(frame),
+            ScopeKind::Globals => eval.globals_at_frame(frame),
+        }
+    }
+}
+
+/// A value shown in the client, as the path to it from a scope of the paused frames.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct VariablePath {
+    scope: ScopeKind,
+    /// The thread whose stack the frame is on.
+    thread: i64,
+    /// The frame of the scope, counting from the top of the stack.
+    frame: usize,
+    /// The variable the path starts from, or `None` for the whole scope.
+    root: Option<String>,
+    steps: Vec<PathStep>,
+}
+
+impl VariablePath {
+    pub(crate) fn scope(scope: ScopeKind, thread: i64, frame: usize) -> Self {
+        Self {
+            scope,
+            thread,
+            frame,
+            root: None,
+            steps: Vec::new(),
+        }
+    }
+
+    pub(crate) fn thread(&self) -> i64 {
+        self.thread
+    }
+
+    /// The frame of the scope, counting from the top of the stack.
+    pub(crate) fn frame(&self) -> usize {
+        self.frame
+    }
+
+    fn child(&self, step: PathStep) -> Self {
+        let mut steps = self.steps.clone();
+        steps.push(step);
+        Self {
+            root: self.root.clone(),
+            steps,
+            ..*self
+        }
+    }
+}
+
+/// The variable references handed out since the evaluator last paused. References are
+/// only valid while paused, and the same path is always given the same reference.
+#[derive(Debug, Default)]
+pub(crate) struct VariableReferences {
+    paths: Vec<VariablePath>,
+    ids: HashMap<VariablePath, i64>,
+}
+
+impl VariableReferences {
+    /// The reference for a path, which is never 0, as that means "no children".
+    pub(crate) fn reference(&mut self, path: VariablePath) -> i64 {
+        if let Some(id) = self.ids.get(&path) {
+            return *id;
+        }
+        self.paths.push(path.clone());
+        let id = self.paths.len() as i64;
+        self.ids.insert(path, id);
+        id
+    }
+
+    pub(crate) fn path(&self, reference: i64) -> Option<&VariablePath> {
+        self.paths.get((reference as usize).checked_sub(1)?)
+    }
+
+    /// Forget all references, as the values may have changed.
+    pub(crate) fn clear(&mut self) {
+        self.paths.clear();
+        self.ids.clear();
+    }
+}
+
+/// The children of a value which are shown when it is expanded.
+enum Children<'v> {
+    /// The elements of a list or tuple, which the client can page through.
+    Indexed(&'v [Value<'v>]),
+    /// The entries of a dict.
+    Entries(DictRef<'v>),
+    /// The attributes of any other value, excluding methods.
+    Attrs(Vec<(String, Value<'v>)>),
+}
+
+impl<'v> Children<'v> {
+    fn new(x: Value<'v>, eval: &Evaluator<'v, '_>) -> Self {
+        if let Some(xs) = List::from_value(x) {
+            Children::Indexed(xs.content())
+        } else if let Some(xs) = Tuple::from_value(x) {
+            Children::Indexed(xs.content())
+        } else if let Some(xs) = Dict::from_value(x) {
+            Children::Entries(xs)
+        } else {
+            Children::Attrs(
+                x.dir_attr()
+                    .into_iter()
+                    .filter_map(|attr| {
+                        let value = x.get_attr(&attr, eval.heap()).ok()??;
+                        (value.get_type() != FUNCTION_TYPE).then(|| (attr, value))
+                    })
+                    .collect(),
+            )
+        }
+    }
+
+    /// The number of indexed and named children.
+    fn counts(&self) -> (usize, usize) {
+        match self {
+            Children::Indexed(xs) => (xs.len(), 0),
+            Children::Entries(xs) => (0, xs.len()),
+            Children::Attrs(xs) => (0, xs.len()),
+        }
+    }
+
+    fn get(&self, step: &PathStep) -> Option<Value<'v>> {
+        match (self, step) {
+            (Children::Indexed(xs), PathStep::Index(i)) => xs.get(*i).copied(),
+            (Children::Entries(xs), PathStep::Entry(i)) => xs.iter().nth(*i).map(|(_, v)| v),
+            (Children::Attrs(xs), PathStep::Attr(name)) => {
+                xs.iter().find(|(x, _)| x == name).map(|(_, v)| *v)
+            }
+            _ => None,
+        }
+    }
+
+    /// Each child, with its name, the step to it and the expression to evaluate it.
+    fn list(&self, parent: &str) -> Vec<(String, PathStep, String, Value<'v>)> {
+        match self {
+            Children::Indexed(xs) => xs
+                .iter()
+                .enumerate()
+                .map(|(i, x)| {
+                    let name = i.to_string();
+                    let expr = format!("{}[{}]", parent, i);
+                    (name, PathStep::Index(i), expr, *x)
+                })
+                .collect(),
+            Children::Entries(xs) => xs
+                .iter()
+                .enumerate()
+                .map(|(i, (k, v))| {
+                    let name = k.to_repr();
+                    let expr = format!("{}[{}]", parent, name);
+                    (name, PathStep::Entry(i), expr, v)
+                })
+                .collect(),
+            Children::Attrs(xs) => xs
+                .iter()
+                .map(|(name, x)| {
+                    let expr = format!("{}.{}", parent, name);
+                    (name.clone(), PathStep::Attr(name.clone()), expr, *x)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Write `x` to `res`, eliding anything which would go past `end`. Returns `true` if
+/// anything was elided, in which case the caller shouldn't write any more.
+fn write_preview(x: Value, res: &mut String, end: usize) -> bool {
+    fn items<'v>(
+        res: &mut String,
+        end: usize,
+        open: &str,
+        close: &str,
+        xs: impl ExactSizeIterator<Item = (Option<Value<'v>>, Value<'v>)>,
+    ) -> bool {
+        res.push_str(open);
+        let singleton = xs.len() == 1;
+        let mut elided = false;
+        for (i, (k, v)) in xs.enumerate() {
+            if i != 0 {
+                res.push_str(", ");
+            }
+            if res.len() >= end {
+                res.push_str("...");
+                elided = true;
+                break;
+            }
+            // An elided child has already written `...`.
+            elided = k.map_or(false, |k| {
+                let elided = write_preview(k, res, end);
+                res.push_str(": ");
+                elided
+            }) || write_preview(v, res, end);
+            if elided {
+                break;
+            }
+        }
+        if singleton && open == "(" {
+            res.push(',');
+        }
+        res.push_str(close);
+        elided
+    }
+
+    if let Some(xs) = List::from_value(x) {
+        items(res, end, "[", "]", xs.iter().map(|x| (None, x)))
+    } else if let Some(xs) = Tuple::from_value(x) {
+        items(res, end, "(", ")", xs.content().iter().map(|x| (None, *x)))
+    } else if let Some(xs) = Dict::from_value(x) {
+        items(res, end, "{", "}", xs.iter().map(|(k, v)| (Some(k), v)))
+    } else {
+        let repr = x.to_repr();
+        let room = end.saturating_sub(res.len());
+        match repr.char_indices().nth(room) {
+            None => {
+                res.push_str(&repr);
+                false
+            }
+            Some((i, _)) => {
+                // Cutting short values in the middle would be misleading.
+                if room >= MIN_PREVIEW_LENGTH {
+                    res.push_str(&repr[..i]);
+                }
+                res.push_str("...");
+                true
+            }
+        }
+    }
+}
+
+/// A short representation of a value, which elides the middle of long values.
+pub(crate) fn preview(x: Value) -> String {
+    let mut res = String::new();
+    write_preview(x, &mut res, PREVIEW_LENGTH);
+    res
+}
+
+/// The variables of the scope a path starts from.
+fn scope_variables<'v>(
+    path: &VariablePath,
+    eval: &Evaluator<'v, '_>,
+) -> anyhow::Result<SmallMap<String, Value<'v>>> {
+    path.scope.variables(path.frame, eval).ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} of frame {} are not available",
+            path.scope.name(),
+            path.frame
+        )
+    })
+}
+
+/// Follow a path from its scope, returning the value and an expression which evaluates to it.
+fn resolve<'v>(
+    path: &VariablePath,
+    eval: &Evaluator<'v, '_>,
+) -> anyhow::Result<Option<(Value<'v>, String)>> {
+    let root = match &path.root {
+        None => return Ok(None),
+        Some(root) => root,
+    };
+    let mut value = match scope_variables(path, eval)?.get(root) {
+        Some(value) => *value,
+        None => return Err(anyhow::anyhow!("Variable `{}` is no longer defined", root)),
+    };
+    let mut expr = root.clone();
+    for step in &path.steps {
+        let children = Children::new(value, eval);
+        value = children
+            .get(step)
+            .ok_or_else(|| anyhow::anyhow!("`{}` has changed since it was expanded", expr))?;
+        expr = children
+            .list(&expr)
+            .into_iter()
+            .find(|(_, x, _, _)| x == step)
+            .unwrap()
+            .2;
+    }
+    Ok(Some((value, expr)))
+}
+
+/// Describe a value for the client. If it has children, also return the path to use
+/// to fetch them, for which the caller should assign a reference.
+pub(crate) fn variable<'v>(
+    name: String,
+    x: Value<'v>,
+    expr: Option<String>,
+    path: VariablePath,
+    eval: &Evaluator<'v, '_>,
+) -> (Variable, Option<VariablePath>) {
+    let (indexed, named) = Children::new(x, eval).counts();
+    let count = |n: usize| if n == 0 { None } else { Some(n as i64) };
+    let expandable = indexed + named > 0;
+    (
+        Variable {
+            name,
+            value: preview(x),
+            type_: Some(x.get_type().to_owned()),
+            evaluate_name: expr,
+            indexed_variables: count(indexed),
+            named_variables: count(named),
+            presentation_hint: None,
+            variables_reference: 0,
+        },
+        expandable.then(|| path),
+    )
+}
+
+/// The children of the value at a path, restricted to those requested by the client.
+pub(crate) fn variables(
+    path: &VariablePath,
+    args: &VariablesArguments,
+    eval: &Evaluator,
+) -> anyhow::Result<Vec<(Variable, Option<VariablePath>)>> {
+    let mut res = Vec::new();
+    match resolve(path, eval)? {
+        None => {
+            for (name, value) in scope_variables(path, eval)? {
+                let path = VariablePath {
+                    root: Some(name.clone()),
+                    ..path.clone()
+                };
+                res.push(variable(name.clone(), value, Some(name), path, eval));
+            }
+        }
+        Some((value, expr)) => {
+            let children = Children::new(value, eval);
+            let indexed = matches!(children, Children::Indexed(_));
+            let wanted = match args.filter.as_deref() {
+                Some("indexed") => indexed,
+                Some("named") => !indexed,
+                _ => true,
+            };
+            if wanted {
+                for (name, step, expr, value) in children.list(&expr) {
+                    res.push(variable(name, value, Some(expr), path.child(step), eval));
+                }
+            }
+        }
+    }
+    let start = args.start.unwrap_or_default().max(0) as usize;
+    let count = match args.count {
+        Some(count) if count > 0 => count as usize,
+        _ => usize::MAX,
+    };
+    Ok(res.into_iter().skip(start).take(count).collect())
+}
+
+/// Assign `value` to the child called `name` of the value at a path, as shown by
+/// [`variables`], and describe the child afterwards.
+pub(crate) fn set_variable<'v>(
+    path: &VariablePath,
+    name: &str,
+    value: Value<'v>,
+    eval: &mut Evaluator<'v, '_>,
+) -> anyhow::Result<(Variable, Option<VariablePath>)> {
+    let (container, expr) = match resolve(path, eval)? {
+        None => {
+            match path.scope {
+                ScopeKind::Locals => eval.set_local_variable_at_frame(path.frame, name, value)?,
+                ScopeKind::Module => eval.set_module_variable_at_frame(path.frame, name, value)?,
+                ScopeKind::Globals => {
+                    return Err(anyhow::anyhow!("Globals can't be assigned"));
+                }
+            }
+            let path = VariablePath {
+                root: Some(name.to_owned()),
+                ..path.clone()
+            };
+            return Ok(variable(
+                name.to_owned(),
+                value,
+                Some(name.to_owned()),
+                path,
+                eval,
+            ));
+        }
+        Some(x) => x,
+    };
+    let children = Children::new(container, eval);
+    let (name, step, expr, _) = children
+        .list(&expr)
+        .into_iter()
+        .find(|(x, _, _, _)| x == name)
+        .ok_or_else(|| anyhow::anyhow!("`{}` has no child `{}`", expr, name))?;
+    let key = match (&children, &step) {
+        (Children::Indexed(_), PathStep::Index(i)) => Some(eval.heap().alloc(*i as i32)),
+        (Children::Entries(xs), PathStep::Entry(i)) => xs.iter().nth(*i).map(|(k, _)| k),
+        _ => None,
+    };
+    // A dict is borrowed while we hold its entries.
+    drop(children);
+    match key {
+        Some(key) => container.set_at(key, value)?,
+        None => container.set_attr(&name, value)?,
+    }
+    Ok(variable(name, value, Some(expr), path.child(step), eval))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::environment::Globals;
+    use crate::environment::Module;
+    use crate::syntax::AstModule;
+    use crate::syntax::Dialect;
+
+    #[test]
+    fn test_preview() {
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module);
+        let mut check = |code: &str, expect: &str| {
+            let ast = AstModule::parse("x.star", code.to_owned(), &Dialect::Extended).unwrap();
+            let x = eval.eval_module(ast, &Globals::standard()).unwrap();
+            assert_eq!(preview(x), expect);
+        };
+        check("[1, (2,), {'a': None}]", "[1, (2,), {\"a\": None}]");
+        check(
+            "list(range(100))",
+            "[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, ...]",
+        );
+        check("'x' * 100", &format!("\"{}...", "x".repeat(79)));
+        check(
+            "{'x' * 50: ['y' * 50], 'z': 1}",
+            &format!("{{\"{}\": [\"{}...]}}", "x".repeat(50), "y".repeat(23)),
+        );
+        check(
+            "{'x' * 70: ['y' * 20]}",
+            &format!("{{\"{}\": [...]}}", "x".repeat(70)),
+        );
+        check("[1, 'z' * 100]", &format!("[1, \"{}...]", "z".repeat(75)));
+    }
+
+    #[test]
+    fn test_variable_references() {
+        let mut refs = VariableReferences::default();
+        let a = VariablePath::scope(ScopeKind::Locals, 0, 0).child(PathStep::Index(1));
+        let b = VariablePath::scope(ScopeKind::Locals, 0, 0).child(PathStep::Attr("b".to_owned()));
+        let c = VariablePath::scope(ScopeKind::Module, 0, 0).child(PathStep::Index(1));
+        assert_eq!(refs.reference(a.clone()), 1);
+        assert_eq!(refs.reference(b.clone()), 2);
+        assert_eq!(refs.reference(a), 1);
+        assert_eq!(refs.reference(c), 3);
+        assert_eq!(refs.path(2), Some(&b));
+        assert_eq!(refs.path(0), None);
+        refs.clear();
+        assert_eq!(refs.path(1), None);
+    }
+}
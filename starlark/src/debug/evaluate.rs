@@ -18,12 +18,25 @@
 use std::mem;
 
 use crate::collections::SmallMap;
-use crate::debug::inspect::to_scope_names_by_local_slot_id;
+use crate::collections::SmallSet;
+use crate::debug::inspect::get_frame_slot;
+use crate::debug::inspect::set_frame_slot;
+use crate::debug::inspect::to_def_info;
+use crate::debug::inspect::to_frozen_module;
+use crate::debug::inspect::to_globals;
+use crate::environment::FrozenModuleRef;
+use crate::environment::Globals;
+use crate::eval::bc::frame::BcFramePtr;
+use crate::eval::runtime::evaluator::EvaluatorError;
+use crate::eval::runtime::slots::LocalSlotId;
 use crate::eval::runtime::slots::LocalSlotIdCapturedOrNot;
 use crate::eval::Evaluator;
 use crate::syntax::AstModule;
+use crate::values::function::NativeFunction;
+use crate::values::FrozenRef;
 use crate::values::FrozenStringValue;
 use crate::values::Value;
+use crate::values::ValueLike;
 
 impl<'v, 'a> Evaluator<'v, 'a> {
     /// Evaluate statements in the existing context. This function is designed for debugging,
@@ -33,6 +46,75 @@ impl<'v, 'a> Evaluator<'v, 'a> {
     /// nested definitions etc. It would be a bad idea to rely on the results of continued execution
     /// after evaluating stuff randomly.
     pub fn eval_statements(&mut self, statements: AstModule) -> anyhow::Result<Value<'v>> {
+        let locals = self
+            .call_stack
+            .to_function_values()
+            .into_iter()
+            .rev()
+            .find(|function| to_def_info(*function).is_some())
+            .map(|function| (function, self.current_frame));
+        // Called from `breakpoint` or `debug_evaluate` we want the scope of their caller,
+        // but called from a `before_stmt` callback we want the scope of the top frame.
+        let called_from_native = self
+            .call_stack
+            .top_nth_function(0)?
+            .downcast_ref::<NativeFunction>()
+            .is_some();
+        let globals = if called_from_native {
+            self.top_second_frame_def_info_for_debugger()?.globals
+        } else {
+            self.top_frame_def_info()?.globals
+        };
+        self.eval_statements_in(statements, locals, self.module_variables, globals, false)
+    }
+
+    /// Like [`eval_statements`](Evaluator::eval_statements), but in the scope of the `n`-th frame
+    /// from the top of [`call_stack`](Evaluator::call_stack), where `n` equal to the number of
+    /// frames is the module itself. Frames other than the top need before statement
+    /// instrumentation, as for [`local_variables_at_frame`](Evaluator::local_variables_at_frame).
+    pub fn eval_statements_at_frame(
+        &mut self,
+        statements: AstModule,
+        n: usize,
+    ) -> anyhow::Result<Value<'v>> {
+        self.eval_statements_at_nth_frame(statements, n, false)
+    }
+
+    /// Like [`eval_statements_at_frame`](Evaluator::eval_statements_at_frame), but variables
+    /// the statements define, other than the local variables of the frame, remain defined
+    /// in the module afterwards, so later evaluations can use them, as in a REPL.
+    pub fn eval_statements_at_frame_for_repl(
+        &mut self,
+        statements: AstModule,
+        n: usize,
+    ) -> anyhow::Result<Value<'v>> {
+        self.eval_statements_at_nth_frame(statements, n, true)
+    }
+
+    fn eval_statements_at_nth_frame(
+        &mut self,
+        statements: AstModule,
+        n: usize,
+        keep_new_variables: bool,
+    ) -> anyhow::Result<Value<'v>> {
+        let (function, frame) = self
+            .call_stack
+            .nth_frame_for_debugger(n, self.current_frame)
+            .ok_or(EvaluatorError::NoSuchFrame(n))?;
+        let locals = to_def_info(function).map(|_| (function, frame));
+        let module = to_frozen_module(function);
+        let globals = to_globals(function).unwrap_or(self.module_def_info.globals);
+        self.eval_statements_in(statements, locals, module, globals, keep_new_variables)
+    }
+
+    fn eval_statements_in(
+        &mut self,
+        statements: AstModule,
+        locals: Option<(Value<'v>, BcFramePtr<'v>)>,
+        frozen: Option<FrozenRef<'static, FrozenModuleRef>>,
+        globals: FrozenRef<'static, Globals>,
+        keep_new_variables: bool,
+    ) -> anyhow::Result<Value<'v>> {
         // We are doing a lot of funky stuff here. It's amazing anything works, so let's not push our luck with GC.
         self.disable_gc();
 
@@ -53,49 +135,57 @@ impl<'v, 'a> Evaluator<'v, 'a> {
             .map(|(name, slot)| (name, self.module_env.slots().get_slot(slot)))
             .collect();
 
+        // The variables we push, which must be removed again afterwards
+        let mut pushed = SmallSet::new();
+
         // Push all the frozen variables into the module
-        if let Some(frozen) = &self.module_variables {
+        if let Some(frozen) = &frozen {
             for (name, slot) in frozen.0.names.symbols() {
                 if let Some(value) = frozen.0.get_slot(slot) {
-                    self.module_env.set(&name, value.to_value())
+                    self.module_env.set(&name, value.to_value());
+                    pushed.insert(name);
                 }
             }
         }
 
         // Push all local variables into the module
-        let locals = self
-            .call_stack
-            .to_function_values()
-            .into_iter()
-            .rev()
-            .find_map(to_scope_names_by_local_slot_id);
-        if let Some(names) = &locals {
+        let names = locals.map(|(function, _)| to_def_info(function).unwrap().used);
+        let mut original_locals = Vec::new();
+        if let (Some(names), Some((_, frame))) = (names, locals) {
             for (slot, name) in names.iter().enumerate() {
-                if let Some(value) = self
-                    .current_frame
-                    .get_slot_slow(LocalSlotIdCapturedOrNot(slot as u32))
-                {
-                    self.module_env.set(name, value)
+                let value = get_frame_slot(frame, LocalSlotIdCapturedOrNot(slot as u32));
+                if let Some(value) = value {
+                    self.module_env.set(name, value);
                 }
+                original_locals.push(value);
+                pushed.insert(*name);
             }
         }
 
         let orig_module_variables = mem::replace(&mut self.module_variables, None);
-        let globals = self.top_second_frame_def_info_for_debugger()?.globals;
-        let res = self.eval_module(statements, &globals);
+        let mut res = self.eval_module(statements, &globals);
         self.module_variables = orig_module_variables;
 
         // Now put the Module back how it was before we started, as best we can
-        // and move things into locals if that makes sense
-        if let Some(names) = &locals {
+        // and move the locals which changed back, the same way the debugger assigns them
+        if let (Some(names), Some((function, frame))) = (names, locals) {
             for (slot, name) in names.iter().enumerate() {
-                if let Some(value) = self.module_env.get(name) {
-                    self.current_frame
-                        .set_slot_slow(LocalSlotIdCapturedOrNot(slot as u32), value)
+                let value = match self.module_env.get(name) {
+                    Some(value) => value,
+                    None => continue,
+                };
+                if original_locals[slot].map_or(false, |x| x.ptr_eq(value)) {
+                    continue;
+                }
+                let assigned =
+                    set_frame_slot(self, function, frame, LocalSlotId(slot as u32), value);
+                if let (Ok(_), Err(e)) = (&res, assigned) {
+                    res = Err(e);
                 }
             }
             for (name, slot) in self.module_env.names().all_names() {
                 match original_module.get(&name) {
+                    None if keep_new_variables && !pushed.contains(&name) => {}
                     None => self.module_env.names().hide_name(&name),
                     Some(Some(value)) => self.module_env.slots().set_slot(slot, *value),
                     _ => {} // No way to unassign a previously assigned value yet
@@ -109,11 +199,16 @@ impl<'v, 'a> Evaluator<'v, 'a> {
 
 #[cfg(test)]
 mod tests {
+    use std::cell::RefCell;
+
     use itertools::Itertools;
 
     use super::*;
     use crate::assert;
+    use crate::codemap::FileSpanRef;
+    use crate::environment::Globals;
     use crate::environment::GlobalsBuilder;
+    use crate::environment::Module;
     use crate::syntax::Dialect;
     use crate::{self as starlark};
 
@@ -161,6 +256,19 @@ assert_eq(foo(1, 2, 3), 2)
 "#,
         );
 
+        // Check captured variables are read and assigned in place
+        a.pass(
+            r#"
+def foo(x):
+    def bar():
+        return x
+    assert_eq(debug_evaluate("x"), 1)
+    debug_evaluate("x = 2")
+    return [x, bar()]
+assert_eq(foo(1), [2, 2])
+"#,
+        );
+
         // Check we can access module-level and globals
         a.pass(
             r#"
@@ -186,4 +294,103 @@ def bar(y):
         );
         a.pass("load('test', 'bar'); assert_eq(bar(4), 4 + 7 + 2)");
     }
+
+    #[test]
+    fn test_debug_evaluate_before_stmt() {
+        // A debugger evaluates in the scope of the statement it is paused at.
+        let results = RefCell::new(Vec::new());
+        let before_stmt = |span: FileSpanRef, eval: &mut Evaluator| {
+            if span.resolve_span().begin_line == 2 {
+                let ast = AstModule::parse("interactive", "x + y".to_owned(), &Dialect::Extended);
+                let res = eval.eval_statements(ast.unwrap()).unwrap();
+                results.borrow_mut().push(res.unpack_int().unwrap());
+            }
+        };
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module);
+        eval.before_stmt(&before_stmt);
+        let code = "y = 10\ndef f(x):\n    return x\n[f(x) for x in [1, 2]]\n";
+        let ast = AstModule::parse("test.star", code.to_owned(), &Dialect::Extended).unwrap();
+        eval.eval_module(ast, &Globals::standard()).unwrap();
+        assert_eq!(*results.borrow(), vec![11, 12]);
+    }
+
+    #[test]
+    fn test_debug_evaluate_at_frame() {
+        // A debugger evaluates in the scope of the frame selected by the user.
+        let results = RefCell::new(Vec::new());
+        let before_stmt = |span: FileSpanRef, eval: &mut Evaluator| {
+            if span.resolve_span().begin_line == 1 {
+                for (n, code) in [(0, "x"), (1, "y = y * 10; y"), (2, "z"), (3, "w")] {
+                    let ast = AstModule::parse("interactive", code.to_owned(), &Dialect::Extended);
+                    let res = eval.eval_statements_at_frame(ast.unwrap(), n);
+                    results.borrow_mut().push(res.map(|x| x.to_string()).ok());
+                }
+            }
+        };
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module);
+        eval.before_stmt(&before_stmt);
+        let code = "def f(x):
+    return x
+def g(y):
+    y += 1
+    return f(y) + y
+z = 3
+z = g(z)
+";
+        let ast = AstModule::parse("test.star", code.to_owned(), &Dialect::Extended).unwrap();
+        eval.eval_module(ast, &Globals::standard()).unwrap();
+        assert_eq!(
+            *results.borrow(),
+            vec![
+                Some("4".to_owned()),
+                Some("40".to_owned()),
+                Some("3".to_owned()),
+                None
+            ]
+        );
+        // The assignment to `y` in the caller frame is visible after returning.
+        assert_eq!(module.get("z").unwrap().unpack_int(), Some(44));
+    }
+
+    #[test]
+    fn test_debug_evaluate_for_repl() {
+        // Variables defined in a REPL remain defined, unlike other evaluations.
+        let results = RefCell::new(Vec::new());
+        let before_stmt = |span: FileSpanRef, eval: &mut Evaluator| {
+            if span.resolve_span().begin_line == 1 {
+                let mut run = |code: &str, repl: bool| {
+                    let ast = AstModule::parse("interactive", code.to_owned(), &Dialect::Extended);
+                    let res = if repl {
+                        eval.eval_statements_at_frame_for_repl(ast.unwrap(), 0)
+                    } else {
+                        eval.eval_statements_at_frame(ast.unwrap(), 0)
+                    };
+                    results.borrow_mut().push(res.map(|x| x.to_string()).ok());
+                };
+                run("v = 1", false);
+                run("v", false);
+                run("w = x + 1; x = 5", true);
+                run("w + x", true);
+            }
+        };
+        let module = Module::new();
+        let mut eval = Evaluator::new(&module);
+        eval.before_stmt(&before_stmt);
+        let code = "def f(x):\n    return x\nres = f(1)\n";
+        let ast = AstModule::parse("test.star", code.to_owned(), &Dialect::Extended).unwrap();
+        eval.eval_module(ast, &Globals::standard()).unwrap();
+        assert_eq!(
+            *results.borrow(),
+            vec![
+                Some("None".to_owned()),
+                None,
+                Some("None".to_owned()),
+                Some("7".to_owned())
+            ]
+        );
+        // Assignments to local variables still go back to the frame.
+        assert_eq!(module.get("res").unwrap().unpack_int(), Some(5));
+    }
 }
@@ -15,11 +15,24 @@
  * limitations under the License.
  */
 
+use crate::collections::Hashed;
 use crate::collections::SmallMap;
+use crate::environment::FrozenModuleRef;
+use crate::environment::Globals;
+use crate::eval::bc::frame::BcFramePtr;
 use crate::eval::compiler::def::Def;
+use crate::eval::compiler::def::DefInfo;
 use crate::eval::compiler::def::FrozenDef;
+use crate::eval::runtime::evaluator::EvaluatorError;
+use crate::eval::runtime::slots::LocalCapturedSlotId;
+use crate::eval::runtime::slots::LocalSlotId;
 use crate::eval::runtime::slots::LocalSlotIdCapturedOrNot;
 use crate::eval::Evaluator;
+use crate::values::layout::value_captured::is_value_captured;
+use crate::values::layout::value_captured::value_captured_get;
+use crate::values::layout::value_captured::FrozenValueCaptured;
+use crate::values::layout::value_captured::ValueCaptured;
+use crate::values::FrozenRef;
 use crate::values::FrozenStringValue;
 use crate::values::Value;
 use crate::values::ValueLike;
@@ -33,6 +46,26 @@ pub(crate) fn to_scope_names_by_local_slot_id<'v>(x: Value<'v>) -> Option<&'v [F
     }
 }
 
+/// The definition of a function, or `None` if it is not a `def`.
+pub(crate) fn to_def_info(x: Value) -> Option<FrozenRef<'static, DefInfo>> {
+    if x.unpack_frozen().is_some() {
+        x.downcast_ref::<FrozenDef>().map(|x| x.def_info)
+    } else {
+        x.downcast_ref::<Def>().map(|x| x.def_info)
+    }
+}
+
+/// The module a function was defined in, or `None` if it is the module being evaluated.
+pub(crate) fn to_frozen_module(x: Value) -> Option<FrozenRef<'static, FrozenModuleRef>> {
+    if x.unpack_frozen().is_some() {
+        x.downcast_ref::<FrozenDef>()
+            .and_then(|x| x.module_for_debugger())
+    } else {
+        x.downcast_ref::<Def>()
+            .and_then(|x| x.module_for_debugger())
+    }
+}
+
 impl<'v, 'a> Evaluator<'v, 'a> {
     /// Obtain the local variables currently in scope. When at top-level these will be
     /// [`Module`](crate::environment::Module) variables, otherwise local definitions. The precise number of variables
@@ -40,6 +73,188 @@ impl<'v, 'a> Evaluator<'v, 'a> {
     pub fn local_variables(&self) -> SmallMap<String, Value<'v>> {
         inspect_local_variables(self).unwrap_or_else(|| inspect_module_variables(self))
     }
+
+    /// Obtain the local variables of the `n`-th frame from the top of [`call_stack`](Evaluator::call_stack),
+    /// where `n` equal to the number of frames is the module itself, which has no local variables.
+    /// Returns `None` if there is no such frame, or the function was inlined.
+    /// For `n` of at least 1, the frame is only known if the calls above it were made with
+    /// [`enable_before_stmt_instrumentation`](Evaluator::enable_before_stmt_instrumentation)
+    /// called, or under a [`Debuggee`](crate::debug::dap::Debuggee), and is `None` otherwise.
+    /// The only legitimate use of this function is for debugging.
+    pub fn local_variables_at_frame(&self, n: usize) -> Option<SmallMap<String, Value<'v>>> {
+        let (function, frame) = self
+            .call_stack
+            .nth_frame_for_debugger(n, self.current_frame)?;
+        Some(match to_scope_names_by_local_slot_id(function) {
+            Some(names) => frame_variables(names, frame),
+            None => SmallMap::new(),
+        })
+    }
+
+    /// Obtain the variables of the module the `n`-th frame from the top of
+    /// [`call_stack`](Evaluator::call_stack) is executing, including private ones.
+    /// Returns `None` if there is no such frame, or the function was inlined.
+    /// Frames other than the top need before statement instrumentation, as for
+    /// [`local_variables_at_frame`](Evaluator::local_variables_at_frame).
+    /// The only legitimate use of this function is for debugging.
+    pub fn module_variables_at_frame(&self, n: usize) -> Option<SmallMap<String, Value<'v>>> {
+        let (function, _) = self
+            .call_stack
+            .nth_frame_for_debugger(n, self.current_frame)?;
+        Some(match to_frozen_module(function) {
+            Some(module) => {
+                let mut res = SmallMap::new();
+                for (name, slot) in module.0.names.all_symbols() {
+                    if let Some(v) = module.0.get_slot(slot) {
+                        res.insert(name.as_str().to_owned(), v.to_value());
+                    }
+                }
+                res
+            }
+            None => inspect_module_variables(self),
+        })
+    }
+
+    /// Assign a local variable of the `n`-th frame from the top of [`call_stack`](Evaluator::call_stack).
+    /// Variables captured by nested functions are updated in place, so those functions see the new value.
+    /// If the variable is a parameter with a type annotation, which requires a dialect enabling types,
+    /// the value must match it.
+    /// Fails if there is no such frame, including frames other than the top when the calls
+    /// were made without before statement instrumentation, as for
+    /// [`local_variables_at_frame`](Evaluator::local_variables_at_frame).
+    /// The only legitimate use of this function is for debugging.
+    pub fn set_local_variable_at_frame(
+        &mut self,
+        n: usize,
+        name: &str,
+        value: Value<'v>,
+    ) -> anyhow::Result<()> {
+        let (function, frame) = self
+            .call_stack
+            .nth_frame_for_debugger(n, self.current_frame)
+            .ok_or(EvaluatorError::NoSuchFrame(n))?;
+        let slot = to_def_info(function)
+            .and_then(|def_info| def_info.used.iter().position(|x| x.as_str() == name))
+            .ok_or_else(|| EvaluatorError::NoSuchVariable(name.to_owned(), n))?;
+        set_frame_slot(self, function, frame, LocalSlotId(slot as u32), value)
+    }
+
+    /// Assign a variable of the module the `n`-th frame from the top of
+    /// [`call_stack`](Evaluator::call_stack) is executing. The variable must already exist,
+    /// and variables of frozen modules can't be assigned.
+    /// Frames other than the top need before statement instrumentation, as for
+    /// [`local_variables_at_frame`](Evaluator::local_variables_at_frame).
+    /// The only legitimate use of this function is for debugging.
+    pub fn set_module_variable_at_frame(
+        &mut self,
+        n: usize,
+        name: &str,
+        value: Value<'v>,
+    ) -> anyhow::Result<()> {
+        let (function, _) = self
+            .call_stack
+            .nth_frame_for_debugger(n, self.current_frame)
+            .ok_or(EvaluatorError::NoSuchFrame(n))?;
+        if to_frozen_module(function).is_some() {
+            return Err(EvaluatorError::FrozenVariable(name.to_owned()).into());
+        }
+        let (slot, _) = self
+            .module_env
+            .names()
+            .get_name(Hashed::new(name))
+            .ok_or_else(|| EvaluatorError::NoSuchVariable(name.to_owned(), n))?;
+        self.set_slot_module(slot, value);
+        Ok(())
+    }
+
+    /// Obtain the globals (builtins) available to the `n`-th frame from the top of
+    /// [`call_stack`](Evaluator::call_stack).
+    /// Returns `None` if there is no such frame, or the function was inlined, and for frames
+    /// other than the top without before statement instrumentation, as for
+    /// [`local_variables_at_frame`](Evaluator::local_variables_at_frame).
+    /// The only legitimate use of this function is for debugging.
+    pub fn globals_at_frame(&self, n: usize) -> Option<SmallMap<String, Value<'v>>> {
+        let (function, _) = self
+            .call_stack
+            .nth_frame_for_debugger(n, self.current_frame)?;
+        let globals = to_globals(function)
+            .unwrap_or(self.module_def_info.globals)
+            .as_ref();
+        let mut res = SmallMap::new();
+        for name in globals.names() {
+            if let Some(v) = globals.get(name.as_str()) {
+                res.insert(name.as_str().to_owned(), v);
+            }
+        }
+        Some(res)
+    }
+}
+
+/// The globals a function was compiled against, or `None` if it is not a `def`.
+pub(crate) fn to_globals(x: Value) -> Option<FrozenRef<'static, Globals>> {
+    if x.unpack_frozen().is_some() {
+        x.downcast_ref::<FrozenDef>().map(|x| x.def_info.globals)
+    } else {
+        x.downcast_ref::<Def>().map(|x| x.def_info.globals)
+    }
+}
+
+/// The value of a local slot, looking through captured variables.
+pub(crate) fn get_frame_slot<'v>(
+    frame: BcFramePtr<'v>,
+    slot: LocalSlotIdCapturedOrNot,
+) -> Option<Value<'v>> {
+    let value = frame.get_slot_slow(slot)?;
+    if is_value_captured(value) {
+        value_captured_get(value)
+    } else {
+        Some(value)
+    }
+}
+
+/// Assign a local slot of a frame executing `function`, which must be a `def`,
+/// checking the type of the parameter in the slot, if any.
+pub(crate) fn set_frame_slot<'v>(
+    eval: &Evaluator<'v, '_>,
+    function: Value<'v>,
+    frame: BcFramePtr<'v>,
+    slot: LocalSlotId,
+    value: Value<'v>,
+) -> anyhow::Result<()> {
+    let def_info = to_def_info(function).expect("frame of a def");
+    if let Some(def) = function.downcast_ref::<FrozenDef>() {
+        def.check_slot_type_for_debugger(slot, value)?;
+    } else if let Some(def) = function.downcast_ref::<Def>() {
+        def.check_slot_type_for_debugger(slot, value)?;
+    }
+    let captured = LocalCapturedSlotId(slot.0);
+    match frame.get_slot_slow(slot.to_captured_or_not()) {
+        Some(v) if v.downcast_ref::<FrozenValueCaptured>().is_some() => {
+            let name = def_info.used[slot.0 as usize].as_str().to_owned();
+            return Err(EvaluatorError::FrozenVariable(name).into());
+        }
+        Some(v) if v.downcast_ref::<ValueCaptured>().is_some() => {
+            eval.set_slot_local_captured(frame, captured, value)
+        }
+        None if def_info.used_captured.contains(&slot.to_captured_or_not()) => {
+            eval.set_slot_local_captured(frame, captured, value)
+        }
+        _ => eval.set_slot_local(frame, slot, value),
+    }
+    Ok(())
+}
+
+fn frame_variables<'v>(
+    names: &[FrozenStringValue],
+    frame: BcFramePtr<'v>,
+) -> SmallMap<String, Value<'v>> {
+    let mut res = SmallMap::new();
+    for (slot, name) in names.iter().enumerate() {
+        if let Some(v) = get_frame_slot(frame, LocalSlotIdCapturedOrNot(slot as u32)) {
+            res.insert(name.as_str().to_owned(), v);
+        }
+    }
+    res
 }
 
 fn inspect_local_variables<'v>(eval: &Evaluator<'v, '_>) -> Option<SmallMap<String, Value<'v>>> {
@@ -49,17 +264,7 @@ fn inspect_local_variables<'v>(eval: &Evaluator<'v, '_>) -> Option<SmallMap<Stri
         .into_iter()
         .rev()
         .find_map(to_scope_names_by_local_slot_id)?;
-    let mut res = SmallMap::new();
-    for (slot, name) in names.iter().enumerate() {
-        // TODO(nga): correctly handle captured.
-        if let Some(v) = eval
-            .current_frame
-            .get_slot_slow(LocalSlotIdCapturedOrNot(slot as u32))
-        {
-            res.insert(name.as_str().to_owned(), v);
-        }
-    }
-    Some(res)
+    Some(frame_variables(names, eval.current_frame))
 }
 
 fn inspect_module_variables<'v>(eval: &Evaluator<'v, '_>) -> SmallMap<String, Value<'v>> {
@@ -82,6 +287,8 @@ mod tests {
     use crate::environment::GlobalsBuilder;
     use crate::eval::Evaluator;
     use crate::values::dict::Dict;
+    use crate::values::none::NoneType;
+    use crate::values::Value;
     use crate::{self as starlark};
 
     #[starlark_module]
@@ -97,6 +304,45 @@ mod tests {
             }
             Ok(Dict::new(coerce(sm)))
         }
+
+        fn debug_inspect_frame<'v>(
+            n: i32,
+            eval: &mut Evaluator<'v, '_>,
+        ) -> anyhow::Result<Vec<Vec<String>>> {
+            let names = |xs: Option<SmallMap<String, Value>>| match xs {
+                None => vec!["<none>".to_owned()],
+                Some(xs) => xs.into_iter().map(|(k, _)| k).collect(),
+            };
+            let n = n as usize;
+            Ok(vec![
+                names(eval.local_variables_at_frame(n)),
+                names(eval.module_variables_at_frame(n)),
+                names(eval.globals_at_frame(n))
+                    .into_iter()
+                    .filter(|x| x == "len")
+                    .collect(),
+            ])
+        }
+
+        fn debug_set_local<'v>(
+            n: i32,
+            name: &str,
+            value: Value<'v>,
+            eval: &mut Evaluator<'v, '_>,
+        ) -> anyhow::Result<NoneType> {
+            eval.set_local_variable_at_frame(n as usize, name, value)?;
+            Ok(NoneType)
+        }
+
+        fn debug_set_module<'v>(
+            n: i32,
+            name: &str,
+            value: Value<'v>,
+            eval: &mut Evaluator<'v, '_>,
+        ) -> anyhow::Result<NoneType> {
+            eval.set_module_variable_at_frame(n as usize, name, value)?;
+            Ok(NoneType)
+        }
     }
 
     #[test]
@@ -136,4 +382,91 @@ assert_eq(debug_inspect_variables(), {"root": 12, "f": f, "_ignore": [True]})
 "#,
         );
     }
+
+    #[test]
+    fn test_debug_frame_variables() {
+        let mut a = assert::Assert::new();
+        a.globals_add(debugger);
+        a.setup_eval(|eval| eval.enable_before_stmt_instrumentation());
+        a.module(
+            "lib",
+            r#"
+_private = 1
+def f(a):
+    b = a + 1
+    return debug_inspect_frame(1) + debug_inspect_frame(2) + debug_inspect_frame(3) + debug_inspect_frame(4)
+"#,
+        );
+        a.pass(
+            r#"
+load("lib", "f")
+root = 12
+def g(c):
+    d = c + 1
+    return f(d)
+res = g(1)
+# The frames are `f`, `g` and the module, then there are no more.
+assert_eq(res[0:3], [["a", "b"], ["_private", "f"], ["len"]])
+assert_eq(res[3:6], [["c", "d"], ["f", "root", "g"], ["len"]])
+assert_eq(res[6:9], [[], ["f", "root", "g"], ["len"]])
+assert_eq(res[9:12], [["<none>"], ["<none>"], []])
+"#,
+        );
+    }
+
+    #[test]
+    fn test_debug_set_variables() {
+        let mut a = assert::Assert::new();
+        a.globals_add(debugger);
+        a.setup_eval(|eval| eval.enable_before_stmt_instrumentation());
+        a.module(
+            "lib",
+            r#"
+_private = 1
+def set_private():
+    debug_set_module(1, "_private", 2)
+"#,
+        );
+        a.pass(
+            r#"
+root = 12
+def f(x, y: int.type):
+    def g():
+        return y + z
+    debug_set_local(1, "x", "changed")
+    debug_set_local(1, "y", 20)
+    # `z` is captured by `g`, but not assigned yet.
+    debug_set_local(1, "z", 3)
+    debug_set_module(2, "root", 13)
+    if False:
+        z = 0
+    return [x, y, g()]
+assert_eq(f(1, 2), ["changed", 20, 23])
+assert_eq(root, 13)
+"#,
+        );
+        a.fail(
+            r#"
+def f(y: int.type):
+    debug_set_local(1, "y", "wrong")
+f(1)
+"#,
+            "does not match the type annotation",
+        );
+        a.fail(
+            r#"
+def f():
+    debug_set_local(1, "missing", 1)
+f()
+"#,
+            "No variable `missing` in frame 1",
+        );
+        a.fail(
+            r#"
+load("lib", "set_private")
+set_private()
+"#,
+            "frozen module",
+        );
+    }
 }
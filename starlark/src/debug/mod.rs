@@ -15,6 +15,10 @@
  * limitations under the License.
  */
 
+//! Support for debugging Starlark programs.
+
 mod breakpoint;
+pub mod dap;
 mod evaluate;
 mod inspect;
+pub mod trace;
@@ -0,0 +1,513 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Record the statements a program executes, for inspecting after it has finished, such as
+//! when it failed somewhere a debugger can't be attached. The DAP [`server`](crate::debug::dap::server)
+//! replays a trace when the `launch` request has a `trace` argument naming the file.
+//!
+//! A trace is a file of JSON lines, each one a file, a call, a statement or an error.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use gazebo::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::codemap::FileSpanRef;
+use crate::codemap::ResolvedSpan;
+use crate::debug::dap::variables::preview;
+use crate::environment::Globals;
+use crate::errors::Diagnostic;
+use crate::eval::Evaluator;
+use crate::syntax::AstModule;
+use crate::values::Value;
+
+/// The name of the frame of a module, which the DAP server also uses.
+const MODULE_FRAME: &str = "Root";
+
+/// A line of a trace.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TraceEvent {
+    /// A file, which later statements refer to by `id`.
+    File { id: usize, path: String },
+    /// A function, or module, starting at `depth`. Statements at that depth belong to it
+    /// until the next call at the same depth or shallower.
+    Call { depth: usize, name: String },
+    /// A statement about to be executed, with the 0-based `[begin_line, begin_column,
+    /// end_line, end_column]` of its span, and the previews of the locals which have
+    /// changed since the previous statement of its frame, if recording locals.
+    Stmt {
+        file: usize,
+        span: [usize; 4],
+        depth: usize,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        locals: BTreeMap<String, String>,
+    },
+    /// The previous statement raised an error.
+    Error { message: String },
+}
+
+/// A frame on the stack while recording, to know when a statement belongs to a new one.
+struct RecordedFrame {
+    /// The name and location of the call, which differ between consecutive calls
+    /// at the same depth.
+    key: (String, Option<String>),
+    /// The previews of the locals as of the last statement.
+    locals: HashMap<String, String>,
+}
+
+struct RecorderState {
+    output: Box<dyn Write + Send>,
+    files: HashMap<String, usize>,
+    frames: Vec<RecordedFrame>,
+    /// The first write which failed, reported by [`TraceRecorder::finish`].
+    error: Option<anyhow::Error>,
+}
+
+struct RecorderShared {
+    state: Mutex<RecorderState>,
+    locals: bool,
+    /// How many modules are currently being evaluated, as loads count as a frame deeper.
+    modules: AtomicUsize,
+}
+
+/// Records a trace of the statements executed by the modules evaluated with
+/// [`eval_module`](TraceRecorder::eval_module).
+pub struct TraceRecorder {
+    shared: Arc<RecorderShared>,
+    // Boxed, so that a reference lives as long as the recorder, whatever the evaluator.
+    before_stmt: Box<dyn for<'v, 'a> Fn(FileSpanRef, &mut Evaluator<'v, 'a>) + Send + Sync>,
+    on_error:
+        Box<dyn for<'v, 'a> Fn(&anyhow::Error, FileSpanRef, &mut Evaluator<'v, 'a>) + Send + Sync>,
+}
+
+impl TraceRecorder {
+    /// Write a trace to `output`. If `locals` is set, each statement also records previews of
+    /// the local variables which changed, which makes the trace larger and evaluation slower.
+    pub fn new(output: impl Write + Send + 'static, locals: bool) -> Self {
+        let shared = Arc::new(RecorderShared {
+            state: Mutex::new(RecorderState {
+                output: box output,
+                files: HashMap::new(),
+                frames: Vec::new(),
+                error: None,
+            }),
+            locals,
+            modules: AtomicUsize::new(0),
+        });
+        let before_stmt = {
+            let shared = shared.dupe();
+            box move |span_loc: FileSpanRef, eval: &mut Evaluator| {
+                shared.before_stmt(span_loc, eval)
+            }
+        };
+        let on_error = {
+            let shared = shared.dupe();
+            box move |e: &anyhow::Error, _: FileSpanRef, _: &mut Evaluator| {
+                let message = match e.downcast_ref::<Diagnostic>() {
+                    Some(d) => format!("{:#}", d.message),
+                    None => format!("{:#}", e),
+                };
+                shared.write(&[TraceEvent::Error { message }]);
+            }
+        };
+        Self {
+            shared,
+            before_stmt,
+            on_error,
+        }
+    }
+
+    /// Evaluate a module, recording the statements it executes. Modules it loads are only
+    /// recorded if the loader evaluates them with this recorder too.
+    pub fn eval_module<'v, 'a>(
+        &'a self,
+        ast: AstModule,
+        globals: &Globals,
+        eval: &mut Evaluator<'v, 'a>,
+    ) -> anyhow::Result<Value<'v>> {
+        // Attach once, even if the evaluator is used for several modules.
+        if eval.on_error(&self.on_error) {
+            eval.before_stmt(&self.before_stmt);
+        }
+        let modules = &self.shared.modules;
+        modules.fetch_add(1, Ordering::SeqCst);
+        let res = eval.eval_module(ast, globals);
+        modules.fetch_sub(1, Ordering::SeqCst);
+        res
+    }
+
+    /// Flush the trace, returning the first error writing it.
+    pub fn finish(self) -> anyhow::Result<()> {
+        let mut state = self.shared.state.lock().unwrap();
+        if let Some(e) = state.error.take() {
+            return Err(e);
+        }
+        state.output.flush()?;
+        Ok(())
+    }
+}
+
+impl Debug for TraceRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraceRecorder")
+            .field("locals", &self.shared.locals)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RecorderShared {
+    fn write(&self, events: &[TraceEvent]) {
+        let mut state = self.state.lock().unwrap();
+        if state.error.is_some() {
+            return;
+        }
+        for event in events {
+            let res = serde_json::to_string(event)
+                .map_err(anyhow::Error::from)
+                .and_then(|x| Ok(writeln!(state.output, "{}", x)?));
+            if let Err(e) = res {
+                state.error = Some(e);
+                return;
+            }
+        }
+    }
+
+    fn before_stmt(&self, span_loc: FileSpanRef, eval: &mut Evaluator) {
+        let frames = eval.call_stack().into_frames();
+        let depth = self.modules.load(Ordering::SeqCst).saturating_sub(1) + frames.len();
+        let key = match frames.last() {
+            Some(x) => (x.name.clone(), x.location.as_ref().map(|x| x.to_string())),
+            None => (
+                MODULE_FRAME.to_owned(),
+                Some(span_loc.filename().to_owned()),
+            ),
+        };
+        let locals = if self.locals {
+            eval.local_variables()
+                .into_iter()
+                .map(|(k, v)| (k, preview(v)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        let mut events = Vec::new();
+        let mut state = self.state.lock().unwrap();
+        let file = span_loc.filename();
+        let known = state.files.get(file).copied();
+        let file = match known {
+            Some(id) => id,
+            None => {
+                let id = state.files.len();
+                state.files.insert(file.to_owned(), id);
+                events.push(TraceEvent::File {
+                    id,
+                    path: file.to_owned(),
+                });
+                id
+            }
+        };
+        state.frames.truncate(depth + 1);
+        if state.frames.len() <= depth || state.frames[depth].key != key {
+            events.push(TraceEvent::Call {
+                depth,
+                name: key.0.clone(),
+            });
+            state.frames.truncate(depth);
+            state.frames.push(RecordedFrame {
+                key,
+                locals: HashMap::new(),
+            });
+        }
+        let frame = state.frames.last_mut().unwrap();
+        let changed = locals
+            .iter()
+            .filter(|(k, v)| frame.locals.get(*k) != Some(*v))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        frame.locals = locals;
+        let span = span_loc.resolve_span();
+        events.push(TraceEvent::Stmt {
+            file,
+            span: [
+                span.begin_line,
+                span.begin_column,
+                span.end_line,
+                span.end_column,
+            ],
+            depth,
+            locals: changed,
+        });
+        drop(state);
+        self.write(&events);
+    }
+}
+
+/// A statement of a trace being replayed.
+#[derive(Debug)]
+pub(crate) struct TraceStep {
+    pub(crate) file: usize,
+    pub(crate) span: ResolvedSpan,
+    pub(crate) depth: usize,
+    /// The index of the frame in [`Trace::frames`] the statement belongs to.
+    pub(crate) frame: usize,
+    pub(crate) locals: BTreeMap<String, String>,
+    /// The error the statement raised, if it failed.
+    pub(crate) error: Option<String>,
+}
+
+/// A frame of a trace being replayed.
+#[derive(Debug)]
+pub(crate) struct TraceFrame {
+    pub(crate) name: String,
+    /// The indices of the steps which belong to the frame.
+    steps: Vec<usize>,
+}
+
+/// A trace read back from a file.
+#[derive(Debug, Default)]
+pub(crate) struct Trace {
+    pub(crate) files: Vec<String>,
+    pub(crate) steps: Vec<TraceStep>,
+    pub(crate) frames: Vec<TraceFrame>,
+}
+
+impl Trace {
+    pub(crate) fn read(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path)
+            .map_err(|e| anyhow::anyhow!("Can't open trace `{}`: {}", path.display(), e))?;
+        let events = BufReader::new(file)
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(&line?).map_err(|e| {
+                    anyhow::anyhow!(
+                        "Invalid trace `{}` at line {}: {}",
+                        path.display(),
+                        i + 1,
+                        e
+                    )
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Self::new(events)
+    }
+
+    pub(crate) fn new(events: Vec<TraceEvent>) -> anyhow::Result<Self> {
+        let mut res = Self::default();
+        // The current frame at each depth.
+        let mut stack: Vec<usize> = Vec::new();
+        for event in events {
+            match event {
+                TraceEvent::File { id, path } => {
+                    if id != res.files.len() {
+                        return Err(anyhow::anyhow!("Trace declares file {} out of order", id));
+                    }
+                    res.files.push(path);
+                }
+                TraceEvent::Call { depth, name } => {
+                    stack.truncate(depth);
+                    stack.resize(depth, usize::MAX);
+                    stack.push(res.frames.len());
+                    res.frames.push(TraceFrame {
+                        name,
+                        steps: Vec::new(),
+                    });
+                }
+                TraceEvent::Stmt {
+                    file,
+                    span,
+                    depth,
+                    locals,
+                } => {
+                    if file >= res.files.len() {
+                        return Err(anyhow::anyhow!("Trace refers to unknown file {}", file));
+                    }
+                    let frame = match stack.get(depth) {
+                        Some(frame) if *frame != usize::MAX => *frame,
+                        _ => {
+                            return Err(anyhow::anyhow!(
+                                "Trace has a statement outside any call at depth {}",
+                                depth
+                            ));
+                        }
+                    };
+                    stack.truncate(depth + 1);
+                    res.frames[frame].steps.push(res.steps.len());
+                    let [begin_line, begin_column, end_line, end_column] = span;
+                    res.steps.push(TraceStep {
+                        file,
+                        span: ResolvedSpan {
+                            begin_line,
+                            begin_column,
+                            end_line,
+                            end_column,
+                        },
+                        depth,
+                        frame,
+                        locals,
+                        error: None,
+                    });
+                }
+                TraceEvent::Error { message } => match res.steps.last_mut() {
+                    Some(step) => step.error = Some(message),
+                    None => return Err(anyhow::anyhow!("Trace has an error before any statement")),
+                },
+            }
+        }
+        Ok(res)
+    }
+
+    /// The steps each frame of the stack is at, when at step `n`, starting from the top.
+    pub(crate) fn stack(&self, n: usize) -> Vec<usize> {
+        let mut res = vec![n];
+        let mut depth = self.steps[n].depth;
+        for i in (0..n).rev() {
+            if depth == 0 {
+                break;
+            }
+            if self.steps[i].depth < depth {
+                depth = self.steps[i].depth;
+                res.push(i);
+            }
+        }
+        res
+    }
+
+    /// The previews of the locals of the frame of step `n`, as recorded before it executed.
+    pub(crate) fn locals(&self, n: usize) -> BTreeMap<&str, &str> {
+        let mut res = BTreeMap::new();
+        for i in &self.frames[self.steps[n].frame].steps {
+            if *i > n {
+                break;
+            }
+            for (k, v) in &self.steps[*i].locals {
+                res.insert(k.as_str(), v.as_str());
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::environment::Module;
+    use crate::syntax::Dialect;
+
+    /// A writer which can be read from after the recorder has taken it.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn record(program: &str, locals: bool) -> Vec<TraceEvent> {
+        let buffer = Buffer::default();
+        let recorder = TraceRecorder::new(buffer.clone(), locals);
+        {
+            let module = Module::new();
+            let mut eval = Evaluator::new(&module);
+            let ast =
+                AstModule::parse("test.star", program.to_owned(), &Dialect::Extended).unwrap();
+            let _ = recorder.eval_module(ast, &Globals::standard(), &mut eval);
+        }
+        recorder.finish().unwrap();
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        output
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_record() {
+        let program = "def f(x):\n    y = x + 1\n    return y\nz = f(1)\nfail('z is', z)\n";
+        let events = record(program, false);
+        let kinds = events.map(|x| match x {
+            TraceEvent::File { path, .. } => format!("file {}", path),
+            TraceEvent::Call { depth, name } => format!("call {} {}", depth, name),
+            TraceEvent::Stmt { span, depth, .. } => format!("stmt {} {}", span[0], depth),
+            TraceEvent::Error { message } => format!("error {}", message),
+        });
+        assert_eq!(
+            kinds,
+            vec![
+                "file test.star",
+                "call 0 Root",
+                "stmt 0 0",
+                "stmt 3 0",
+                "call 1 f",
+                "stmt 1 1",
+                "stmt 2 1",
+                "stmt 4 0",
+                "error fail: z is 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_locals() {
+        let program = "\
+def f(x):
+    y = x + 1
+    return y
+a = f(1)
+b = f(a)
+";
+        let trace = Trace::new(record(program, true)).unwrap();
+        let lines = trace.steps.map(|x| x.span.begin_line);
+        assert_eq!(lines, vec![0, 3, 1, 2, 4, 1, 2]);
+        // Only changes are recorded, but the replay sees every local.
+        assert_eq!(trace.steps[3].locals.len(), 1);
+        assert_eq!(
+            trace.locals(3).into_iter().collect::<Vec<_>>(),
+            vec![("x", "1"), ("y", "2")]
+        );
+        // The second call starts again, rather than continuing the first.
+        assert_eq!(
+            trace.locals(5).into_iter().collect::<Vec<_>>(),
+            vec![("x", "2")]
+        );
+        assert_eq!(trace.locals(4)["a"], "2");
+        assert_eq!(trace.stack(6), vec![6, 4]);
+        assert_eq!(trace.frames[trace.steps[6].frame].name, "f");
+    }
+}
@@ -15,8 +15,8 @@
  * limitations under the License.
  */
 
+use std::cell::Cell;
 use std::cell::RefCell;
-use std::convert::TryInto;
 use std::iter::Iterator;
 
 use crate::collections::Hashed;
@@ -42,19 +42,25 @@ use crate::values::FrozenStringValue;
 /// fresh slots at the end, and bind them to the names in the comprehension.
 /// On an unscope, we do the reverse, putting things back to how they were
 /// before (apart from the total) number of slots required.
+///
+/// Hidden names keep their slots, so a slot is never given to two names.
 #[derive(Debug)]
-pub(crate) struct MutableNames(RefCell<SmallMap<FrozenStringValue, (ModuleSlotId, Visibility)>>);
+pub(crate) struct MutableNames(
+    RefCell<SmallMap<FrozenStringValue, (ModuleSlotId, Visibility)>>,
+    /// The number of slots allocated, including those of hidden names.
+    Cell<u32>,
+);
 
 #[derive(Debug)]
 pub(crate) struct FrozenNames(SmallMap<FrozenStringValue, (ModuleSlotId, Visibility)>);
 
 impl MutableNames {
     pub fn new() -> Self {
-        Self(RefCell::new(SmallMap::new()))
+        Self(RefCell::new(SmallMap::new()), Cell::new(0))
     }
 
     pub fn slot_count(&self) -> u32 {
-        self.0.borrow().len().try_into().unwrap()
+        self.1.get()
     }
 
     /// Try and go back from a slot to a name.
@@ -88,7 +94,8 @@ impl MutableNames {
                 *slot
             }
             None => {
-                let slot = ModuleSlotId::new(x.len().try_into().unwrap());
+                let slot = ModuleSlotId::new(self.1.get());
+                self.1.set(self.1.get() + 1);
                 x.insert_hashed(name.get_hashed(), (slot, vis));
                 slot
             }
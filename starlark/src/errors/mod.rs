@@ -30,9 +30,19 @@ use annotate_snippets::snippet::Slice;
 use annotate_snippets::snippet::Snippet;
 use annotate_snippets::snippet::SourceAnnotation;
 
+pub use crate::analysis::Case;
 pub use crate::analysis::EvalMessage;
 pub use crate::analysis::EvalSeverity;
+pub use crate::analysis::FunctionMetrics;
 pub use crate::analysis::Lint;
+pub use crate::analysis::LintConfig;
+pub use crate::analysis::LintEdit;
+pub use crate::analysis::LintFix;
+pub use crate::analysis::LintOptions;
+pub use crate::analysis::MetricLimits;
+pub use crate::analysis::ModuleMetrics;
+pub use crate::analysis::NamingConventions;
+pub use crate::analysis::Signatures;
 use crate::codemap::CodeMap;
 use crate::codemap::FileSpan;
 use crate::codemap::Span;
@@ -19,6 +19,8 @@
 
 use std::fmt::Write;
 
+use crate::codemap::FileSpanRef;
+use crate::errors::Diagnostic;
 use crate::eval::bc::addr::BcPtrAddr;
 use crate::eval::bc::frame::BcFramePtr;
 use crate::eval::bc::instr::BcInstr;
@@ -77,10 +79,28 @@ impl Bc {
     pub(crate) fn wrap_error_for_instr_ptr(
         ptr: BcPtrAddr,
         e: anyhow::Error,
-        eval: &Evaluator,
+        eval: &mut Evaluator,
     ) -> EvalException {
         let span = Self::slow_arg_at_ptr(ptr).span;
-        add_span_to_expr_error(e, span, eval)
+        // Once an error has a span it is propagating from a callee, not being raised here.
+        let raised = !matches!(
+            e.downcast_ref::<Diagnostic>(),
+            Some(Diagnostic { span: Some(_), .. })
+        );
+        let e = add_span_to_expr_error(e, span, eval);
+        if raised {
+            for on_error in eval.on_error.clone() {
+                on_error(
+                    &e.0,
+                    FileSpanRef {
+                        span: span.span(),
+                        file: &span.file(),
+                    },
+                    eval,
+                );
+            }
+        }
+        e
     }
 
     /// Run the bytecode in the current frame allocated in the evaluator.
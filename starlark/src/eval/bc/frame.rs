@@ -47,7 +47,7 @@ struct BcFrame<'v> {
     slots: [Option<Value<'v>>; 0],
 }
 
-#[derive(Copy, Clone, Dupe)]
+#[derive(Copy, Clone, Dupe, Debug)]
 pub(crate) struct BcFramePtr<'v> {
     /// Pointer to the `slots` field of `BcFrame`.
     ///
@@ -103,11 +103,6 @@ impl<'v> BcFramePtr<'v> {
         self.frame().get_slot(slot)
     }
 
-    pub(crate) fn set_slot_slow(self, slot: LocalSlotIdCapturedOrNot, value: Value<'v>) {
-        assert!(slot.0 < self.frame().local_count);
-        self.set_slot(slot, value);
-    }
-
     #[inline(always)]
     pub(crate) fn set_slot(mut self, slot: LocalSlotIdCapturedOrNot, value: Value<'v>) {
         self.frame_mut().set_slot(slot, value)
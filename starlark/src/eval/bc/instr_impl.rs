@@ -247,7 +247,7 @@ impl InstrNoFlowImpl for InstrStoreLocalCapturedImpl {
         (source, target): &(BcSlotIn, LocalCapturedSlotId),
     ) -> anyhow::Result<()> {
         let v = frame.get_bc_slot(*source);
-        eval.set_slot_local_captured(*target, v);
+        eval.set_slot_local_captured(frame, *target, v);
         Ok(())
     }
 }
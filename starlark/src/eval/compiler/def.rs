@@ -245,6 +245,9 @@ pub(crate) struct DefInfo {
     /// Slots to copy from the parent. (index in parent, index in child).
     /// Module-level identifiers are not copied over, to avoid excess copying.
     pub(crate) parent: FrozenRef<'static, [(LocalSlotIdCapturedOrNot, LocalSlotIdCapturedOrNot)]>,
+    /// Slots captured by nested defs or lambdas, which hold [`ValueCaptured`](crate::values::layout::value_captured::ValueCaptured)
+    /// once assigned. Only needed to assign variables from the debugger.
+    pub(crate) used_captured: FrozenRef<'static, [LocalSlotIdCapturedOrNot]>,
     /// Statement compiled for non-frozen def.
     #[derivative(Debug = "ignore")]
     stmt_compiled: Bc,
@@ -270,6 +273,7 @@ impl DefInfo {
             docstring: None,
             used: FrozenRef::new(&[]),
             parent: FrozenRef::new(&[]),
+            used_captured: FrozenRef::new(&[]),
             stmt_compiled: Bc::default(),
             body_stmts: StmtsCompiled::empty(),
             stmt_compile_context: StmtCompileContext::default(),
@@ -291,6 +295,7 @@ impl DefInfo {
             docstring: None,
             used: local_names,
             parent,
+            used_captured: FrozenRef::new(&[]),
             stmt_compiled: Bc::default(),
             body_stmts: StmtsCompiled::empty(),
             stmt_compile_context: StmtCompileContext::default(),
@@ -398,6 +403,17 @@ impl Compiler<'_, '_, '_> {
             .eval
             .frozen_heap()
             .alloc_any_slice_display_from_debug(&scope_names.used);
+        let used_captured: Vec<LocalSlotIdCapturedOrNot> = scope_names
+            .mp
+            .values()
+            .filter(|(_, binding_id)| {
+                matches!(
+                    self.scope_data.get_binding(*binding_id).captured,
+                    Captured::Yes
+                )
+            })
+            .map(|(slot, _)| *slot)
+            .collect();
         let info = self.eval.module_env.frozen_heap().alloc_any(DefInfo {
             name,
             codemap: self.codemap,
@@ -407,6 +423,10 @@ impl Compiler<'_, '_, '_> {
                 .eval
                 .frozen_heap()
                 .alloc_any_slice_display_from_debug(&scope_names.parent),
+            used_captured: self
+                .eval
+                .frozen_heap()
+                .alloc_any_slice_display_from_debug(&used_captured),
             stmt_compiled: body.as_bc(
                 &self.compile_context(return_type.is_some()),
                 used,
@@ -460,6 +480,13 @@ pub(crate) struct DefGen<V> {
     optimized_on_freeze_stmt: StmtCompiledCell,
 }
 
+impl<V> DefGen<V> {
+    /// The module the function was defined in, or `None` if it is the module being evaluated.
+    pub(crate) fn module_for_debugger(&self) -> Option<FrozenRef<'static, FrozenModuleRef>> {
+        self.module.load_relaxed()
+    }
+}
+
 impl<V> Display for DefGen<V> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.parameters.signature())
@@ -622,6 +649,22 @@ where
         Ok(())
     }
 
+    /// Check a value the debugger assigns to a local slot against the type annotation
+    /// of the parameter in that slot, if any. Parameters only have types when the
+    /// dialect enables them.
+    pub(crate) fn check_slot_type_for_debugger(
+        &self,
+        slot: LocalSlotId,
+        value: Value<'v>,
+    ) -> anyhow::Result<()> {
+        for (i, arg_name, ty, ty2) in &self.parameter_types {
+            if *i == slot {
+                value.check_type_compiled(ty.to_value(), ty2, Some(arg_name))?;
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn check_return_type(
         &self,
         ret: Value<'v>,
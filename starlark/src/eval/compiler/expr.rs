@@ -1137,7 +1137,8 @@ impl<'v, 'a, 'e> Compiler<'v, 'a, 'e> {
 
                 // We can only inline variables if they were assigned once
                 // otherwise we might inline the wrong value.
-                if binding.assign_count == AssignCount::AtMostOnce {
+                // With `before_stmt` a debugger may assign the variable while paused.
+                if binding.assign_count == AssignCount::AtMostOnce && !self.has_before_stmt {
                     if let Some(v) = self.eval.module_env.slots().get_slot(slot) {
                         // We could inline non-frozen values, but these values
                         // can be garbage-collected, so it is somewhat harder to implement.
@@ -36,6 +36,7 @@ use crate::codemap::CodeMap;
 use crate::codemap::FileSpan;
 use crate::codemap::Span;
 use crate::errors::Frame;
+use crate::eval::bc::frame::BcFramePtr;
 use crate::eval::runtime::inlined_frame::InlinedFrames;
 use crate::values::error::ControlError;
 use crate::values::FrozenRef;
@@ -164,6 +165,10 @@ enum CallStackError {
 pub(crate) struct CheapCallStack<'v> {
     count: usize,
     stack: [CheapFrame<'v>; MAX_CALLSTACK_RECURSION],
+    /// The frame current when the call at a given stack index was made, which holds the
+    /// local variables of the entry below it. Only debuggers need these, so they are
+    /// only recorded while `before_stmt` instrumentation is enabled.
+    caller_frames: Vec<(usize, BcFramePtr<'v>)>,
 }
 
 impl<'v> Default for CheapCallStack<'v> {
@@ -174,6 +179,7 @@ impl<'v> Default for CheapCallStack<'v> {
                 function: Value::new_none(),
                 span: None,
             }; MAX_CALLSTACK_RECURSION],
+            caller_frames: Vec::new(),
         }
     }
 }
@@ -216,6 +222,19 @@ impl<'v> CheapCallStack<'v> {
         Ok(())
     }
 
+    /// Record the frame which was current when the top element was pushed.
+    /// Must be paired with `pop_caller_frame` before the matching `pop`.
+    pub(crate) fn push_caller_frame(&mut self, caller_frame: BcFramePtr<'v>) {
+        debug_assert!(self.count >= 1);
+        self.caller_frames.push((self.count - 1, caller_frame));
+    }
+
+    /// Remove the frame recorded by `push_caller_frame`.
+    pub(crate) fn pop_caller_frame(&mut self) {
+        debug_assert!(self.caller_frames.last().map(|x| x.0) == self.count.checked_sub(1));
+        self.caller_frames.pop();
+    }
+
     /// Remove the top element from the stack. Called after `push`.
     pub(crate) fn pop(&mut self) {
         debug_assert!(self.count >= 1);
@@ -260,6 +279,40 @@ impl<'v> CheapCallStack<'v> {
     pub(crate) fn to_function_values(&self) -> Vec<Value<'v>> {
         self.stack[1..self.count].map(|x| x.function)
     }
+
+    /// The function and its frame for the `n`-th frame from the top of
+    /// [`to_diagnostic_frames`](CheapCallStack::to_diagnostic_frames), where `current_frame`
+    /// is the frame of the top function. `n` equal to the number of diagnostic frames
+    /// is the module itself, with a `None` function. `None` if there is no such frame,
+    /// or the function was inlined, so has no frame of its own. Frames below the top are
+    /// only known if the calls were made while `before_stmt` instrumentation was enabled.
+    pub(crate) fn nth_frame_for_debugger(
+        &self,
+        mut n: usize,
+        current_frame: BcFramePtr<'v>,
+    ) -> Option<(Value<'v>, BcFramePtr<'v>)> {
+        for i in (0..self.count).rev() {
+            if n == 0 {
+                let frame = if i + 1 == self.count {
+                    current_frame
+                } else {
+                    self.caller_frames.iter().rev().find(|x| x.0 == i + 1)?.1
+                };
+                return Some((self.stack[i].function, frame));
+            }
+            n -= 1;
+            // Frames inlined at the call site come next, from the top.
+            let mut inlined = Vec::new();
+            if let Some(span) = self.stack[i].span {
+                span.inlined_frames.extend_frames(&mut inlined);
+            }
+            if n < inlined.len() {
+                return None;
+            }
+            n -= inlined.len();
+        }
+        None
+    }
 }
 
 /// Owned call stack.
@@ -18,6 +18,7 @@
 use std::cell::Cell;
 use std::collections::HashSet;
 use std::fs;
+use std::intrinsics::unlikely;
 use std::mem;
 use std::mem::MaybeUninit;
 use std::path::Path;
@@ -91,6 +92,12 @@ pub(crate) enum EvaluatorError {
     TopSecondFrameNotDef,
     #[error("Top frame is not native (internal error)")]
     TopFrameNotNative,
+    #[error("No frame {0} on the call stack, or it was inlined or not instrumented")]
+    NoSuchFrame(usize),
+    #[error("No variable `{0}` in frame {1}")]
+    NoSuchVariable(String, usize),
+    #[error("Variable `{0}` belongs to a frozen module and can't be assigned")]
+    FrozenVariable(String),
 }
 
 /// Number of bytes to allocate between GC's.
@@ -126,6 +133,9 @@ pub struct Evaluator<'v, 'a> {
     pub(crate) next_gc_level: usize,
     // Extra functions to run on each statement, usually empty
     pub(crate) before_stmt: BeforeStmt<'a>,
+    // Functions to run where an error is raised, before the stack unwinds, usually empty
+    pub(crate) on_error:
+        Vec<&'a dyn for<'v1> Fn(&anyhow::Error, FileSpanRef, &mut Evaluator<'v1, 'a>)>,
     // Used for line profiling
     stmt_profile: StmtProfile,
     // Bytecode profile.
@@ -181,6 +191,7 @@ impl<'v, 'a> Evaluator<'v, 'a> {
             flame_profile: FlameProfile::new(),
             heap_or_flame_profile: false,
             before_stmt: BeforeStmt::default(),
+            on_error: Vec::new(),
             module_def_info: DefInfo::empty(), // Will be replaced before it is used
             string_pool: StringPool::default(),
             breakpoint_handler: None,
@@ -362,14 +373,20 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         self.before_stmt.before_stmt.push(f)
     }
 
-    /// This function is used by DAP, and it is not public API.
-    // TODO(nga): pull DAP into the crate, and hide this function.
-    #[doc(hidden)]
-    pub fn before_stmt_for_dap(
+    /// Add a function to run where an error is raised, before the stack unwinds.
+    /// Returns `false`, and adds nothing, if that function was already added.
+    pub(crate) fn on_error(
         &mut self,
-        f: &'a dyn for<'v1> Fn(FileSpanRef, &mut Evaluator<'v1, 'a>),
-    ) {
-        self.before_stmt(f);
+        f: &'a dyn for<'v1> Fn(&anyhow::Error, FileSpanRef, &mut Evaluator<'v1, 'a>),
+    ) -> bool {
+        fn address<T: ?Sized>(x: &T) -> *const u8 {
+            (x as *const T).cast()
+        }
+        if self.on_error.iter().any(|x| address(*x) == address(f)) {
+            return false;
+        }
+        self.on_error.push(f);
+        true
     }
 
     /// Set the handler invoked when `print` function is used.
@@ -396,8 +413,17 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         }
 
         self.call_stack.push(function, span)?;
+        // Only debuggers need the caller frame, so only record it when they could be inspecting
+        // frames: with `before_stmt` hooks installed, or instrumentation explicitly requested.
+        let record_frame = unlikely(self.before_stmt.enabled());
+        if record_frame {
+            self.call_stack.push_caller_frame(self.current_frame);
+        }
         // Must always call .pop regardless
         let res = within(self).map_err(|e| add_diagnostics(e, self));
+        if record_frame {
+            self.call_stack.pop_caller_frame();
+        }
         self.call_stack.pop();
         res
     }
@@ -547,9 +573,25 @@ impl<'v, 'a> Evaluator<'v, 'a> {
         self.module_env.slots().set_slot(slot, value);
     }
 
-    pub(crate) fn set_slot_local_captured(&mut self, slot: LocalCapturedSlotId, value: Value<'v>) {
+    /// Unlike [`get_slot_local`](Evaluator::get_slot_local), the frame need not be the current
+    /// one, because the debugger assigns variables of any frame on the call stack.
+    pub(crate) fn set_slot_local(
+        &self,
+        frame: BcFramePtr<'v>,
+        slot: LocalSlotId,
+        value: Value<'v>,
+    ) {
+        frame.set_slot(slot.to_captured_or_not(), value);
+    }
+
+    pub(crate) fn set_slot_local_captured(
+        &self,
+        frame: BcFramePtr<'v>,
+        slot: LocalCapturedSlotId,
+        value: Value<'v>,
+    ) {
         let slot = LocalSlotId(slot.0);
-        match self.current_frame.get_slot(slot.to_captured_or_not()) {
+        match frame.get_slot(slot.to_captured_or_not()) {
             Some(value_captured) => {
                 let value_captured = value_captured
                     .downcast_ref::<ValueCaptured>()
@@ -560,8 +602,7 @@ impl<'v, 'a> Evaluator<'v, 'a> {
                 let value_captured = self
                     .heap()
                     .alloc_complex(ValueCaptured(Cell::new(Some(value))));
-                self.current_frame
-                    .set_slot(slot.to_captured_or_not(), value_captured);
+                frame.set_slot(slot.to_captured_or_not(), value_captured);
             }
         };
     }
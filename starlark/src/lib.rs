@@ -415,7 +415,7 @@ pub(crate) mod analysis;
 pub mod assert;
 pub mod codemap;
 pub mod collections;
-mod debug;
+pub mod debug;
 pub mod environment;
 pub mod errors;
 pub mod eval;
@@ -0,0 +1,155 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The text of documents the client has open, kept up to date with incremental edits.
+
+use lsp_types::Position;
+use lsp_types::TextDocumentContentChangeEvent;
+
+/// The contents of an open document, as last sent by the client.
+#[derive(Debug, Clone)]
+pub(crate) struct LspDocument {
+    /// The version the client assigned to this text.
+    pub(crate) version: i32,
+    pub(crate) text: String,
+}
+
+impl LspDocument {
+    pub(crate) fn new(version: i32, text: String) -> Self {
+        Self { version, text }
+    }
+
+    /// Apply the changes from a `didChange` notification, in order.
+    pub(crate) fn apply_changes(
+        &mut self,
+        version: i32,
+        changes: impl IntoIterator<Item = TextDocumentContentChangeEvent>,
+    ) {
+        for change in changes {
+            match change.range {
+                None => self.text = change.text,
+                Some(range) => {
+                    let start = self.offset(range.start);
+                    // Be lenient about clients which send a reversed range.
+                    let end = self.offset(range.end).max(start);
+                    self.text.replace_range(start..end, &change.text);
+                }
+            }
+        }
+        self.version = version;
+    }
+
+    /// Convert an LSP position into a byte offset into the text, see [`offset`].
+    pub(crate) fn offset(&self, position: Position) -> usize {
+        offset(&self.text, position)
+    }
+
+    /// The inverse of [`offset`](LspDocument::offset), converting a byte offset into the
+    /// text into an LSP position.
+    pub(crate) fn position(&self, offset: usize) -> Position {
+        let before = &self.text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Position::new(
+            before.matches('\n').count() as u32,
+            before[line_start..].encode_utf16().count() as u32,
+        )
+    }
+}
+
+/// Convert an LSP position, whose `character` is measured in UTF-16 code units,
+/// into a byte offset into the text. Positions past the end of a line refer to the
+/// end of that line, and positions past the end of the document to its end.
+pub(crate) fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Range;
+
+    use super::*;
+
+    fn change(
+        range: Option<((u32, u32), (u32, u32))>,
+        text: &str,
+    ) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: range.map(|((l1, c1), (l2, c2))| {
+                Range::new(Position::new(l1, c1), Position::new(l2, c2))
+            }),
+            range_length: None,
+            text: text.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_offset_utf16() {
+        // `é` is one UTF-16 unit but two bytes, `😀` is two UTF-16 units and four bytes.
+        let doc = LspDocument::new(0, "é😀x\r\nab\nc".to_owned());
+        assert_eq!(doc.offset(Position::new(0, 0)), 0);
+        assert_eq!(doc.offset(Position::new(0, 1)), 2);
+        assert_eq!(doc.offset(Position::new(0, 3)), 6);
+        assert_eq!(doc.offset(Position::new(0, 4)), 7);
+        // Past the end of the line stops before the `\r\n`.
+        assert_eq!(doc.offset(Position::new(0, 100)), 7);
+        assert_eq!(doc.offset(Position::new(1, 1)), 10);
+        assert_eq!(doc.offset(Position::new(2, 1)), 13);
+        assert_eq!(doc.offset(Position::new(7, 0)), 13);
+
+        for offset in [0, 2, 6, 7, 10, 13] {
+            assert_eq!(doc.offset(doc.position(offset)), offset);
+        }
+        assert_eq!(doc.position(6), Position::new(0, 3));
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let mut doc = LspDocument::new(1, "def f():\n    pass\n".to_owned());
+        doc.apply_changes(
+            2,
+            vec![
+                change(Some(((1, 4), (1, 8))), "return 1"),
+                change(Some(((0, 4), (0, 5))), "🙂g"),
+                change(Some(((2, 0), (2, 0))), "f😀 = 1\n"),
+                change(Some(((2, 1), (2, 3))), ""),
+            ],
+        );
+        assert_eq!(doc.version, 2);
+        assert_eq!(doc.text, "def 🙂g():\n    return 1\nf = 1\n");
+
+        doc.apply_changes(3, vec![change(None, "x = 1\n")]);
+        assert_eq!(doc.text, "x = 1\n");
+    }
+}
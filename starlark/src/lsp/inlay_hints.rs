@@ -0,0 +1,349 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Inlay hints, which name the parameters positional arguments are passed to, and
+//! show the types of variables whose type is obvious from their assignment.
+
+use std::collections::HashMap;
+
+use lsp_types::InlayHint;
+use lsp_types::InlayHintKind;
+use lsp_types::InlayHintLabel;
+use lsp_types::Position;
+use lsp_types::Range;
+
+use crate::analysis::BindingKind;
+use crate::codemap::Pos;
+use crate::codemap::Span;
+use crate::codemap::Spanned;
+use crate::syntax::ast::ArgumentP;
+use crate::syntax::ast::AssignP;
+use crate::syntax::ast::AstArgument;
+use crate::syntax::ast::AstExpr;
+use crate::syntax::ast::AstLiteral;
+use crate::syntax::ast::AstNoPayload;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::BinOp;
+use crate::syntax::ast::Expr;
+use crate::syntax::ast::ParameterP;
+use crate::syntax::ast::Stmt;
+use crate::syntax::uniplate::Visit;
+use crate::syntax::AstModule;
+use crate::syntax::DialectTypes;
+
+/// What we know about a function defined in the module.
+struct Def<'a> {
+    /// The parameters which can be passed positionally.
+    positional: Vec<&'a str>,
+    /// The source of the return type annotation.
+    ret: Option<&'a str>,
+}
+
+struct Hints<'a, F> {
+    ast: &'a AstModule,
+    /// The `def`s in the module, by the span of their name.
+    defs: HashMap<Span, Def<'a>>,
+    /// How each identifier is bound, by its span.
+    bindings: HashMap<Span, (BindingKind, Option<Span>)>,
+    /// The positional parameters of a global function, such as a native function.
+    global_parameters: F,
+    types: bool,
+    res: Vec<InlayHint>,
+}
+
+/// Parameter names which tell the reader nothing, like `x` or `a1`.
+fn uninformative(name: &str) -> bool {
+    name.trim_end_matches(|c: char| c.is_ascii_digit()).len() <= 1
+}
+
+impl<'a, F: Fn(&str) -> Option<Vec<String>>> Hints<'a, F> {
+    fn position(&self, pos: Pos) -> Position {
+        let codemap = &self.ast.codemap;
+        let line = codemap.find_line(pos);
+        let prefix = codemap.source_span(Span::new(codemap.line_span(line).begin(), pos));
+        Position::new(line as u32, prefix.encode_utf16().count() as u32)
+    }
+
+    fn add(&mut self, pos: Pos, label: String, kind: InlayHintKind) {
+        let parameter = kind == InlayHintKind::PARAMETER;
+        let position = self.position(pos);
+        self.res.push(InlayHint {
+            position,
+            label: InlayHintLabel::String(label),
+            kind: Some(kind),
+            text_edits: None,
+            tooltip: None,
+            padding_left: None,
+            padding_right: Some(parameter),
+            data: None,
+        });
+    }
+
+    /// The function being called, if it is a variable bound to something we know about.
+    fn callee(&self, f: &AstExpr) -> Option<Vec<String>> {
+        let name = match &f.node {
+            Expr::Identifier(name, _) => name,
+            _ => return None,
+        };
+        match self.bindings.get(&name.span)? {
+            (BindingKind::Builtin, _) => (self.global_parameters)(&name.node),
+            (_, binding) => {
+                let def = self.defs.get(binding.as_ref()?)?;
+                Some(def.positional.iter().map(|x| (*x).to_owned()).collect())
+            }
+        }
+    }
+
+    fn call(&mut self, f: &AstExpr, args: &[AstArgument]) {
+        let params = match self.callee(f) {
+            Some(params) => params,
+            None => return,
+        };
+        for (arg, param) in args.iter().zip(params) {
+            let e = match &arg.node {
+                ArgumentP::Positional(e) => e,
+                // After `*args` we can't tell which parameter is which.
+                _ => return,
+            };
+            let same = matches!(&e.node, Expr::Identifier(name, _) if name.node == param);
+            if !same && !uninformative(&param) {
+                self.add(
+                    arg.span.begin(),
+                    format!("{}:", param),
+                    InlayHintKind::PARAMETER,
+                );
+            }
+        }
+    }
+
+    fn is_builtin(&self, x: &AstExpr, name: &str) -> bool {
+        match &x.node {
+            Expr::Identifier(x, _) => {
+                x.node == name
+                    && matches!(self.bindings.get(&x.span), Some((BindingKind::Builtin, _)))
+            }
+            _ => false,
+        }
+    }
+
+    /// The type of an expression, if it is obvious.
+    fn infer(&self, x: &AstExpr) -> Option<String> {
+        let known = |x: &str| Some(x.to_owned());
+        match &x.node {
+            Expr::Literal(AstLiteral::Int(_)) => known("int"),
+            Expr::Literal(AstLiteral::Float(_)) => known("float"),
+            Expr::Literal(AstLiteral::String(_)) => known("str"),
+            Expr::List(_) | Expr::ListComprehension(..) => known("list"),
+            Expr::Dict(_) | Expr::DictComprehension(..) => known("dict"),
+            Expr::Tuple(_) => known("tuple"),
+            Expr::Not(_) => known("bool"),
+            Expr::Identifier(..) if self.is_builtin(x, "True") || self.is_builtin(x, "False") => {
+                known("bool")
+            }
+            Expr::Minus(e) | Expr::Plus(e) => self.infer(e).filter(|t| t == "int" || t == "float"),
+            Expr::Op(l, op, r) => match op {
+                BinOp::Equal
+                | BinOp::NotEqual
+                | BinOp::Less
+                | BinOp::Greater
+                | BinOp::LessOrEqual
+                | BinOp::GreaterOrEqual
+                | BinOp::In
+                | BinOp::NotIn => known("bool"),
+                BinOp::Percent if self.infer(l).as_deref() == Some("str") => known("str"),
+                BinOp::Add | BinOp::Subtract | BinOp::Multiply => {
+                    let l = self.infer(l)?;
+                    (Some(&l) == self.infer(r).as_ref()).then(|| l)
+                }
+                _ => None,
+            },
+            Expr::Call(f, _) => {
+                for ty in ["str", "int", "float", "bool", "list", "dict", "tuple"] {
+                    if self.is_builtin(f, ty) {
+                        return known(ty);
+                    }
+                }
+                if self.is_builtin(f, "len") {
+                    return known("int");
+                }
+                let binding = match &f.node {
+                    Expr::Identifier(name, _) => self.bindings.get(&name.span)?.1?,
+                    _ => return None,
+                };
+                self.defs.get(&binding)?.ret.map(|x| x.to_owned())
+            }
+            _ => None,
+        }
+    }
+
+    fn visit(&mut self, x: Visit<'_, AstNoPayload>) {
+        match x {
+            Visit::Expr(Spanned {
+                node: Expr::Call(f, args),
+                ..
+            }) => self.call(f, args),
+            Visit::Stmt(Spanned {
+                node: Stmt::Assign(lhs, rhs),
+                ..
+            }) if self.types => {
+                if let AssignP::Identifier(name) = &lhs.node {
+                    if let Some(ty) = self.infer(rhs) {
+                        self.add(name.span.end(), format!(": {}", ty), InlayHintKind::TYPE);
+                    }
+                }
+            }
+            _ => {}
+        }
+        x.visit_children(|x| self.visit(x));
+    }
+}
+
+fn collect_defs<'a>(ast: &'a AstModule, x: &'a AstStmt, res: &mut HashMap<Span, Def<'a>>) {
+    if let Stmt::Def(name, params, ret, _, _) = &x.node {
+        let mut positional = Vec::new();
+        for p in params {
+            match &p.node {
+                ParameterP::Normal(name, _) | ParameterP::WithDefaultValue(name, _, _) => {
+                    positional.push(name.node.0.as_str())
+                }
+                _ => break,
+            }
+        }
+        res.insert(
+            name.span,
+            Def {
+                positional,
+                ret: ret.as_ref().map(|x| ast.codemap.source_span(x.span)),
+            },
+        );
+    }
+    x.visit_stmt(|x| collect_defs(ast, x, res));
+}
+
+/// The inlay hints for the lines spanned by `range`. Parameter names of functions which
+/// aren't defined in the module are looked up with `global_parameters`.
+pub(crate) fn inlay_hints(
+    ast: &AstModule,
+    range: Range,
+    global_parameters: impl Fn(&str) -> Option<Vec<String>>,
+) -> Vec<InlayHint> {
+    let mut defs = HashMap::new();
+    collect_defs(ast, &ast.statement, &mut defs);
+    let bindings = ast
+        .references()
+        .into_iter()
+        .map(|x| (x.span, (x.kind, x.binding)))
+        .collect();
+    let mut hints = Hints {
+        ast,
+        defs,
+        bindings,
+        global_parameters,
+        types: ast.dialect.enable_types != DialectTypes::Disable,
+        res: Vec::new(),
+    };
+    hints.visit(Visit::Stmt(&ast.statement));
+    let mut res: Vec<InlayHint> = hints
+        .res
+        .into_iter()
+        .filter(|x| range.start.line <= x.position.line && x.position.line <= range.end.line)
+        .collect();
+    res.sort_by_key(|x| (x.position.line, x.position.character));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use gazebo::prelude::*;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn hints(code: &str, dialect: &Dialect) -> Vec<String> {
+        let m = AstModule::parse("X", code.to_owned(), dialect).unwrap();
+        let range = Range::new(Position::new(0, 0), Position::new(100, 0));
+        let global_parameters = |name: &str| match name {
+            "native" => Some(vec!["value".to_owned(), "a1".to_owned()]),
+            _ => None,
+        };
+        inlay_hints(&m, range, global_parameters).into_map(|x| {
+            let label = match x.label {
+                InlayHintLabel::String(x) => x,
+                InlayHintLabel::LabelParts(_) => unreachable!(),
+            };
+            format!("{}:{} {}", x.position.line, x.position.character, label)
+        })
+    }
+
+    #[test]
+    fn test_parameter_hints() {
+        let code = r#"
+def f(first, second, *args, named = 1):
+    pass
+def g(value):
+    f(value, value, 3)
+f(1, *[2])
+f(1, second = 2)
+native(1, 2)
+h(1)
+"#;
+        assert_eq!(
+            hints(code, &Dialect::Extended),
+            vec![
+                "4:6 first:",
+                "4:13 second:",
+                "5:2 first:",
+                "6:2 first:",
+                "7:7 value:",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_type_hints() {
+        let code = r#"
+def f() -> "list[int]":
+    return []
+a = 1
+b = -1.5 * 2.0
+c = "x" % a
+d = [x for x in f()]
+e = f()
+g = a == 1
+h, i = 1, 2
+j = a
+k = len(d)
+"#;
+        assert_eq!(
+            hints(code, &Dialect::Extended),
+            vec![
+                "3:1 : int",
+                "4:1 : float",
+                "5:1 : str",
+                "6:1 : list",
+                "7:1 : \"list[int]\"",
+                "8:1 : bool",
+                "11:1 : int",
+            ]
+        );
+        // Without types in the dialect, there are no type hints.
+        assert_eq!(
+            hints("a = 1\nb = [a]\n", &Dialect::Standard),
+            Vec::<String>::new()
+        );
+    }
+}
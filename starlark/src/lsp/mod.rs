@@ -18,6 +18,9 @@
 //! The server that allows IDEs to evaluate and interpret starlark code according
 //! to the Language Server Protocol <https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/>
 
+mod document;
+mod inlay_hints;
+mod semantic_tokens;
 pub mod server;
 #[cfg(all(test, not(windows)))]
 mod test;
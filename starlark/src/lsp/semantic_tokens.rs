@@ -0,0 +1,258 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Semantic tokens, which let the editor colour identifiers by how they are bound.
+
+use lsp_types::Range;
+use lsp_types::SemanticToken;
+use lsp_types::SemanticTokenModifier;
+use lsp_types::SemanticTokenType;
+use lsp_types::SemanticTokensLegend;
+
+use crate::analysis::BindingKind;
+use crate::codemap::Span;
+use crate::syntax::AstModule;
+
+/// The index of each of these in the token types of the legend.
+const TYPE_PARAMETER: u32 = 0;
+const TYPE_VARIABLE: u32 = 1;
+
+/// The bit of each of these in the token modifiers of the legend.
+const MODIFIER_DECLARATION: u32 = 1 << 0;
+const MODIFIER_DEFAULT_LIBRARY: u32 = 1 << 1;
+const MODIFIER_GLOBAL: u32 = 1 << 2;
+const MODIFIER_LOADED: u32 = 1 << 3;
+const MODIFIER_UNUSED: u32 = 1 << 4;
+
+/// The legend describing the tokens we produce, which has to line up with the constants above.
+pub(crate) fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: vec![SemanticTokenType::PARAMETER, SemanticTokenType::VARIABLE],
+        token_modifiers: vec![
+            SemanticTokenModifier::DECLARATION,
+            SemanticTokenModifier::DEFAULT_LIBRARY,
+            SemanticTokenModifier::new("global"),
+            SemanticTokenModifier::new("loaded"),
+            SemanticTokenModifier::new("unused"),
+        ],
+    }
+}
+
+/// A token before it is encoded relative to the previous one. Columns are in UTF-16 code units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Token {
+    line: u32,
+    start: u32,
+    length: u32,
+    token_type: u32,
+    modifiers: u32,
+}
+
+fn token(ast: &AstModule, span: Span, token_type: u32, modifiers: u32) -> Option<Token> {
+    let codemap = &ast.codemap;
+    let text = codemap.source_span(span);
+    if text.contains('\n') {
+        return None;
+    }
+    let line = codemap.find_line(span.begin());
+    let line_begin = codemap.line_span(line).begin();
+    let prefix = codemap.source_span(Span::new(line_begin, span.begin()));
+    Some(Token {
+        line: line as u32,
+        start: prefix.encode_utf16().count() as u32,
+        length: text.encode_utf16().count() as u32,
+        token_type,
+        modifiers,
+    })
+}
+
+/// The semantic tokens for every identifier in the module, optionally restricted to those
+/// on the lines spanned by `range`.
+pub(crate) fn semantic_tokens(ast: &AstModule, range: Option<Range>) -> Vec<SemanticToken> {
+    let mut tokens: Vec<Token> = ast
+        .references()
+        .into_iter()
+        .filter_map(|r| {
+            let mut modifiers = 0;
+            let token_type = match r.kind {
+                BindingKind::Parameter => TYPE_PARAMETER,
+                BindingKind::Local => TYPE_VARIABLE,
+                BindingKind::ModuleGlobal => {
+                    modifiers |= MODIFIER_GLOBAL;
+                    TYPE_VARIABLE
+                }
+                BindingKind::Loaded => {
+                    modifiers |= MODIFIER_LOADED;
+                    TYPE_VARIABLE
+                }
+                BindingKind::Builtin => {
+                    modifiers |= MODIFIER_DEFAULT_LIBRARY;
+                    TYPE_VARIABLE
+                }
+            };
+            if r.write && r.binding == Some(r.span) {
+                modifiers |= MODIFIER_DECLARATION;
+            }
+            if r.unused {
+                modifiers |= MODIFIER_UNUSED;
+            }
+            token(ast, r.identifier_span(&ast.codemap), token_type, modifiers)
+        })
+        .filter(|t| match range {
+            Some(range) => range.start.line <= t.line && t.line <= range.end.line,
+            None => true,
+        })
+        .collect();
+    tokens.sort();
+    tokens.dedup_by_key(|t| (t.line, t.start));
+
+    let mut res = Vec::with_capacity(tokens.len());
+    let mut previous = (0, 0);
+    for t in tokens {
+        let delta_line = t.line - previous.0;
+        let delta_start = if delta_line == 0 {
+            t.start - previous.1
+        } else {
+            t.start
+        };
+        res.push(SemanticToken {
+            delta_line,
+            delta_start,
+            length: t.length,
+            token_type: t.token_type,
+            token_modifiers_bitset: t.modifiers,
+        });
+        previous = (t.line, t.start);
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::Position;
+
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    fn token(
+        delta_line: u32,
+        delta_start: u32,
+        length: u32,
+        token_type: u32,
+        token_modifiers_bitset: u32,
+    ) -> SemanticToken {
+        SemanticToken {
+            delta_line,
+            delta_start,
+            length,
+            token_type,
+            token_modifiers_bitset,
+        }
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let m = module("load('a', 'x')\ndef f(p):\n    return len(p) + x\ny = 'é'; z = y\n");
+        assert_eq!(
+            semantic_tokens(&m, None),
+            vec![
+                // x in the load
+                token(
+                    0,
+                    11,
+                    1,
+                    TYPE_VARIABLE,
+                    MODIFIER_LOADED | MODIFIER_DECLARATION
+                ),
+                // f
+                token(
+                    1,
+                    4,
+                    1,
+                    TYPE_VARIABLE,
+                    MODIFIER_GLOBAL | MODIFIER_DECLARATION
+                ),
+                // p
+                token(0, 2, 1, TYPE_PARAMETER, MODIFIER_DECLARATION),
+                token(1, 11, 3, TYPE_VARIABLE, MODIFIER_DEFAULT_LIBRARY),
+                token(0, 4, 1, TYPE_PARAMETER, 0),
+                token(0, 5, 1, TYPE_VARIABLE, MODIFIER_LOADED),
+                token(
+                    1,
+                    0,
+                    1,
+                    TYPE_VARIABLE,
+                    MODIFIER_GLOBAL | MODIFIER_DECLARATION
+                ),
+                // `é` takes up one UTF-16 unit
+                token(
+                    0,
+                    9,
+                    1,
+                    TYPE_VARIABLE,
+                    MODIFIER_GLOBAL | MODIFIER_DECLARATION
+                ),
+                token(0, 4, 1, TYPE_VARIABLE, MODIFIER_GLOBAL),
+            ]
+        );
+
+        let range = Range::new(Position::new(2, 0), Position::new(2, 100));
+        assert_eq!(
+            semantic_tokens(&m, Some(range)),
+            vec![
+                token(2, 11, 3, TYPE_VARIABLE, MODIFIER_DEFAULT_LIBRARY),
+                token(0, 4, 1, TYPE_PARAMETER, 0),
+                token(0, 5, 1, TYPE_VARIABLE, MODIFIER_LOADED),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens_unused() {
+        let m = module("def f(p):\n    _x = 1\n");
+        assert_eq!(
+            semantic_tokens(&m, None),
+            vec![
+                token(
+                    0,
+                    4,
+                    1,
+                    TYPE_VARIABLE,
+                    MODIFIER_GLOBAL | MODIFIER_DECLARATION
+                ),
+                token(
+                    0,
+                    2,
+                    1,
+                    TYPE_PARAMETER,
+                    MODIFIER_DECLARATION | MODIFIER_UNUSED
+                ),
+                token(
+                    1,
+                    4,
+                    2,
+                    TYPE_VARIABLE,
+                    MODIFIER_DECLARATION | MODIFIER_UNUSED
+                ),
+            ]
+        );
+    }
+}
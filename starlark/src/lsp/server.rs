@@ -18,51 +18,113 @@
 //! Based on the reference lsp-server example at <https://github.com/rust-analyzer/lsp-server/blob/master/examples/goto_def.rs>.
 
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::path::Path;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::path::PathBuf;
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::RwLock;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 use derivative::Derivative;
 use gazebo::prelude::*;
 use lsp_server::Connection;
+use lsp_server::ErrorCode;
 use lsp_server::Message;
 use lsp_server::Notification;
 use lsp_server::Request;
 use lsp_server::RequestId;
 use lsp_server::Response;
 use lsp_server::ResponseError;
+use lsp_types::notification::Cancel;
 use lsp_types::notification::DidChangeTextDocument;
 use lsp_types::notification::DidCloseTextDocument;
 use lsp_types::notification::DidOpenTextDocument;
+use lsp_types::notification::Exit;
 use lsp_types::notification::LogMessage;
 use lsp_types::notification::PublishDiagnostics;
+use lsp_types::request::CodeActionRequest;
+use lsp_types::request::DocumentHighlightRequest;
+use lsp_types::request::FoldingRangeRequest;
+use lsp_types::request::Formatting;
 use lsp_types::request::GotoDefinition;
+use lsp_types::request::InlayHintRequest;
+use lsp_types::request::RangeFormatting;
+use lsp_types::request::SemanticTokensFullRequest;
+use lsp_types::request::SemanticTokensRangeRequest;
+use lsp_types::request::Shutdown;
+use lsp_types::CodeAction;
+use lsp_types::CodeActionKind;
+use lsp_types::CodeActionOrCommand;
+use lsp_types::CodeActionParams;
+use lsp_types::CodeActionProviderCapability;
+use lsp_types::CodeActionResponse;
 use lsp_types::DefinitionOptions;
 use lsp_types::Diagnostic;
 use lsp_types::DidChangeTextDocumentParams;
 use lsp_types::DidCloseTextDocumentParams;
 use lsp_types::DidOpenTextDocumentParams;
+use lsp_types::DocumentFormattingParams;
+use lsp_types::DocumentHighlightParams;
+use lsp_types::DocumentRangeFormattingParams;
+use lsp_types::FoldingRange;
+use lsp_types::FoldingRangeParams;
+use lsp_types::FoldingRangeProviderCapability;
+use lsp_types::FormattingOptions;
 use lsp_types::GotoDefinitionParams;
 use lsp_types::GotoDefinitionResponse;
 use lsp_types::InitializeParams;
+use lsp_types::InlayHintParams;
 use lsp_types::LocationLink;
 use lsp_types::LogMessageParams;
 use lsp_types::MessageType;
+use lsp_types::NumberOrString;
 use lsp_types::OneOf;
 use lsp_types::PublishDiagnosticsParams;
 use lsp_types::Range;
+use lsp_types::SemanticTokens;
+use lsp_types::SemanticTokensFullOptions;
+use lsp_types::SemanticTokensOptions;
+use lsp_types::SemanticTokensParams;
+use lsp_types::SemanticTokensRangeParams;
+use lsp_types::SemanticTokensServerCapabilities;
 use lsp_types::ServerCapabilities;
 use lsp_types::TextDocumentSyncCapability;
 use lsp_types::TextDocumentSyncKind;
+use lsp_types::TextEdit;
 use lsp_types::Url;
 use lsp_types::WorkDoneProgressOptions;
+use lsp_types::WorkspaceEdit;
 use serde::de::DeserializeOwned;
 
+use crate::analysis::disable_lint_edit;
 use crate::analysis::DefinitionLocation;
+use crate::analysis::DiagnosticFix;
 use crate::analysis::LspModule;
+use crate::codemap::Pos;
+use crate::lsp::document;
+use crate::lsp::document::LspDocument;
+use crate::lsp::inlay_hints::inlay_hints;
+use crate::lsp::semantic_tokens::legend;
+use crate::lsp::semantic_tokens::semantic_tokens;
 use crate::syntax::AstModule;
+use crate::syntax::FormatOptions;
+use crate::values::docs::DocItem;
+use crate::values::docs::Param;
+
+/// How long to wait for edits to stop arriving before re-analysing a document,
+/// so that a burst of keystrokes only results in one parse.
+const ANALYSIS_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// The longest to put off re-analysing while edits keep arriving, so that diagnostics
+/// still appear under continuous typing.
+const ANALYSIS_MAX_DELAY: Duration = Duration::from_millis(500);
 
 /// The result of resolving a StringLiteral when looking up a definition.
 #[derive(Derivative)]
@@ -89,21 +151,32 @@ pub struct LspEvalResult {
 /// Settings that the LspContext can provide to change what capabilities the server enables
 /// or disables.
 #[derive(Dupe, Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct LspServerSettings {
     /// Whether goto definition should work.
     pub enable_goto_definition: bool,
+    /// When formatting, split bracketed expressions which would make a line longer than this.
+    pub format_max_line_length: usize,
+    /// When formatting, sort runs of `load` statements and the symbols within them.
+    pub format_sort_loads: bool,
 }
 
 impl Default for LspServerSettings {
     fn default() -> Self {
+        let format = FormatOptions::default();
         Self {
             enable_goto_definition: true,
+            format_max_line_length: format.max_line_length,
+            format_sort_loads: format.sort_loads,
         }
     }
 }
 
 /// Various pieces of context to allow the LSP to interact with starlark parsers, etc.
-pub trait LspContext {
+///
+/// Documents are analysed on a thread of their own, so that requests are answered while
+/// that happens, which is why the context must be `Sync`.
+pub trait LspContext: Sync {
     /// Parse a file with the given contents. The filename is used in the diagnostics.
     fn parse_file_with_contents(&self, uri: &Url, content: String) -> LspEvalResult;
 
@@ -130,6 +203,14 @@ pub trait LspContext {
     /// Get the contents of a starlark program at a given path, if it exists.
     fn get_load_contents(&self, uri: &Url) -> anyhow::Result<Option<String>>;
 
+    /// Get the documentation of a global symbol the context makes available, such as a
+    /// native function. Used to label the arguments of calls with their parameter names.
+    ///
+    /// By default, nothing is known about globals.
+    fn get_global_documentation(&self, _name: &str) -> Option<DocItem> {
+        None
+    }
+
     /// Get the contents of a file at a given URI, and attempt to parse it.
     fn parse_file(&self, uri: &Url) -> anyhow::Result<Option<LspEvalResult>> {
         let result = self
@@ -162,6 +243,9 @@ struct Backend<T: LspContext> {
     /// The `AstModule` from the last time that a file was opened / changed and parsed successfully.
     /// Entries are evicted when the file is closed.
     last_valid_parse: RwLock<HashMap<Url, Arc<LspModule>>>,
+    /// The latest contents of every file the client has open.
+    documents: RwLock<HashMap<Url, LspDocument>>,
+    settings: LspServerSettings,
 }
 
 /// The logic implementations of stuff
@@ -175,8 +259,26 @@ impl<T: LspContext> Backend<T> {
             })
         });
         ServerCapabilities {
-            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(
+                TextDocumentSyncKind::INCREMENTAL,
+            )),
             definition_provider,
+            code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+            semantic_tokens_provider: Some(
+                SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+                    work_done_progress_options: WorkDoneProgressOptions {
+                        work_done_progress: None,
+                    },
+                    legend: legend(),
+                    range: Some(true),
+                    full: Some(SemanticTokensFullOptions::Bool(true)),
+                }),
+            ),
+            folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+            document_highlight_provider: Some(OneOf::Left(true)),
+            inlay_hint_provider: Some(OneOf::Left(true)),
+            document_formatting_provider: Some(OneOf::Left(true)),
+            document_range_formatting_provider: Some(OneOf::Left(true)),
             ..ServerCapabilities::default()
         }
     }
@@ -197,36 +299,91 @@ impl<T: LspContext> Backend<T> {
         Ok(module)
     }
 
-    fn validate(&self, uri: Url, version: Option<i64>, text: String) {
-        let eval_result = self.context.parse_file_with_contents(&uri, text);
+    /// Parse and lint the latest version of a document. If the document is closed or changed
+    /// while that is happening, the now stale result is thrown away.
+    fn validate(&self, uri: &Url) {
+        let document = self.documents.read().unwrap().get(uri).cloned();
+        let document = match document {
+            Some(document) => document,
+            None => return,
+        };
+        let eval_result = self.context.parse_file_with_contents(uri, document.text);
+
+        // Hold the lock while publishing so that a `didClose` can't slip in between.
+        let documents = self.documents.read().unwrap();
+        if documents.get(uri).map(|x| x.version) != Some(document.version) {
+            return;
+        }
         if let Some(ast) = eval_result.ast {
             let module = Arc::new(LspModule::new(ast));
             let mut last_valid_parse = self.last_valid_parse.write().unwrap();
             last_valid_parse.insert(uri.clone(), module);
         }
-        self.publish_diagnostics(uri, eval_result.diagnostics, version)
+        self.publish_diagnostics(
+            uri.clone(),
+            eval_result.diagnostics,
+            Some(document.version as i64),
+        )
     }
 
-    fn did_open(&self, params: DidOpenTextDocumentParams) {
-        self.validate(
-            params.text_document.uri,
-            Some(params.text_document.version as i64),
-            params.text_document.text,
-        )
+    /// Analyse documents as they are queued up by the message loop, until it shuts down.
+    /// A panic analysing one document is reported, rather than ending the analysis thread.
+    fn analysis_loop(&self, receiver: mpsc::Receiver<Url>) {
+        while let Ok(uri) = receiver.recv() {
+            let pending = debounce(
+                uri,
+                |timeout| receiver.recv_timeout(timeout).ok(),
+                Instant::now,
+                ANALYSIS_DEBOUNCE,
+                ANALYSIS_MAX_DELAY,
+            );
+            for uri in &pending {
+                if panic::catch_unwind(AssertUnwindSafe(|| self.validate(uri))).is_err() {
+                    self.log_message(
+                        MessageType::ERROR,
+                        &format!("Analysis of `{}` panicked", uri),
+                    );
+                }
+            }
+        }
     }
 
-    fn did_change(&self, params: DidChangeTextDocumentParams) {
-        // We asked for Sync full, so can just grab all the text from params
-        let change = params.content_changes.into_iter().next().unwrap();
-        self.validate(
-            params.text_document.uri,
-            Some(params.text_document.version as i64),
-            change.text,
+    /// Queue up a document for analysis. If the analysis thread has stopped, the diagnostics
+    /// can no longer be updated, so tell the client rather than failing the request.
+    fn analyse(&self, uri: Url, analysis: &mpsc::Sender<Url>) {
+        if let Err(mpsc::SendError(uri)) = analysis.send(uri) {
+            self.log_message(
+                MessageType::ERROR,
+                &format!("Analysis has stopped, so can't analyse `{}`", uri),
+            );
+        }
+    }
+
+    fn did_open(&self, params: DidOpenTextDocumentParams, analysis: &mpsc::Sender<Url>) {
+        let document = params.text_document;
+        self.documents.write().unwrap().insert(
+            document.uri.clone(),
+            LspDocument::new(document.version, document.text),
         );
+        self.analyse(document.uri, analysis);
+    }
+
+    fn did_change(&self, params: DidChangeTextDocumentParams, analysis: &mpsc::Sender<Url>) {
+        let uri = params.text_document.uri;
+        self.documents
+            .write()
+            .unwrap()
+            .entry(uri.clone())
+            // If we never saw it opened, the changes had better be a full update.
+            .or_insert_with(|| LspDocument::new(0, String::new()))
+            .apply_changes(params.text_document.version, params.content_changes);
+        self.analyse(uri, analysis);
     }
 
     fn did_close(&self, params: DidCloseTextDocumentParams) {
         {
+            let mut documents = self.documents.write().unwrap();
+            documents.remove(&params.text_document.uri);
             let mut last_valid_parse = self.last_valid_parse.write().unwrap();
             last_valid_parse.remove(&params.text_document.uri);
         }
@@ -243,6 +400,185 @@ impl<T: LspContext> Backend<T> {
         self.send_response(new_response(id, self.find_definition(params)));
     }
 
+    /// Offer the fixes attached to the diagnostics the client sends back as quick fixes,
+    /// and for each lint, one which disables it on its line with a comment.
+    ///
+    /// The fixes travel in the `data` field of each diagnostic, so this does not need to
+    /// re-run the linter, and works even if the file no longer parses.
+    fn code_action(&self, id: RequestId, params: CodeActionParams) {
+        self.send_response(new_response(id, Ok(self.code_actions(params))));
+    }
+
+    fn code_actions(&self, params: CodeActionParams) -> CodeActionResponse {
+        let uri = params.text_document.uri;
+        let mut res = Vec::new();
+        for diagnostic in params.context.diagnostics {
+            let fixes: Vec<DiagnosticFix> = match &diagnostic.data {
+                Some(data) => serde_json::from_value(data.clone()).unwrap_or_default(),
+                None => Vec::new(),
+            };
+            for fix in fixes {
+                res.push(quick_fix(&uri, &diagnostic, fix.title, fix.edits));
+            }
+            if let Some((title, edit)) = self.disable_lint_edit(&uri, &diagnostic) {
+                res.push(quick_fix(&uri, &diagnostic, title, vec![edit]));
+            }
+        }
+        res
+    }
+
+    /// The edit which adds a `# starlark-lint: disable=` comment for the lint reported by
+    /// the diagnostic to the end of its line, if the last valid parse of the file is still
+    /// up to date, so we know where the line ends.
+    fn disable_lint_edit(&self, uri: &Url, diagnostic: &Diagnostic) -> Option<(String, TextEdit)> {
+        let name = match &diagnostic.code {
+            Some(NumberOrString::String(name)) if name != "error" => name,
+            _ => return None,
+        };
+        let document = self.documents.read().unwrap().get(uri).cloned()?;
+        let module = self.get_ast(uri)?;
+        if module.ast.codemap.source() != document.text {
+            return None;
+        }
+        let line = diagnostic.range.start.line as usize;
+        let (offset, text) = disable_lint_edit(&module.ast, line, name)?;
+        let position = document.position(offset);
+        Some((
+            format!("Disable `{}` on this line", name),
+            TextEdit::new(Range::new(position, position), text),
+        ))
+    }
+
+    /// Colour the identifiers in the last valid parse of a file by how they are bound.
+    fn semantic_tokens_full(&self, id: RequestId, params: SemanticTokensParams) {
+        let tokens = self
+            .get_ast(&params.text_document.uri)
+            .map(|module| SemanticTokens {
+                result_id: None,
+                data: semantic_tokens(&module.ast, None),
+            });
+        self.send_response(new_response(id, Ok(tokens)));
+    }
+
+    fn semantic_tokens_range(&self, id: RequestId, params: SemanticTokensRangeParams) {
+        let tokens = self
+            .get_ast(&params.text_document.uri)
+            .map(|module| SemanticTokens {
+                result_id: None,
+                data: semantic_tokens(&module.ast, Some(params.range)),
+            });
+        self.send_response(new_response(id, Ok(tokens)));
+    }
+
+    fn folding_range(&self, id: RequestId, params: FoldingRangeParams) {
+        let ranges: Option<Vec<FoldingRange>> = self
+            .get_ast(&params.text_document.uri)
+            .map(|module| module.ast.folding_ranges());
+        self.send_response(new_response(id, Ok(ranges)));
+    }
+
+    /// Highlight the reads and writes of the variable under the cursor, using the last
+    /// valid parse of the file.
+    fn document_highlight(&self, id: RequestId, params: DocumentHighlightParams) {
+        let position = params.text_document_position_params;
+        let highlights = self.get_ast(&position.text_document.uri).map(|module| {
+            let offset = document::offset(module.ast.codemap.source(), position.position);
+            module.ast.document_highlights(Pos::new(offset as u32))
+        });
+        self.send_response(new_response(id, Ok(highlights)));
+    }
+
+    /// Name the parameters positional arguments are passed to, and the types of variables
+    /// where they are obvious, using the last valid parse of the file.
+    fn inlay_hint(&self, id: RequestId, params: InlayHintParams) {
+        let hints = self.get_ast(&params.text_document.uri).map(|module| {
+            inlay_hints(&module.ast, params.range, |name| {
+                self.global_parameters(name)
+            })
+        });
+        self.send_response(new_response(id, Ok(hints)));
+    }
+
+    /// The parameters of a global function which can be passed positionally.
+    fn global_parameters(&self, name: &str) -> Option<Vec<String>> {
+        match self.context.get_global_documentation(name)? {
+            DocItem::Function(function) => Some(
+                function
+                    .params
+                    .into_iter()
+                    .map_while(|x| match x {
+                        Param::Arg { name, .. } => Some(name),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Lay out the whole of an open document canonically.
+    ///
+    /// This parses the latest text rather than using the last valid parse, as the edits
+    /// have to apply to what the client currently has. If it doesn't parse, there are no edits.
+    fn formatting(&self, id: RequestId, params: DocumentFormattingParams) {
+        let edits = self.format_edits(&params.text_document.uri, &params.options, None);
+        self.send_response(new_response(id, Ok(edits)));
+    }
+
+    /// Lay out the top-level statements which overlap the selected lines.
+    fn range_formatting(&self, id: RequestId, params: DocumentRangeFormattingParams) {
+        let edits = self.format_edits(
+            &params.text_document.uri,
+            &params.options,
+            Some(params.range),
+        );
+        self.send_response(new_response(id, Ok(edits)));
+    }
+
+    /// Indentation is always `tab_size` spaces: `insert_spaces` is ignored, because
+    /// Starlark rejects tabs in indentation, so indenting with them would break the file.
+    fn format_edits(
+        &self,
+        uri: &Url,
+        options: &FormattingOptions,
+        range: Option<Range>,
+    ) -> Option<Vec<TextEdit>> {
+        let document = self.documents.read().unwrap().get(uri).cloned()?;
+        let ast = self
+            .context
+            .parse_file_with_contents(uri, document.text.clone())
+            .ast?;
+        let options = FormatOptions {
+            indent_width: options.tab_size as usize,
+            max_line_length: self.settings.format_max_line_length,
+            sort_loads: self.settings.format_sort_loads,
+        };
+        let (begin, end, text) = match range {
+            None => (0, document.text.len(), ast.format(&options)),
+            Some(range) => {
+                // A selection which ends at the start of a line doesn't include that line.
+                let last = if range.end.character == 0 && range.end.line > range.start.line {
+                    range.end.line - 1
+                } else {
+                    range.end.line
+                };
+                match ast.format_lines(&options, range.start.line as usize, last as usize) {
+                    Some((span, text)) => {
+                        (span.begin().get() as usize, span.end().get() as usize, text)
+                    }
+                    None => return Some(Vec::new()),
+                }
+            }
+        };
+        if document.text[begin..end] == text {
+            return Some(Vec::new());
+        }
+        Some(vec![TextEdit {
+            range: Range::new(document.position(begin), document.position(end)),
+            new_text: text,
+        }])
+    }
+
     fn resolve_load_path(&self, path: &str, current_uri: &Url) -> anyhow::Result<Url> {
         let current_file = Path::new(current_uri.path());
         self.context.resolve_load(path, current_file)
@@ -388,25 +724,80 @@ impl<T: LspContext> Backend<T> {
 
     fn main_loop(&self, _params: InitializeParams) -> anyhow::Result<()> {
         self.log_message(MessageType::INFO, "Starlark server initialised");
-        for msg in &self.connection.receiver {
-            match msg {
+        let (analysis, analysis_receiver) = mpsc::channel();
+        thread::scope(|s| {
+            s.spawn(|| self.analysis_loop(analysis_receiver));
+            // Returning drops `analysis`, which lets the analysis thread finish.
+            self.message_loop(analysis)
+        })
+    }
+
+    fn message_loop(&self, analysis: mpsc::Sender<Url>) -> anyhow::Result<()> {
+        let mut queue = VecDeque::new();
+        let mut cancelled = HashSet::new();
+        loop {
+            if queue.is_empty() {
+                match self.connection.receiver.recv() {
+                    Ok(msg) => queue.push_back(msg),
+                    Err(_) => return Ok(()),
+                }
+            }
+            // Look at everything that has already arrived, so that requests cancelled
+            // before we get to them are never run.
+            for msg in self.connection.receiver.try_iter() {
+                if let Message::Notification(x) = &msg {
+                    if let Some(params) = as_notification::<Cancel>(x) {
+                        cancelled.insert(request_id(params.id));
+                    }
+                }
+                queue.push_back(msg);
+            }
+
+            match queue.pop_front().unwrap() {
                 Message::Request(req) => {
                     // TODO(nmj): Also implement DocumentSymbols so that some logic can
                     //            be handled client side.
-                    if let Some(params) = as_request::<GotoDefinition>(&req) {
+                    if cancelled.remove(&req.id) {
+                        self.send_response(Response::new_err(
+                            req.id,
+                            ErrorCode::RequestCanceled as i32,
+                            "Request cancelled".to_owned(),
+                        ));
+                    } else if let Some(params) = as_request::<GotoDefinition>(&req) {
                         self.goto_definition(req.id, params);
-                    } else if self.connection.handle_shutdown(&req)? {
-                        return Ok(());
+                    } else if let Some(params) = as_request::<CodeActionRequest>(&req) {
+                        self.code_action(req.id, params);
+                    } else if let Some(params) = as_request::<SemanticTokensFullRequest>(&req) {
+                        self.semantic_tokens_full(req.id, params);
+                    } else if let Some(params) = as_request::<SemanticTokensRangeRequest>(&req) {
+                        self.semantic_tokens_range(req.id, params);
+                    } else if let Some(params) = as_request::<FoldingRangeRequest>(&req) {
+                        self.folding_range(req.id, params);
+                    } else if let Some(params) = as_request::<DocumentHighlightRequest>(&req) {
+                        self.document_highlight(req.id, params);
+                    } else if let Some(params) = as_request::<InlayHintRequest>(&req) {
+                        self.inlay_hint(req.id, params);
+                    } else if let Some(params) = as_request::<Formatting>(&req) {
+                        self.formatting(req.id, params);
+                    } else if let Some(params) = as_request::<RangeFormatting>(&req) {
+                        self.range_formatting(req.id, params);
+                    } else if req.method == <Shutdown as lsp_types::request::Request>::METHOD {
+                        self.send_response(Response::new_ok(req.id, ()));
                     }
                     // Currently don't handle any other requests
                 }
                 Message::Notification(x) => {
                     if let Some(params) = as_notification::<DidOpenTextDocument>(&x) {
-                        self.did_open(params)
+                        self.did_open(params, &analysis)
                     } else if let Some(params) = as_notification::<DidChangeTextDocument>(&x) {
-                        self.did_change(params)
+                        self.did_change(params, &analysis)
                     } else if let Some(params) = as_notification::<DidCloseTextDocument>(&x) {
                         self.did_close(params)
+                    } else if let Some(params) = as_notification::<Cancel>(&x) {
+                        // Any request this refers to has been dealt with by now.
+                        cancelled.remove(&request_id(params.id));
+                    } else if x.method == <Exit as lsp_types::notification::Notification>::METHOD {
+                        return Ok(());
                     }
                 }
                 Message::Response(_) => {
@@ -414,11 +805,67 @@ impl<T: LspContext> Backend<T> {
                 }
             }
         }
-        Ok(())
     }
 }
 
-/// Instantiate an LSP server that reads on stdin, and writes to stdout
+/// Wait for the client to go quiet, collecting the documents changed in the meantime after
+/// `first`, so we skip the versions that are already stale. It is quiet once nothing has
+/// arrived for `quiet`, and we stop waiting after `max_delay` regardless.
+///
+/// `recv_timeout` waits up to the given time for the next document, and `now` reads the
+/// clock, so tests can run this without waiting in real time.
+fn debounce(
+    first: Url,
+    mut recv_timeout: impl FnMut(Duration) -> Option<Url>,
+    now: impl Fn() -> Instant,
+    quiet: Duration,
+    max_delay: Duration,
+) -> Vec<Url> {
+    let mut pending = vec![first];
+    let deadline = now() + max_delay;
+    while let Some(remaining) = deadline.checked_duration_since(now()) {
+        match recv_timeout(quiet.min(remaining)) {
+            Some(uri) => {
+                if !pending.contains(&uri) {
+                    pending.push(uri);
+                }
+            }
+            None => break,
+        }
+    }
+    pending
+}
+
+/// A quick fix which makes the `edits` to the file `uri` to resolve the `diagnostic`.
+fn quick_fix(
+    uri: &Url,
+    diagnostic: &Diagnostic,
+    title: String,
+    edits: Vec<TextEdit>,
+) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit {
+            changes: Some(HashMap::from([(uri.clone(), edits)])),
+            ..WorkspaceEdit::default()
+        }),
+        ..CodeAction::default()
+    })
+}
+
+fn request_id(id: NumberOrString) -> RequestId {
+    match id {
+        NumberOrString::Number(x) => RequestId::from(x),
+        NumberOrString::String(x) => RequestId::from(x),
+    }
+}
+
+/// Instantiate an LSP server that reads on stdin, and writes to stdout.
+///
+/// Documents are analysed on a separate thread to the one answering requests,
+/// so the context must be shareable between threads.
 pub fn stdio_server<T: LspContext>(context: T) -> anyhow::Result<()> {
     // Note that  we must have our logging only write out to stderr.
     eprintln!("Starting Rust Starlark server");
@@ -441,12 +888,12 @@ pub fn server_with_connection<T: LspContext>(
     let (init_request_id, init_value) = connection.initialize_start()?;
 
     let initialization_params: InitializeParams = serde_json::from_value(init_value)?;
-    let server_settings = initialization_params
+    let server_settings: LspServerSettings = initialization_params
         .initialization_options
         .as_ref()
         .and_then(|opts| serde_json::from_value(opts.clone()).ok())
         .unwrap_or_default();
-    let capabilities_payload = Backend::<T>::server_capabilities(server_settings);
+    let capabilities_payload = Backend::<T>::server_capabilities(server_settings.dupe());
     let server_capabilities = serde_json::to_value(&capabilities_payload).unwrap();
 
     let initialize_data = serde_json::json!({
@@ -458,6 +905,8 @@ pub fn server_with_connection<T: LspContext>(
         connection,
         context,
         last_valid_parse: RwLock::default(),
+        documents: RwLock::default(),
+        settings: server_settings,
     }
     .main_loop(initialization_params)?;
 
@@ -534,23 +983,54 @@ where
 #[cfg(all(test, not(windows)))]
 mod test {
     use std::path::Path;
+    use std::cell::Cell;
     use std::path::PathBuf;
+    use std::time::Duration;
+    use std::time::Instant;
 
     use lsp_server::Request;
     use lsp_server::RequestId;
+    use lsp_types::notification::PublishDiagnostics;
+    use lsp_types::request::CodeActionRequest;
+    use lsp_types::request::DocumentHighlightRequest;
+    use lsp_types::request::FoldingRangeRequest;
+    use lsp_types::request::Formatting;
     use lsp_types::request::GotoDefinition;
+    use lsp_types::request::InlayHintRequest;
+    use lsp_types::request::RangeFormatting;
+    use lsp_types::request::SemanticTokensFullRequest;
+    use lsp_types::CodeActionContext;
+    use lsp_types::CodeActionOrCommand;
+    use lsp_types::CodeActionParams;
+    use lsp_types::CodeActionResponse;
+    use lsp_types::DocumentFormattingParams;
+    use lsp_types::DocumentHighlight;
+    use lsp_types::DocumentHighlightKind;
+    use lsp_types::DocumentHighlightParams;
+    use lsp_types::DocumentRangeFormattingParams;
+    use lsp_types::FoldingRange;
+    use lsp_types::FoldingRangeParams;
+    use lsp_types::FormattingOptions;
     use lsp_types::GotoDefinitionParams;
     use lsp_types::GotoDefinitionResponse;
+    use lsp_types::InlayHint;
+    use lsp_types::InlayHintLabel;
+    use lsp_types::InlayHintParams;
     use lsp_types::LocationLink;
+    use lsp_types::NumberOrString;
     use lsp_types::Position;
     use lsp_types::Range;
+    use lsp_types::SemanticTokensParams;
+    use lsp_types::SemanticTokensResult;
     use lsp_types::TextDocumentIdentifier;
     use lsp_types::TextDocumentPositionParams;
+    use lsp_types::TextEdit;
     use lsp_types::Url;
     use textwrap::dedent;
 
     use crate::analysis::FixtureWithRanges;
     use crate::codemap::ResolvedSpan;
+    use crate::lsp::server::debounce;
     use crate::lsp::server::LspServerSettings;
     use crate::lsp::test::TestServer;
 
@@ -705,6 +1185,31 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn applies_incremental_changes() -> anyhow::Result<()> {
+        let uri = temp_file_uri("file.star");
+        let expected_location = expected_location_link(uri.clone(), 4, 6, 13, 2, 4, 11);
+
+        let mut server = TestServer::new()?;
+        let contents = "y = 1\ndef nothing():\n    pass\nprint(nothing())\n";
+        server.open_file(uri.clone(), contents.to_owned())?;
+        server.edit_file(
+            uri.clone(),
+            Range::new(Position::new(0, 0), Position::new(0, 0)),
+            "x = 1\n".to_owned(),
+        )?;
+        let diagnostics = server.get_notification::<PublishDiagnostics>()?;
+        assert_eq!(Some(2), diagnostics.version);
+
+        let goto_definition = goto_definition_request(&mut server, uri, 4, 6);
+
+        let request_id = server.send_request(goto_definition)?;
+        let location = goto_definition_response_location(&mut server, request_id)?;
+
+        assert_eq!(expected_location, location);
+        Ok(())
+    }
+
     #[test]
     fn jumps_to_definition_from_opened_loaded_file() -> anyhow::Result<()> {
         let foo_uri = temp_file_uri("foo.star");
@@ -1330,6 +1835,7 @@ mod test {
     fn disables_goto_definition() -> anyhow::Result<()> {
         let server = TestServer::new_with_settings(Some(LspServerSettings {
             enable_goto_definition: false,
+            ..LspServerSettings::default()
         }))?;
 
         let goto_definition_disabled = server
@@ -1343,6 +1849,7 @@ mod test {
 
         let server = TestServer::new_with_settings(Some(LspServerSettings {
             enable_goto_definition: true,
+            ..LspServerSettings::default()
         }))?;
 
         let goto_definition_enabled = server
@@ -1355,4 +1862,335 @@ mod test {
         assert!(goto_definition_enabled);
         Ok(())
     }
+
+    #[test]
+    fn debounce_waits_for_changes_to_stop() {
+        let uri = temp_file_uri("file.star");
+        let other = temp_file_uri("other.star");
+        let start = Instant::now();
+        let clock = Cell::new(start);
+        // Changes arrive every 10ms, then stop.
+        let mut changes = vec![uri.clone(), other.clone(), uri.clone()].into_iter();
+        let recv_timeout = |timeout: Duration| match changes.next() {
+            Some(x) => {
+                clock.set(clock.get() + Duration::from_millis(10));
+                Some(x)
+            }
+            None => {
+                clock.set(clock.get() + timeout);
+                None
+            }
+        };
+        let quiet = Duration::from_millis(50);
+        let pending = debounce(
+            uri.clone(),
+            recv_timeout,
+            || clock.get(),
+            quiet,
+            Duration::from_millis(500),
+        );
+        assert_eq!(vec![uri, other], pending);
+        assert_eq!(clock.get() - start, Duration::from_millis(30) + quiet);
+    }
+
+    #[test]
+    fn debounce_gives_up_under_continuous_changes() {
+        let uri = temp_file_uri("file.star");
+        let start = Instant::now();
+        let clock = Cell::new(start);
+        // A change arrives every 10ms, forever.
+        let recv_timeout = |timeout: Duration| {
+            let next = Duration::from_millis(10);
+            if timeout < next {
+                clock.set(clock.get() + timeout);
+                None
+            } else {
+                clock.set(clock.get() + next);
+                Some(uri.clone())
+            }
+        };
+        let max_delay = Duration::from_millis(500);
+        let pending = debounce(
+            uri.clone(),
+            recv_timeout,
+            || clock.get(),
+            Duration::from_millis(50),
+            max_delay,
+        );
+        assert_eq!(vec![uri.clone()], pending);
+        assert_eq!(clock.get() - start, max_delay);
+    }
+
+    #[test]
+    fn offers_quick_fixes_for_lints() -> anyhow::Result<()> {
+        let uri = temp_file_uri("file.star");
+
+        let mut server = TestServer::new()?;
+        let contents = "load(\"foo.star\", \"used\", \"unused\")\nused()\n";
+        let diagnostics = server.open_file(uri.clone(), contents.to_owned())?;
+
+        let req = server.new_request::<CodeActionRequest>(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: Range::default(),
+            context: CodeActionContext {
+                diagnostics: diagnostics.diagnostics,
+                only: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<CodeActionResponse>(request_id)?;
+
+        // Only look at the fixes for the unused load, the loads are also unsorted.
+        let unused_load = Some(NumberOrString::String("unused-load".to_owned()));
+        let actions: Vec<_> = response
+            .into_iter()
+            .filter_map(|x| match x {
+                CodeActionOrCommand::CodeAction(x) => Some(x),
+                CodeActionOrCommand::Command(_) => None,
+            })
+            .filter(|x| {
+                x.diagnostics
+                    .iter()
+                    .flatten()
+                    .any(|d| d.code == unused_load)
+            })
+            .collect();
+        assert_eq!(2, actions.len());
+        assert_eq!("Remove unused load of `unused`", actions[0].title);
+        assert_eq!("Disable `unused-load` on this line", actions[1].title);
+        let changes = actions[0].edit.as_ref().unwrap().changes.as_ref().unwrap();
+        assert_eq!(
+            &vec![TextEdit::new(
+                Range::new(Position::new(0, 23), Position::new(0, 33)),
+                String::new()
+            )],
+            changes.get(&uri).unwrap()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn offers_to_disable_lints() -> anyhow::Result<()> {
+        let uri = temp_file_uri("file.star");
+
+        let mut server = TestServer::new()?;
+        let contents = "def f():\n    x = 1\n";
+        let diagnostics = server.open_file(uri.clone(), contents.to_owned())?;
+        let unused_assign = Some(NumberOrString::String("unused-assign".to_owned()));
+        let diagnostic = diagnostics
+            .diagnostics
+            .into_iter()
+            .find(|x| x.code == unused_assign)
+            .unwrap();
+
+        let req = server.new_request::<CodeActionRequest>(CodeActionParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: diagnostic.range,
+            context: CodeActionContext {
+                diagnostics: vec![diagnostic],
+                only: None,
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<CodeActionResponse>(request_id)?;
+        let edits = response
+            .into_iter()
+            .find_map(|x| match x {
+                CodeActionOrCommand::CodeAction(x)
+                    if x.title == "Disable `unused-assign` on this line" =>
+                {
+                    x.edit?.changes?.remove(&uri)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(
+            vec![TextEdit::new(
+                Range::new(Position::new(1, 9), Position::new(1, 9)),
+                "  # starlark-lint: disable=unused-assign".to_owned()
+            )],
+            edits
+        );
+
+        server.edit_file(uri, edits[0].range, edits[0].new_text.clone())?;
+        let diagnostics = server.get_notification::<PublishDiagnostics>()?;
+        assert!(diagnostics.diagnostics.iter().all(|x| x.code != unused_assign));
+        Ok(())
+    }
+
+    #[test]
+    fn provides_semantic_tokens_and_folding_ranges() -> anyhow::Result<()> {
+        let uri = temp_file_uri("file.star");
+
+        let mut server = TestServer::new()?;
+        let contents = "def f(x):\n    return x\n";
+        server.open_file(uri.clone(), contents.to_owned())?;
+
+        let req = server.new_request::<SemanticTokensFullRequest>(SemanticTokensParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Option<SemanticTokensResult>>(request_id)?;
+        let tokens = match response {
+            Some(SemanticTokensResult::Tokens(tokens)) => tokens.data,
+            _ => panic!("Expected semantic tokens, got {:?}", response),
+        };
+        let positions: Vec<_> = tokens
+            .iter()
+            .map(|x| (x.delta_line, x.delta_start, x.length, x.token_type))
+            .collect();
+        assert_eq!(vec![(0, 4, 1, 1), (0, 2, 1, 0), (1, 11, 1, 0)], positions);
+
+        let req = server.new_request::<FoldingRangeRequest>(FoldingRangeParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Option<Vec<FoldingRange>>>(request_id)?;
+        let ranges: Vec<_> = response
+            .unwrap()
+            .into_iter()
+            .map(|x| (x.start_line, x.end_line))
+            .collect();
+        assert_eq!(vec![(0, 1)], ranges);
+        Ok(())
+    }
+
+    #[test]
+    fn formats_documents() -> anyhow::Result<()> {
+        let uri = temp_file_uri("file.star");
+
+        let mut server = TestServer::new()?;
+        let contents = "x=1\ndef f( a ):\n  return a # é\n";
+        server.open_file(uri.clone(), contents.to_owned())?;
+        let options = FormattingOptions {
+            tab_size: 2,
+            insert_spaces: true,
+            ..FormattingOptions::default()
+        };
+
+        let req = server.new_request::<Formatting>(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            options: options.clone(),
+            work_done_progress_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Option<Vec<TextEdit>>>(request_id)?;
+        assert_eq!(
+            Some(vec![TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(3, 0)),
+                "x = 1\ndef f(a):\n  return a  # é\n".to_owned(),
+            )]),
+            response
+        );
+
+        // Asking for tabs still indents with spaces.
+        let req = server.new_request::<Formatting>(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            options: FormattingOptions {
+                insert_spaces: false,
+                ..options.clone()
+            },
+            work_done_progress_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Option<Vec<TextEdit>>>(request_id)?;
+        assert_eq!(
+            Some(vec![TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(3, 0)),
+                "x = 1\ndef f(a):\n  return a  # é\n".to_owned(),
+            )]),
+            response
+        );
+
+        let req = server.new_request::<RangeFormatting>(DocumentRangeFormattingParams {
+            text_document: TextDocumentIdentifier { uri: uri.clone() },
+            range: Range::new(Position::new(0, 0), Position::new(0, 1)),
+            options: options.clone(),
+            work_done_progress_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Option<Vec<TextEdit>>>(request_id)?;
+        assert_eq!(
+            Some(vec![TextEdit::new(
+                Range::new(Position::new(0, 0), Position::new(0, 3)),
+                "x = 1".to_owned(),
+            )]),
+            response
+        );
+
+        // Documents which don't parse are left alone.
+        server.edit_file(
+            uri.clone(),
+            Range::new(Position::new(0, 0), Position::new(3, 0)),
+            "def f(:\n".to_owned(),
+        )?;
+        let req = server.new_request::<Formatting>(DocumentFormattingParams {
+            text_document: TextDocumentIdentifier { uri },
+            options,
+            work_done_progress_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Option<Vec<TextEdit>>>(request_id)?;
+        assert_eq!(None, response);
+        Ok(())
+    }
+
+    #[test]
+    fn provides_document_highlights_and_inlay_hints() -> anyhow::Result<()> {
+        let uri = temp_file_uri("file.star");
+
+        let mut server = TestServer::new()?;
+        let contents = "def f(value):\n    return value\nx = f(1)\n";
+        server.open_file(uri.clone(), contents.to_owned())?;
+
+        let req = server.new_request::<DocumentHighlightRequest>(DocumentHighlightParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri: uri.clone() },
+                position: Position::new(1, 12),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Option<Vec<DocumentHighlight>>>(request_id)?;
+        assert_eq!(
+            Some(vec![
+                DocumentHighlight {
+                    range: Range::new(Position::new(0, 6), Position::new(0, 11)),
+                    kind: Some(DocumentHighlightKind::WRITE),
+                },
+                DocumentHighlight {
+                    range: Range::new(Position::new(1, 11), Position::new(1, 16)),
+                    kind: Some(DocumentHighlightKind::READ),
+                },
+            ]),
+            response
+        );
+
+        let req = server.new_request::<InlayHintRequest>(InlayHintParams {
+            text_document: TextDocumentIdentifier { uri },
+            range: Range::new(Position::new(0, 0), Position::new(3, 0)),
+            work_done_progress_params: Default::default(),
+        });
+        let request_id = server.send_request(req)?;
+        let response = server.get_response::<Option<Vec<InlayHint>>>(request_id)?;
+        let hints: Vec<_> = response
+            .unwrap()
+            .into_iter()
+            .map(|x| match x.label {
+                InlayHintLabel::String(label) => (x.position, label),
+                InlayHintLabel::LabelParts(_) => panic!("Expected a string label"),
+            })
+            .collect();
+        assert_eq!(vec![(Position::new(2, 6), "value:".to_owned())], hints);
+        Ok(())
+    }
 }
@@ -105,7 +105,9 @@ impl LspContext for TestServerContext {
     fn parse_file_with_contents(&self, uri: &Url, content: String) -> LspEvalResult {
         match AstModule::parse(uri.path(), content, &Dialect::Extended) {
             Ok(ast) => {
-                let diagnostics = ast.lint(None).into_map(|l| EvalMessage::from(l).into());
+                let diagnostics = ast
+                    .lint(None)
+                    .into_map(|l| EvalMessage::from(l).into());
                 LspEvalResult {
                     diagnostics,
                     ast: Some(ast),
@@ -481,6 +483,24 @@ impl TestServer {
         Ok(())
     }
 
+    /// Send a notification saying that the text at `range` in a file was replaced with `text`.
+    pub fn edit_file(&mut self, uri: Url, range: Range, text: String) -> anyhow::Result<()> {
+        let change_params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri,
+                version: self.next_document_version(),
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(range),
+                range_length: None,
+                text,
+            }],
+        };
+        let change_notification = new_notification::<DidChangeTextDocument>(change_params);
+        self.send_notification(change_notification)?;
+        Ok(())
+    }
+
     /// Set the file contents that `get_load_contents()` will return. The path must be absolute.
     pub fn set_file_contents(&self, path: PathBuf, contents: String) -> anyhow::Result<()> {
         let path = get_path_from_uri(&format!("{}", path.display()));
@@ -25,6 +25,7 @@ use gazebo::coerce::Coerce;
 use gazebo::prelude::*;
 use itertools::Itertools;
 
+use crate::codemap::FileSpan;
 use crate::collections::symbol_map::Symbol;
 use crate::environment::GlobalsBuilder;
 use crate::eval::runtime::arguments::ArgNames;
@@ -174,6 +175,12 @@ impl fmt::Display for PrintWrapper<'_, '_> {
 pub trait PrintHandler {
     /// If this function returns error, evaluation fails with this error.
     fn println(&self, text: &str) -> anyhow::Result<()>;
+
+    /// Like [`println`](PrintHandler::println), but also given the location of the call to
+    /// `print` or `pprint`, if known. By default, the location is ignored.
+    fn println_at(&self, text: &str, _location: Option<FileSpan>) -> anyhow::Result<()> {
+        self.println(text)
+    }
 }
 
 pub(crate) struct StderrPrintHandler;
@@ -190,8 +197,9 @@ pub fn print(builder: &mut GlobalsBuilder) {
     fn print(#[starlark(args)] args: Vec<Value>, eval: &mut Evaluator) -> anyhow::Result<NoneType> {
         // In practice most users should want to put the print somewhere else, but this does for now
         // Unfortunately, we can't use PrintWrapper because strings to_str() and Display are different.
+        let location = eval.call_stack_top_location();
         eval.print_handler
-            .println(&args.iter().map(|x| x.to_str()).join(" "))?;
+            .println_at(&args.iter().map(|x| x.to_str()).join(" "), location)?;
         Ok(NoneType)
     }
 }
@@ -203,8 +211,9 @@ pub fn pprint(builder: &mut GlobalsBuilder) {
         eval: &mut Evaluator,
     ) -> anyhow::Result<NoneType> {
         // In practice most users may want to put the print somewhere else, but this does for now
+        let location = eval.call_stack_top_location();
         eval.print_handler
-            .println(&format!("{:#}", PrintWrapper(&args)))?;
+            .println_at(&format!("{:#}", PrintWrapper(&args)), location)?;
         Ok(NoneType)
     }
 }
@@ -321,6 +330,7 @@ mod tests {
 
     use crate::assert;
     use crate::assert::Assert;
+    use crate::codemap::FileSpan;
     use crate::stdlib::PrintHandler;
 
     #[test]
@@ -433,4 +443,32 @@ assert_eq(dedupe([a,b,a]), [a,b])
         a.pass("print('hw')");
         assert_eq!("hw", s_copy.borrow().as_str());
     }
+
+    #[test]
+    fn test_print_location() {
+        struct PrintHandlerImpl {
+            lines: RefCell<Vec<Option<usize>>>,
+        }
+        impl PrintHandler for PrintHandlerImpl {
+            fn println(&self, _: &str) -> anyhow::Result<()> {
+                unreachable!("`println_at` is overridden")
+            }
+
+            fn println_at(&self, _: &str, location: Option<FileSpan>) -> anyhow::Result<()> {
+                let line = location.map(|x| x.resolve_span().begin_line);
+                self.lines.borrow_mut().push(line);
+                Ok(())
+            }
+        }
+        let print_handler = PrintHandlerImpl {
+            lines: RefCell::new(Vec::new()),
+        };
+        let mut a = Assert::new();
+        a.set_print_handler(&print_handler);
+        a.pass("def f():\n    return 1\nprint(f())\npprint(f())");
+        // `Assert` runs the code several times.
+        let lines = print_handler.lines.borrow();
+        assert!(!lines.is_empty());
+        assert!(lines.chunks(2).all(|x| x == [Some(2), Some(3)]));
+    }
 }
@@ -0,0 +1,1353 @@
+/*
+ * Copyright 2019 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Lay out a module canonically, keeping its comments.
+//!
+//! The layout is computed from the AST, so parentheses are only kept where precedence
+//! requires them (or around tuples, where they were written). Literals are reproduced
+//! exactly as written. A bracketed construct is put on one line if it fits, and
+//! otherwise gets one item per line with a trailing comma. Writing a trailing comma
+//! after the last item, or a comment anywhere inside, forces the multi-line layout.
+//!
+//! The lexer throws comments away, so we recover them from the gaps between tokens,
+//! and then attach them to the nearest statement or bracketed item.
+
+use std::fmt::Write;
+use std::mem;
+
+use gazebo::prelude::*;
+
+use crate::codemap::CodeMap;
+use crate::codemap::Pos;
+use crate::codemap::Span;
+use crate::syntax::ast::ArgumentP;
+use crate::syntax::ast::AssignOp;
+use crate::syntax::ast::AssignP;
+use crate::syntax::ast::AstArgument;
+use crate::syntax::ast::AstAssign;
+use crate::syntax::ast::AstAssignIdent;
+use crate::syntax::ast::AstExpr;
+use crate::syntax::ast::AstModule;
+use crate::syntax::ast::AstParameter;
+use crate::syntax::ast::AstStmt;
+use crate::syntax::ast::AstString;
+use crate::syntax::ast::BinOp;
+use crate::syntax::ast::Clause;
+use crate::syntax::ast::Expr;
+use crate::syntax::ast::ForClause;
+use crate::syntax::ast::ParameterP;
+use crate::syntax::ast::Stmt;
+use crate::syntax::lexer::Lexer;
+
+/// Options controlling [`AstModule::format`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatOptions {
+    /// The number of spaces to indent each block by.
+    pub indent_width: usize,
+    /// Bracketed expressions longer than this are split over several lines.
+    pub max_line_length: usize,
+    /// Sort each run of consecutive `load` statements by module, and the symbols within them.
+    pub sort_loads: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            max_line_length: 100,
+            sort_loads: true,
+        }
+    }
+}
+
+/// A comment in the source, as byte offsets from its `#` to the end of the line.
+#[derive(Debug)]
+pub(crate) struct Comment {
+    pub(crate) begin: usize,
+    pub(crate) end: usize,
+    /// The column of the `#`, in characters.
+    pub(crate) column: usize,
+    /// Whether the comment is the only thing on its line.
+    pub(crate) own_line: bool,
+}
+
+/// Find the comments, which are whatever the lexer skipped over that starts with `#`.
+pub(crate) fn comments(module: &AstModule) -> Vec<Comment> {
+    let source = module.codemap.source();
+    let mut res = Vec::new();
+    let mut gap = |begin: usize, end: usize| {
+        let mut i = begin;
+        while let Some(hash) = source[i..end].find('#') {
+            let begin = i + hash;
+            let end = begin + source[begin..end].find('\n').unwrap_or(end - begin);
+            let line_start = source[..begin].rfind('\n').map_or(0, |x| x + 1);
+            let before = &source[line_start..begin];
+            res.push(Comment {
+                begin,
+                end,
+                column: before.chars().count(),
+                own_line: before.trim().is_empty(),
+            });
+            i = end;
+        }
+    };
+    let mut last = 0;
+    // The module has already parsed, so the lexer won't fail.
+    for (begin, _, end) in Lexer::new(source, &module.dialect, module.codemap.dupe()).flatten() {
+        if begin > last {
+            gap(last, begin);
+        }
+        last = last.max(end);
+    }
+    gap(last, source.len());
+    res
+}
+
+fn begin(span: Span) -> usize {
+    span.begin().get() as usize
+}
+
+fn end(span: Span) -> usize {
+    span.end().get() as usize
+}
+
+fn width(x: &str) -> usize {
+    x.chars().count()
+}
+
+fn pad(indent: usize) -> String {
+    " ".repeat(indent)
+}
+
+/// The column after writing `text` starting at `col`.
+fn col_after(col: usize, text: &str) -> usize {
+    match text.rsplit_once('\n') {
+        Some((_, last)) => width(last),
+        None => col + width(text),
+    }
+}
+
+/// Statements, with any `a; b` groups and single line blocks split out.
+fn flatten(x: &AstStmt) -> Vec<&AstStmt> {
+    match &x.node {
+        Stmt::Statements(xs) => xs.iter().flat_map(flatten).collect(),
+        _ => vec![x],
+    }
+}
+
+fn is_compound(x: &AstStmt) -> bool {
+    matches!(
+        x.node,
+        Stmt::Def(..) | Stmt::If(..) | Stmt::IfElse(..) | Stmt::For(..)
+    )
+}
+
+fn binop_precedence(op: BinOp) -> u8 {
+    match op {
+        BinOp::Or => 1,
+        BinOp::And => 2,
+        BinOp::Equal
+        | BinOp::NotEqual
+        | BinOp::Less
+        | BinOp::Greater
+        | BinOp::LessOrEqual
+        | BinOp::GreaterOrEqual
+        | BinOp::In
+        | BinOp::NotIn => 4,
+        BinOp::BitOr => 5,
+        BinOp::BitXor => 6,
+        BinOp::BitAnd => 7,
+        BinOp::LeftShift | BinOp::RightShift => 8,
+        BinOp::Add | BinOp::Subtract => 9,
+        BinOp::Multiply | BinOp::Percent | BinOp::Divide | BinOp::FloorDivide => 10,
+    }
+}
+
+/// Precedence of unary `-`, `+` and `~`.
+const PRECEDENCE_UNARY: u8 = 11;
+/// Precedence of atoms, calls, indexing and attribute access.
+const PRECEDENCE_PRIMARY: u8 = 12;
+
+fn assign_op(op: AssignOp) -> &'static str {
+    match op {
+        AssignOp::Add => " += ",
+        AssignOp::Subtract => " -= ",
+        AssignOp::Multiply => " *= ",
+        AssignOp::Divide => " /= ",
+        AssignOp::FloorDivide => " //= ",
+        AssignOp::Percent => " %= ",
+        AssignOp::BitAnd => " &= ",
+        AssignOp::BitOr => " |= ",
+        AssignOp::BitXor => " ^= ",
+        AssignOp::LeftShift => " <<= ",
+        AssignOp::RightShift => " >>= ",
+    }
+}
+
+/// Something which goes between brackets, separated by commas.
+#[derive(Clone, Copy)]
+enum Item<'a> {
+    Expr(&'a AstExpr),
+    Argument(&'a AstArgument),
+    Parameter(&'a AstParameter),
+    Entry(&'a (AstExpr, AstExpr)),
+    LoadModule(&'a AstString),
+    LoadSymbol(&'a (AstAssignIdent, AstString)),
+}
+
+impl<'a> Item<'a> {
+    fn span(self) -> Span {
+        match self {
+            Item::Expr(x) => x.span,
+            Item::Argument(x) => x.span,
+            Item::Parameter(x) => x.span,
+            Item::Entry((k, v)) => k.span.merge(v.span),
+            Item::LoadModule(x) => x.span,
+            Item::LoadSymbol((local, name)) => local.span.merge(name.span),
+        }
+    }
+}
+
+/// The brackets around some items, and where they are in the source.
+struct Brackets<'a> {
+    open: String,
+    close: &'a str,
+    /// The source between the brackets, in which comments belong to these items.
+    begin: usize,
+    end: usize,
+    /// A single item needs a comma after it, as it is a tuple.
+    tuple: bool,
+}
+
+struct Formatter<'a> {
+    codemap: &'a CodeMap,
+    source: &'a str,
+    options: &'a FormatOptions,
+    /// Usually the option, but unlimited when producing the single line form of something.
+    max_line_length: usize,
+    comments: Vec<Comment>,
+    used: Vec<bool>,
+    /// The end of the last thing written out, used to preserve blank lines.
+    last_emitted: usize,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(module: &'a AstModule, options: &'a FormatOptions, comments: Vec<Comment>) -> Self {
+        Self {
+            codemap: &module.codemap,
+            source: module.codemap.source(),
+            options,
+            max_line_length: options.max_line_length,
+            used: vec![false; comments.len()],
+            comments,
+            last_emitted: 0,
+        }
+    }
+
+    fn line(&self, pos: usize) -> usize {
+        self.codemap.find_line(Pos::new(pos as u32))
+    }
+
+    fn text(&self, span: Span) -> &'a str {
+        &self.source[begin(span)..end(span)]
+    }
+
+    fn comment(&self, i: usize) -> &'a str {
+        self.source[self.comments[i].begin..self.comments[i].end].trim_end()
+    }
+
+    fn fits(&self, col: usize, text: &str, tail: usize) -> bool {
+        match text.split_once('\n') {
+            Some((first, _)) => col + width(first) <= self.max_line_length,
+            None => col + width(text) + tail <= self.max_line_length,
+        }
+    }
+
+    /// Run `f` with no limit on line length, to produce the single line form of something.
+    fn unlimited<T>(&mut self, f: impl FnOnce(&mut Self) -> T) -> T {
+        let old = mem::replace(&mut self.max_line_length, usize::MAX);
+        let res = f(self);
+        self.max_line_length = old;
+        res
+    }
+
+    /// The position of the next `c` at or after `pos`, skipping over comments.
+    fn find(&self, pos: usize, c: char) -> usize {
+        let mut in_comment = false;
+        for (i, x) in self.source[pos..].char_indices() {
+            match x {
+                '\n' => in_comment = false,
+                '#' => in_comment = true,
+                _ if x == c && !in_comment => return pos + i,
+                _ => {}
+            }
+        }
+        self.source.len()
+    }
+
+    /// The position of the first token at or after `pos`.
+    fn next_token(&self, pos: usize) -> usize {
+        let mut in_comment = false;
+        for (i, x) in self.source[pos..].char_indices() {
+            match x {
+                '\n' => in_comment = false,
+                '#' => in_comment = true,
+                _ if in_comment || x.is_whitespace() || x == '\\' => {}
+                _ => return pos + i,
+            }
+        }
+        self.source.len()
+    }
+
+    /// The indentation of the line containing `pos` in the source.
+    fn source_indent(&self, pos: usize) -> usize {
+        let line = self.codemap.source_line(self.line(pos));
+        width(line) - width(line.trim_start())
+    }
+
+    /// Does the source between these positions, ignoring comments, contain a comma.
+    fn has_comma(&self, begin: usize, end: usize) -> bool {
+        let mut in_comment = false;
+        for x in self.source[begin..end].chars() {
+            match x {
+                '\n' => in_comment = false,
+                '#' => in_comment = true,
+                ',' if !in_comment => return true,
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// The brackets written around this span in the source, if any.
+    fn brackets(&self, span: Span) -> Option<(char, char)> {
+        let before = self.source[..begin(span)].trim_end().chars().last()?;
+        let after = self.source[end(span)..].trim_start().chars().next()?;
+        match (before, after) {
+            ('(', ')') => Some(('(', ')')),
+            ('[', ']') => Some(('[', ']')),
+            _ => None,
+        }
+    }
+
+    fn has_comment(&self, begin: usize, end: usize) -> bool {
+        self.comments
+            .iter()
+            .any(|c| begin <= c.begin && c.begin < end)
+    }
+
+    /// Take the unused comments which start in `begin..end`.
+    fn take_comments(&mut self, begin: usize, end: usize) -> Vec<usize> {
+        let mut res = Vec::new();
+        for (i, c) in self.comments.iter().enumerate() {
+            if !self.used[i] && begin <= c.begin && c.begin < end {
+                self.used[i] = true;
+                res.push(i);
+            }
+        }
+        res
+    }
+
+    /// Take the comment following `pos` on the same line, with only separators in between.
+    fn take_trailing(&mut self, pos: usize) -> Option<usize> {
+        let line = self.line(pos);
+        let i = self.comments.iter().enumerate().position(|(i, c)| {
+            !self.used[i]
+                && !c.own_line
+                && c.begin >= pos
+                && self.line(c.begin) == line
+                && self.source[pos..c.begin]
+                    .chars()
+                    .all(|x| x.is_whitespace() || x == ',' || x == ';')
+        })?;
+        self.used[i] = true;
+        Some(i)
+    }
+
+    /// Is there a blank line in the source between what we last wrote out and `pos`.
+    fn blank_before(&self, pos: usize) -> bool {
+        let first = self.line(self.last_emitted) + 1;
+        (first..self.line(pos)).any(|l| self.codemap.source_line(l).trim().is_empty())
+    }
+
+    fn emit_comment(&mut self, i: usize, indent: usize, first: &mut bool, out: &mut Vec<String>) {
+        if !*first && self.blank_before(self.comments[i].begin) {
+            out.push(String::new());
+        }
+        *first = false;
+        out.push(format!("{}{}", pad(indent), self.comment(i)));
+        self.last_emitted = self.last_emitted.max(self.comments[i].end);
+    }
+
+    /// Whether anything within the expression must be split over several lines, because it
+    /// contains a comment, or a trailing comma after the last item in brackets.
+    fn must_break_expr(&self, x: &AstExpr) -> bool {
+        if self.has_comment(begin(x.span), end(x.span)) {
+            return true;
+        }
+        let last_comma = |xs: &[Span]| match xs.last() {
+            Some(last) => self.has_comma(end(*last), end(x.span)),
+            None => false,
+        };
+        let magic = match &x.node {
+            Expr::List(xs) => last_comma(&xs.map(|x| x.span)),
+            Expr::Dict(xs) => last_comma(&xs.map(|(_, v)| v.span)),
+            Expr::Call(_, xs) => last_comma(&xs.map(|x| x.span)),
+            Expr::Tuple(xs) => xs.len() > 1 && last_comma(&xs.map(|x| x.span)),
+            _ => false,
+        };
+        let mut res = magic;
+        x.visit_expr(|x| res = res || self.must_break_expr(x));
+        res
+    }
+
+    /// Whether the arguments of a call must be split over several lines. Unlike the other
+    /// bracketed expressions, splitting the function being called doesn't count.
+    fn must_break_call(&self, x: &AstExpr, f: &AstExpr, args: &[AstArgument]) -> bool {
+        self.has_comment(end(f.span), end(x.span))
+            || args
+                .last()
+                .map_or(false, |last| self.has_comma(end(last.span), end(x.span)))
+            || args.iter().any(|x| self.must_break_expr(x.expr()))
+    }
+
+    fn must_break_item(&self, x: Item) -> bool {
+        match x {
+            Item::Expr(x) => self.must_break_expr(x),
+            Item::Argument(x) => self.must_break_expr(x.expr()),
+            Item::Parameter(x) => {
+                let (_, typ, default) = x.split();
+                typ.iter()
+                    .chain(default.iter())
+                    .any(|x| self.must_break_expr(x))
+            }
+            Item::Entry((k, v)) => self.must_break_expr(k) || self.must_break_expr(v),
+            Item::LoadModule(_) | Item::LoadSymbol(_) => false,
+        }
+    }
+
+    fn precedence(&self, x: &AstExpr) -> u8 {
+        match &x.node {
+            Expr::Lambda(..) | Expr::If(..) => 0,
+            Expr::Tuple(xs) if !xs.is_empty() && self.brackets(x.span).is_none() => 0,
+            Expr::Op(_, op, _) => binop_precedence(*op),
+            Expr::Not(_) => 3,
+            Expr::Minus(_) | Expr::Plus(_) | Expr::BitNot(_) => PRECEDENCE_UNARY,
+            _ => PRECEDENCE_PRIMARY,
+        }
+    }
+
+    /// Lay out an expression which starts at column `col`, where any lines it is split onto
+    /// are relative to `indent`, and is followed by `tail` characters on its last line.
+    fn expr(
+        &mut self,
+        x: &AstExpr,
+        precedence: u8,
+        col: usize,
+        indent: usize,
+        tail: usize,
+    ) -> String {
+        if self.precedence(x) < precedence {
+            return format!("({})", self.expr(x, 0, col + 1, indent, tail + 1));
+        }
+        match &x.node {
+            Expr::Identifier(name, _) => name.node.clone(),
+            Expr::Literal(_) => self.text(x.span).to_owned(),
+            Expr::Dot(e, name) => {
+                format!(
+                    "{}.{}",
+                    self.expr(e, PRECEDENCE_PRIMARY, col, indent, 0),
+                    name.node
+                )
+            }
+            Expr::Call(f, args) => {
+                let open = format!("{}(", self.expr(f, PRECEDENCE_PRIMARY, col, indent, 0));
+                let brackets = Brackets {
+                    open,
+                    close: ")",
+                    begin: end(f.span),
+                    end: end(x.span),
+                    tuple: false,
+                };
+                let items = args.map(Item::Argument);
+                let must_break = self.must_break_call(x, f, args);
+                self.items(brackets, &items, must_break, col, indent, tail)
+            }
+            Expr::ArrayIndirection(box (e, i)) => {
+                let e = self.expr(e, PRECEDENCE_PRIMARY, col, indent, 0);
+                let i = self.expr(i, 0, col_after(col, &e) + 1, indent, tail + 1);
+                format!("{}[{}]", e, i)
+            }
+            Expr::Slice(e, a, b, c) => {
+                let mut res = self.expr(e, PRECEDENCE_PRIMARY, col, indent, 0);
+                res.push('[');
+                if let Some(a) = a {
+                    res += &self.expr(a, 0, col_after(col, &res), indent, 0);
+                }
+                res.push(':');
+                if let Some(b) = b {
+                    res += &self.expr(b, 0, col_after(col, &res), indent, 0);
+                }
+                if let Some(c) = c {
+                    res.push(':');
+                    res += &self.expr(c, 0, col_after(col, &res), indent, 0);
+                }
+                res.push(']');
+                res
+            }
+            Expr::Lambda(params, body, _) => {
+                let mut res = "lambda".to_owned();
+                for (i, p) in params.iter().enumerate() {
+                    res += if i == 0 { " " } else { ", " };
+                    res += &self.item(Item::Parameter(p), col_after(col, &res), indent, 0);
+                }
+                res += ": ";
+                res += &self.expr(body, 0, col_after(col, &res), indent, tail);
+                res
+            }
+            Expr::Not(e) => format!("not {}", self.expr(e, 3, col + 4, indent, tail)),
+            Expr::Minus(e) => format!("-{}", self.expr(e, PRECEDENCE_UNARY, col + 1, indent, tail)),
+            Expr::Plus(e) => format!("+{}", self.expr(e, PRECEDENCE_UNARY, col + 1, indent, tail)),
+            Expr::BitNot(e) => {
+                format!("~{}", self.expr(e, PRECEDENCE_UNARY, col + 1, indent, tail))
+            }
+            Expr::Op(l, op, r) => {
+                let p = binop_precedence(*op);
+                // Comparisons don't chain, everything else is left associative.
+                let left = if p == 4 { p + 1 } else { p };
+                let l = self.expr(l, left, col, indent, 0);
+                let op = op.to_string();
+                let r = self.expr(r, p + 1, col_after(col, &l) + op.len(), indent, tail);
+                format!("{}{}{}", l, op, r)
+            }
+            Expr::If(box (cond, then, els)) => {
+                let then = self.expr(then, 1, col, indent, 0);
+                let cond = self.expr(cond, 1, col_after(col, &then) + 4, indent, 0);
+                let mut res = format!("{} if {} else ", then, cond);
+                res += &self.expr(els, 0, col_after(col, &res), indent, tail);
+                res
+            }
+            Expr::List(xs) => {
+                let brackets = Brackets {
+                    open: "[".to_owned(),
+                    close: "]",
+                    begin: begin(x.span),
+                    end: end(x.span),
+                    tuple: false,
+                };
+                let must_break = self.must_break_expr(x);
+                self.items(brackets, &xs.map(Item::Expr), must_break, col, indent, tail)
+            }
+            Expr::Dict(xs) => {
+                let brackets = Brackets {
+                    open: "{".to_owned(),
+                    close: "}",
+                    begin: begin(x.span),
+                    end: end(x.span),
+                    tuple: false,
+                };
+                let must_break = self.must_break_expr(x);
+                self.items(
+                    brackets,
+                    &xs.map(Item::Entry),
+                    must_break,
+                    col,
+                    indent,
+                    tail,
+                )
+            }
+            Expr::Tuple(xs) if xs.is_empty() => "()".to_owned(),
+            Expr::Tuple(xs) => {
+                let must_break = self.must_break_expr(x);
+                let items = xs.map(Item::Expr);
+                if self.brackets(x.span).is_none() && !must_break {
+                    let flat = self.unlimited(|f| f.flat_items(&items, true));
+                    if self.fits(col, &flat, tail) {
+                        return flat;
+                    }
+                }
+                // Either it had brackets, or it has to be split, which requires them.
+                let brackets = Brackets {
+                    open: "(".to_owned(),
+                    close: ")",
+                    begin: begin(x.span),
+                    end: end(x.span),
+                    tuple: true,
+                };
+                self.items(brackets, &items, must_break, col, indent, tail)
+            }
+            Expr::ListComprehension(e, for_, clauses) => self.comprehension(
+                x,
+                ("[", "]"),
+                e.span,
+                |f, col, indent| f.expr(e, 0, col, indent, 0),
+                for_,
+                clauses,
+                col,
+                indent,
+                tail,
+            ),
+            Expr::DictComprehension(box (k, v), for_, clauses) => self.comprehension(
+                x,
+                ("{", "}"),
+                k.span.merge(v.span),
+                |f, col, indent| {
+                    let k = f.expr(k, 0, col, indent, 0);
+                    let v = f.expr(v, 0, col_after(col, &k) + 2, indent, 0);
+                    format!("{}: {}", k, v)
+                },
+                for_,
+                clauses,
+                col,
+                indent,
+                tail,
+            ),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn comprehension(
+        &mut self,
+        x: &AstExpr,
+        (open, close): (&str, &str),
+        elem_span: Span,
+        elem: impl Fn(&mut Self, usize, usize) -> String,
+        for_: &ForClause,
+        clauses: &[Clause],
+        col: usize,
+        indent: usize,
+        tail: usize,
+    ) -> String {
+        let clause = |f: &mut Self, x: &Clause, col: usize, indent: usize| match x {
+            Clause::For(x) => f.for_clause(x, col, indent),
+            Clause::If(x) => format!("if {}", f.expr(x, 1, col + 3, indent, 0)),
+        };
+        if !self.must_break_expr(x) {
+            let flat = self.unlimited(|f| {
+                let mut res = format!("{}{} {}", open, elem(f, 0, 0), f.for_clause(for_, 0, 0));
+                for x in clauses {
+                    res.push(' ');
+                    res += &clause(f, x, 0, 0);
+                }
+                res + close
+            });
+            if self.fits(col, &flat, tail) {
+                return flat;
+            }
+        }
+        // One line for the element and each clause, each preceded by its comments.
+        let inner = indent + self.options.indent_width;
+        let mut res = open.to_owned();
+        self.comprehension_comments(begin(x.span), elem_span, inner, &mut res);
+        write!(res, "\n{}{}", pad(inner), elem(self, inner, inner)).unwrap();
+        let mut previous = elem_span;
+        let for_span = for_.var.span.merge(for_.over.span);
+        self.comprehension_comments(end(previous), for_span, inner, &mut res);
+        write!(
+            res,
+            "\n{}{}",
+            pad(inner),
+            self.for_clause(for_, inner, inner)
+        )
+        .unwrap();
+        previous = for_span;
+        for x in clauses {
+            let span = match x {
+                Clause::For(x) => x.var.span.merge(x.over.span),
+                Clause::If(x) => x.span,
+            };
+            self.comprehension_comments(end(previous), span, inner, &mut res);
+            write!(res, "\n{}{}", pad(inner), clause(self, x, inner, inner)).unwrap();
+            previous = span;
+        }
+        for c in self.take_comments(end(previous), end(x.span)) {
+            write!(res, "\n{}{}", pad(inner), self.comment(c)).unwrap();
+        }
+        res + "\n" + &pad(indent) + close
+    }
+
+    /// Write out the comments between `lower` and the part of a comprehension at `span`.
+    fn comprehension_comments(
+        &mut self,
+        lower: usize,
+        span: Span,
+        indent: usize,
+        res: &mut String,
+    ) {
+        for c in self.take_comments(lower, begin(span)) {
+            write!(res, "\n{}{}", pad(indent), self.comment(c)).unwrap();
+        }
+    }
+
+    fn for_clause(&mut self, x: &ForClause, col: usize, indent: usize) -> String {
+        let var = self.assign(&x.var, col + 4, indent);
+        let over = self.expr(&x.over, 1, col_after(col + 4, &var) + 4, indent, 0);
+        format!("for {} in {}", var, over)
+    }
+
+    fn assign(&mut self, x: &AstAssign, col: usize, indent: usize) -> String {
+        match &x.node {
+            AssignP::Identifier(x) => x.node.0.clone(),
+            AssignP::Dot(e, name) => {
+                format!(
+                    "{}.{}",
+                    self.expr(e, PRECEDENCE_PRIMARY, col, indent, 0),
+                    name.node
+                )
+            }
+            AssignP::ArrayIndirection(box (e, i)) => {
+                let e = self.expr(e, PRECEDENCE_PRIMARY, col, indent, 0);
+                let i = self.expr(i, 0, col_after(col, &e) + 1, indent, 0);
+                format!("{}[{}]", e, i)
+            }
+            // The span of an empty tuple includes its brackets.
+            AssignP::Tuple(xs) if xs.is_empty() => self.text(x.span).to_owned(),
+            AssignP::Tuple(xs) => {
+                let mut res = String::new();
+                for (i, x) in xs.iter().enumerate() {
+                    if i != 0 {
+                        res += ", ";
+                    }
+                    res += &self.assign(x, col_after(col, &res), indent);
+                }
+                if xs.len() == 1 {
+                    res.push(',');
+                }
+                // Lists are written as tuples, but their span includes the brackets.
+                if begin(xs[0].span) > begin(x.span) {
+                    format!("[{}]", res)
+                } else {
+                    match self.brackets(x.span) {
+                        Some((open, close)) => format!("{}{}{}", open, res, close),
+                        None => res,
+                    }
+                }
+            }
+        }
+    }
+
+    fn item(&mut self, x: Item, col: usize, indent: usize, tail: usize) -> String {
+        match x {
+            Item::Expr(x) => self.expr(x, 0, col, indent, tail),
+            Item::Argument(x) => {
+                let (prefix, e) = match &x.node {
+                    ArgumentP::Positional(e) => (String::new(), e),
+                    ArgumentP::Named(name, e) => (format!("{} = ", name.node), e),
+                    ArgumentP::Args(e) => ("*".to_owned(), e),
+                    ArgumentP::KwArgs(e) => ("**".to_owned(), e),
+                };
+                let e = self.expr(e, 0, col + width(&prefix), indent, tail);
+                prefix + &e
+            }
+            Item::Parameter(x) => {
+                let (prefix, name, typ, default) = match &x.node {
+                    ParameterP::Normal(name, typ) => ("", name, typ, None),
+                    ParameterP::WithDefaultValue(name, typ, default) => {
+                        ("", name, typ, Some(default))
+                    }
+                    ParameterP::NoArgs => return "*".to_owned(),
+                    ParameterP::Args(name, typ) => ("*", name, typ, None),
+                    ParameterP::KwArgs(name, typ) => ("**", name, typ, None),
+                };
+                let mut res = format!("{}{}", prefix, name.node.0);
+                if let Some(typ) = typ {
+                    res += ": ";
+                    res += &self.expr(typ, 0, col_after(col, &res), indent, 0);
+                }
+                if let Some(default) = default {
+                    res += " = ";
+                    res += &self.expr(default, 0, col_after(col, &res), indent, tail);
+                }
+                res
+            }
+            Item::Entry((k, v)) => {
+                let k = self.expr(k, 0, col, indent, 0);
+                let v = self.expr(v, 0, col_after(col, &k) + 2, indent, tail);
+                format!("{}: {}", k, v)
+            }
+            Item::LoadModule(x) => self.text(x.span).to_owned(),
+            Item::LoadSymbol((local, name)) => {
+                if local.span == name.span {
+                    self.text(name.span).to_owned()
+                } else {
+                    format!("{} = {}", local.node.0, self.text(name.span))
+                }
+            }
+        }
+    }
+
+    fn flat_items(&mut self, items: &[Item], tuple: bool) -> String {
+        let mut res = items.map(|x| self.item(*x, 0, 0, 0)).join(", ");
+        if tuple && items.len() == 1 {
+            res.push(',');
+        }
+        res
+    }
+
+    /// Lay out items in brackets, on one line if possible, otherwise one per line.
+    fn items(
+        &mut self,
+        brackets: Brackets,
+        items: &[Item],
+        must_break: bool,
+        col: usize,
+        indent: usize,
+        tail: usize,
+    ) -> String {
+        if !must_break {
+            let flat = self.unlimited(|f| f.flat_items(items, brackets.tuple));
+            let flat = format!("{}{}{}", brackets.open, flat, brackets.close);
+            if self.fits(col, &flat, tail) {
+                return flat;
+            }
+        }
+
+        let inner = indent + self.options.indent_width;
+        let mut res = brackets.open;
+        res.push('\n');
+        // Items might have been sorted, so comments before an item are those after
+        // whichever item preceded it in the source.
+        let mut ends = items.map(|x| end(x.span()));
+        ends.sort_unstable();
+        for x in items {
+            let span = x.span();
+            let lower = ends
+                .iter()
+                .copied()
+                .filter(|e| *e <= begin(span))
+                .max()
+                .unwrap_or(brackets.begin);
+            for c in self.take_comments(lower, begin(span)) {
+                writeln!(res, "{}{}", pad(inner), self.comment(c)).unwrap();
+            }
+            res += &pad(inner);
+            res += &self.item(*x, inner, inner, 1);
+            res.push(',');
+            if let Some(c) = self.take_trailing(end(span)) {
+                res += "  ";
+                res += self.comment(c);
+            }
+            res.push('\n');
+        }
+        for c in self.take_comments(brackets.begin, brackets.end) {
+            writeln!(res, "{}{}", pad(inner), self.comment(c)).unwrap();
+        }
+        res + &pad(indent) + brackets.close
+    }
+
+    fn load_symbols<'b>(
+        &self,
+        xs: &'b [(AstAssignIdent, AstString)],
+    ) -> Vec<&'b (AstAssignIdent, AstString)> {
+        let mut xs: Vec<_> = xs.iter().collect();
+        if self.options.sort_loads {
+            xs.sort_by(|a, b| a.0.node.0.cmp(&b.0.node.0));
+        }
+        xs
+    }
+
+    /// A statement which fits on one line, apart from any brackets in it being split.
+    fn simple(&mut self, x: &AstStmt, indent: usize) -> String {
+        match &x.node {
+            Stmt::Break => "break".to_owned(),
+            Stmt::Continue => "continue".to_owned(),
+            Stmt::Pass => "pass".to_owned(),
+            Stmt::Return(None) => "return".to_owned(),
+            Stmt::Return(Some(e)) => format!("return {}", self.expr(e, 0, indent + 7, indent, 0)),
+            Stmt::Expression(e) => self.expr(e, 0, indent, indent, 0),
+            Stmt::Assign(l, r) => {
+                let l = self.assign(l, indent, indent);
+                let r = self.expr(r, 0, col_after(indent, &l) + 3, indent, 0);
+                format!("{} = {}", l, r)
+            }
+            Stmt::AssignModify(l, op, r) => {
+                let l = self.assign(l, indent, indent);
+                let op = assign_op(*op);
+                let r = self.expr(r, 0, col_after(indent, &l) + op.len(), indent, 0);
+                format!("{}{}{}", l, op, r)
+            }
+            Stmt::Load(load) => {
+                let last = match load.node.args.last() {
+                    Some(x) => Item::LoadSymbol(x).span(),
+                    None => load.node.module.span,
+                };
+                let must_break = self.has_comment(begin(x.span), end(x.span))
+                    || self.has_comma(end(last), end(x.span));
+                let mut items = vec![Item::LoadModule(&load.node.module)];
+                items.extend(
+                    self.load_symbols(&load.node.args)
+                        .into_iter()
+                        .map(Item::LoadSymbol),
+                );
+                let brackets = Brackets {
+                    open: "load(".to_owned(),
+                    close: ")",
+                    begin: begin(x.span),
+                    end: end(x.span),
+                    tuple: false,
+                };
+                self.items(brackets, &items, must_break, indent, indent, 0)
+            }
+            Stmt::Statements(_)
+            | Stmt::If(..)
+            | Stmt::IfElse(..)
+            | Stmt::For(..)
+            | Stmt::Def(..) => unreachable!("not a simple statement"),
+        }
+    }
+
+    /// Write out `text` starting at `indent`. Its subsequent lines are already indented.
+    fn emit_lines(&self, text: &str, indent: usize, out: &mut Vec<String>) {
+        for (i, line) in text.split('\n').enumerate() {
+            out.push(if i == 0 {
+                format!("{}{}", pad(indent), line)
+            } else {
+                line.to_owned()
+            });
+        }
+    }
+
+    fn stmt(&mut self, x: &AstStmt, indent: usize, out: &mut Vec<String>) {
+        match &x.node {
+            Stmt::Def(name, params, ret, body, _) => {
+                let ret_span = ret.as_ref().map(|r| r.span);
+                let close = match params.last() {
+                    Some(p) => self.find(end(p.span), ')'),
+                    None => self.find(end(name.span), ')'),
+                };
+                let ret = ret.as_ref().map(|r| {
+                    let r = self.expr(r, 0, indent, indent, 0);
+                    format!(" -> {}", r)
+                });
+                let ret = ret.unwrap_or_default();
+                let brackets = Brackets {
+                    open: format!("def {}(", name.node.0),
+                    close: ")",
+                    begin: end(name.span),
+                    end: close,
+                    tuple: false,
+                };
+                let items = params.map(Item::Parameter);
+                let must_break = self.has_comment(end(name.span), close)
+                    || params
+                        .last()
+                        .map_or(false, |p| self.has_comma(end(p.span), close))
+                    || items.iter().any(|x| self.must_break_item(*x));
+                let header = self.items(
+                    brackets,
+                    &items,
+                    must_break,
+                    indent,
+                    indent,
+                    width(&ret) + 1,
+                );
+                let colon = self.find(ret_span.map_or(close, end), ':');
+                self.compound(
+                    x,
+                    header + &ret + ":",
+                    begin(x.span),
+                    colon,
+                    body,
+                    indent,
+                    out,
+                );
+            }
+            Stmt::For(var, box (over, body)) => {
+                let var = self.assign(var, indent + 4, indent);
+                let over_col = col_after(indent + 4, &var) + 4;
+                let over_text = self.expr(over, 0, over_col, indent, 1);
+                let colon = self.find(end(over.span), ':');
+                let header = format!("for {} in {}:", var, over_text);
+                self.compound(x, header, begin(x.span), colon, body, indent, out);
+            }
+            Stmt::If(..) | Stmt::IfElse(..) => self.if_stmt(x, "if", indent, out),
+            _ => {
+                let text = self.simple(x, indent);
+                // Comments we couldn't find a place for within the statement go before it.
+                for c in self.take_comments(begin(x.span), end(x.span)) {
+                    out.push(format!("{}{}", pad(indent), self.comment(c)));
+                }
+                self.emit_lines(&text, indent, out);
+                self.last_emitted = self.last_emitted.max(end(x.span));
+                if let Some(c) = self.take_trailing(end(x.span)) {
+                    let text = format!("  {}", self.comment(c));
+                    out.last_mut().unwrap().push_str(&text);
+                    self.last_emitted = self.last_emitted.max(self.comments[c].end);
+                }
+            }
+        }
+    }
+
+    fn if_stmt(&mut self, x: &AstStmt, keyword: &str, indent: usize, out: &mut Vec<String>) {
+        let (cond, then, els) = match &x.node {
+            Stmt::If(cond, box then) => (cond, then, None),
+            Stmt::IfElse(cond, box (then, els)) => (cond, then, Some(els)),
+            _ => unreachable!("not an if statement"),
+        };
+        let col = indent + keyword.len() + 1;
+        let header = format!("{} {}:", keyword, self.expr(cond, 0, col, indent, 1));
+        let colon = self.find(end(cond.span), ':');
+        self.compound(x, header, begin(x.span), colon, then, indent, out);
+        match els {
+            None => {}
+            Some(els) if matches!(els.node, Stmt::If(..) | Stmt::IfElse(..)) => {
+                self.if_stmt(els, "elif", indent, out)
+            }
+            Some(els) => {
+                let keyword = self.next_token(end(then.span));
+                let colon = self.find(keyword, ':');
+                self.compound(x, "else:".to_owned(), colon, colon, els, indent, out);
+            }
+        }
+    }
+
+    /// Write out a statement with a block, where `hoist..colon` is the header.
+    #[allow(clippy::too_many_arguments)]
+    fn compound(
+        &mut self,
+        x: &AstStmt,
+        header: String,
+        hoist: usize,
+        colon: usize,
+        body: &AstStmt,
+        indent: usize,
+        out: &mut Vec<String>,
+    ) {
+        for c in self.take_comments(hoist, colon) {
+            out.push(format!("{}{}", pad(indent), self.comment(c)));
+        }
+        self.emit_lines(&header, indent, out);
+        self.last_emitted = self.last_emitted.max(colon);
+        let body = flatten(body);
+        if let Some(c) = self.take_trailing(colon + 1) {
+            if self.comments[c].begin < begin(body[0].span) {
+                let text = format!("  {}", self.comment(c));
+                out.last_mut().unwrap().push_str(&text);
+                self.last_emitted = self.last_emitted.max(self.comments[c].end);
+            } else {
+                self.used[c] = false;
+            }
+        }
+        let limit = self.next_token(end(body.last().unwrap().span));
+        let header_indent = self.source_indent(begin(x.span));
+        self.block(
+            &body,
+            indent + self.options.indent_width,
+            Some(header_indent),
+            limit,
+            out,
+        );
+    }
+
+    /// Sort each run of `load` statements by module, if requested.
+    fn sort_loads<'b>(&self, xs: &[&'b AstStmt]) -> Vec<&'b AstStmt> {
+        let mut xs = xs.to_vec();
+        if self.options.sort_loads {
+            let module = |x: &AstStmt| match &x.node {
+                Stmt::Load(load) => Some(load.node.module.node.clone()),
+                _ => None,
+            };
+            for run in xs.split_mut(|x| module(x).is_none()) {
+                run.sort_by_key(|x| module(x));
+            }
+        }
+        xs
+    }
+
+    /// The unused comments before the `i`th statement, split into those before the last blank
+    /// line, and those after. Comments before a statement are those after the statement which
+    /// preceded it, except for any on the same line as its end. Blocks may own comments after
+    /// their last statement, so we can't tell where those end.
+    fn leading(&self, xs: &[&AstStmt], i: usize) -> (Vec<usize>, Vec<usize>) {
+        let lower = match i.checked_sub(1).map(|i| xs[i]) {
+            Some(y) if !is_compound(y) => end(y.span),
+            _ => 0,
+        };
+        let upper = begin(xs[i].span);
+        let mut res: Vec<usize> = (0..self.comments.len())
+            .filter(|&c| {
+                let comment = &self.comments[c];
+                !self.used[c]
+                    && lower <= comment.begin
+                    && comment.begin < upper
+                    && (comment.own_line
+                        || lower == 0
+                        || self.line(comment.begin) != self.line(lower))
+            })
+            .collect();
+        let detached = res
+            .iter()
+            .rposition(|&c| {
+                let next = self.line(self.comments[c].begin) + 1;
+                next < self.line(upper) && self.codemap.source_line(next).trim().is_empty()
+            })
+            .map_or(0, |p| p + 1);
+        let attached = res.split_off(detached);
+        (res, attached)
+    }
+
+    /// Write out the statements of a block, up to `limit` in the source. Trailing comments
+    /// belong to the block if they are indented further than its header.
+    fn block(
+        &mut self,
+        xs: &[&AstStmt],
+        indent: usize,
+        header_indent: Option<usize>,
+        limit: usize,
+        out: &mut Vec<String>,
+    ) {
+        let mut first = true;
+        let sorted = self.sort_loads(xs);
+        for (i, x) in sorted.iter().enumerate() {
+            // Comments separated from the statement below them by a blank line stay where
+            // they are, the rest move with the statement if it gets sorted.
+            let (detached, _) = self.leading(xs, i);
+            let (_, attached) =
+                self.leading(xs, xs.iter().position(|y| std::ptr::eq(*y, *x)).unwrap());
+            for c in detached.into_iter().chain(attached) {
+                self.used[c] = true;
+                self.emit_comment(c, indent, &mut first, out);
+            }
+            if !first && self.blank_before(begin(x.span)) {
+                out.push(String::new());
+            }
+            first = false;
+            self.stmt(x, indent, out);
+        }
+        for i in 0..self.comments.len() {
+            let c = &self.comments[i];
+            if self.used[i] || c.begin < self.last_emitted.min(limit) {
+                continue;
+            }
+            if c.begin >= limit || header_indent.map_or(false, |h| c.column <= h) {
+                break;
+            }
+            self.used[i] = true;
+            self.emit_comment(i, indent, &mut first, out);
+        }
+    }
+}
+
+impl AstModule {
+    /// Lay out the module canonically, preserving comments.
+    pub fn format(&self, options: &FormatOptions) -> String {
+        let mut f = Formatter::new(self, options, comments(self));
+        let mut out = Vec::new();
+        f.block(
+            &flatten(&self.statement),
+            0,
+            None,
+            self.codemap.source().len(),
+            &mut out,
+        );
+        // Anything left over, which shouldn't happen, goes at the end.
+        for c in f.take_comments(0, usize::MAX) {
+            out.push(f.comment(c).to_owned());
+        }
+        let mut res = out.join("\n");
+        if !res.is_empty() {
+            res.push('\n');
+        }
+        res
+    }
+
+    /// Lay out the top-level statements which overlap the lines `first..=last`, returning
+    /// the region of the source they cover along with its replacement.
+    pub(crate) fn format_lines(
+        &self,
+        options: &FormatOptions,
+        first: usize,
+        last: usize,
+    ) -> Option<(Span, String)> {
+        let mut f = Formatter::new(self, options, Vec::new());
+        let statements = flatten(&self.statement);
+        let last_line = |x: &AstStmt| {
+            let text = f.text(x.span).trim_end();
+            f.line(begin(x.span) + text.len().saturating_sub(1))
+        };
+        let selected: Vec<&AstStmt> = statements
+            .into_iter()
+            .filter(|x| f.line(begin(x.span)) <= last && last_line(x) >= first)
+            .collect();
+        let first_line = f.line(begin(selected.first()?.span));
+        let region_begin = self.codemap.line_span(first_line).begin().get() as usize;
+        let end_line = last_line(selected.last()?);
+        let region_end = self.codemap.line_span(end_line).begin().get() as usize
+            + self.codemap.source_line(end_line).len();
+
+        f.comments = comments(self)
+            .into_iter()
+            .filter(|c| region_begin <= c.begin && c.end <= region_end)
+            .collect();
+        f.used = vec![false; f.comments.len()];
+        f.last_emitted = region_begin;
+        let mut out = Vec::new();
+        f.block(&selected, 0, None, region_end, &mut out);
+        Some((
+            Span::new(Pos::new(region_begin as u32), Pos::new(region_end as u32)),
+            out.join("\n"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::Dialect;
+
+    fn module(x: &str) -> AstModule {
+        AstModule::parse("X", x.to_owned(), &Dialect::Extended).unwrap()
+    }
+
+    fn format_with(x: &str, options: &FormatOptions) -> String {
+        let res = module(x).format(options);
+        // Formatting is idempotent.
+        assert_eq!(res, module(&res).format(options), "Not idempotent");
+        res
+    }
+
+    fn format(x: &str) -> String {
+        format_with(x, &FormatOptions::default())
+    }
+
+    #[test]
+    fn test_format_layout() {
+        assert_eq!(
+            format(
+                "x=1+2*3\ny  =  (1+2)*3\nif x :\n  pass\nelif y: x.f [1] (a,b=2,*c,**d)\nelse :\n  z ,w= 1 ,  2\n"
+            ),
+            "x = 1 + 2 * 3\ny = (1 + 2) * 3\nif x:\n    pass\nelif y:\n    x.f[1](a, b = 2, *c, **d)\nelse:\n    z, w = 1, 2\n"
+        );
+        assert_eq!(
+            format(
+                "def f(a,b:int=1,*c,**d)->str:\n  return -(a+b) if not a else [x for x in b if x]\nx -= 1; y = (1,)\nz = ()\n"
+            ),
+            "def f(a, b: int = 1, *c, **d) -> str:\n    return -(a + b) if not a else [x for x in b if x]\nx -= 1\ny = (1,)\nz = ()\n"
+        );
+        assert_eq!(
+            format("x = a - (b - c) - d\ny = (a or b) and not (c == d)\n"),
+            "x = a - (b - c) - d\ny = (a or b) and not c == d\n"
+        );
+    }
+
+    #[test]
+    fn test_format_blank_lines() {
+        assert_eq!(
+            format("\n\nx = 1\n\n\n\ny = 2\ndef f():\n\n    a = 1\n\n\n    b = 2\n"),
+            "x = 1\n\ny = 2\ndef f():\n    a = 1\n\n    b = 2\n"
+        );
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let code = r#"
+# Header
+
+load("b", "y")  # b
+load("a", "x")
+def f(
+    a,  # first
+    # before b
+    b,
+):  # header
+    # body
+    if a: # cond
+        pass
+        # end of if
+    # end of f
+
+x = [1, 2]  # list
+y = {
+    # key
+    "k": 1,
+}
+# The end
+"#;
+        let expected = r#"# Header
+
+load("a", "x")
+load("b", "y")  # b
+def f(
+    a,  # first
+    # before b
+    b,
+):  # header
+    # body
+    if a:  # cond
+        pass
+        # end of if
+    # end of f
+
+x = [1, 2]  # list
+y = {
+    # key
+    "k": 1,
+}
+# The end
+"#;
+        assert_eq!(format(code), expected);
+    }
+
+    #[test]
+    fn test_format_breaking() {
+        let options = FormatOptions {
+            max_line_length: 20,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_with("f(aaaaa, bbbbb, ccccc)\ng(1)\nh(1,)\n", &options),
+            "f(\n    aaaaa,\n    bbbbb,\n    ccccc,\n)\ng(1)\nh(\n    1,\n)\n"
+        );
+        assert_eq!(
+            format_with("x = [y for y in zzzzzzzz if y]\n", &options),
+            "x = [\n    y\n    for y in zzzzzzzz\n    if y\n]\n"
+        );
+        assert_eq!(
+            format_with("x = aaaaa, bbbbb, ccccc\n", &options),
+            "x = (\n    aaaaa,\n    bbbbb,\n    ccccc,\n)\n"
+        );
+    }
+
+    #[test]
+    fn test_format_loads() {
+        let code = "load('c', 'z')\nload('a', y = 'q', x = 'p')\nx = 1\nload('b', 'w')\n";
+        assert_eq!(
+            format(code),
+            "load('a', x = 'p', y = 'q')\nload('c', 'z')\nx = 1\nload('b', 'w')\n"
+        );
+        let options = FormatOptions {
+            sort_loads: false,
+            ..FormatOptions::default()
+        };
+        assert_eq!(
+            format_with(code, &options),
+            "load('c', 'z')\nload('a', y = 'q', x = 'p')\nx = 1\nload('b', 'w')\n"
+        );
+    }
+
+    #[test]
+    fn test_format_lines() {
+        let m = module("x=1\ny  = 2 # two\n\ndef f():\n  return 1\n");
+        let (span, text) = m.format_lines(&FormatOptions::default(), 1, 1).unwrap();
+        assert_eq!(m.codemap.source_span(span), "y  = 2 # two");
+        assert_eq!(text, "y = 2  # two");
+        let (span, text) = m.format_lines(&FormatOptions::default(), 4, 4).unwrap();
+        assert_eq!(m.codemap.source_span(span), "def f():\n  return 1");
+        assert_eq!(text, "def f():\n    return 1");
+        assert!(m.format_lines(&FormatOptions::default(), 2, 2).is_none());
+    }
+}
@@ -20,6 +20,7 @@
 pub use ast::AstModule;
 pub use dialect::Dialect;
 pub use dialect::DialectTypes;
+pub use format::FormatOptions;
 
 #[cfg(test)]
 mod grammar_tests;
@@ -31,6 +32,7 @@ mod testcases;
 pub(crate) mod ast;
 pub(crate) mod cursors;
 mod dialect;
+pub(crate) mod format;
 pub(crate) mod lexer;
 pub(crate) mod payload_map;
 pub(crate) mod validate;
@@ -26,6 +26,7 @@ mod docstring;
 mod freeze_access_value;
 mod go;
 mod interop;
+mod on_error;
 mod opt;
 mod runtime;
 mod type_annot;
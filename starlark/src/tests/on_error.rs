@@ -0,0 +1,73 @@
+/*
+ * Copyright 2018 The Starlark in Rust Authors.
+ * Copyright (c) Facebook, Inc. and its affiliates.
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use std::cell::Cell;
+use std::cell::RefCell;
+
+use gazebo::prelude::*;
+
+use crate::codemap::FileSpanRef;
+use crate::environment::Globals;
+use crate::environment::Module;
+use crate::eval::Evaluator;
+use crate::syntax::AstModule;
+use crate::syntax::Dialect;
+
+#[test]
+fn on_error() {
+    let module = Module::new();
+    let globals = Globals::standard();
+    let mut evaluator = Evaluator::new(&module);
+    let errors = RefCell::new(Vec::new());
+    let on_error = |_e: &anyhow::Error, span: FileSpanRef, eval: &mut Evaluator<'_, '_>| {
+        let locals = eval.local_variables();
+        let stack = eval.call_stack().into_frames().into_map(|x| x.name);
+        errors.borrow_mut().push((
+            span.resolve_span().begin_line,
+            locals.keys().cloned().collect::<Vec<_>>(),
+            stack,
+        ));
+    };
+    let count = Cell::new(0);
+    let count_errors = |_e: &anyhow::Error, _: FileSpanRef, _: &mut Evaluator<'_, '_>| {
+        count.set(count.get() + 1);
+    };
+    // Each handler runs, and adding the same handler again does nothing.
+    assert!(evaluator.on_error(&on_error));
+    assert!(evaluator.on_error(&count_errors));
+    assert!(!evaluator.on_error(&on_error));
+
+    let program = "\
+def f(x):
+    y = x * 2
+    fail('bad', y)
+def g():
+    f(1)
+g()
+";
+    let ast = AstModule::parse("a.star", program.to_owned(), &Dialect::Extended).unwrap();
+    assert!(evaluator.eval_module(ast, &globals).is_err());
+    // Called once, where `fail` is called, with the frame of `f` still on the stack.
+    assert_eq!(
+        *errors.borrow(),
+        vec![(
+            2,
+            vec!["x".to_owned(), "y".to_owned()],
+            vec!["g".to_owned(), "f".to_owned()]
+        )]
+    );
+    assert_eq!(count.get(), 1);
+}
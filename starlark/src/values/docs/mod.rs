@@ -253,7 +253,7 @@ impl DocString {
     /// - A new instance of `DocString`, with the requested sections, if found, removed.
     /// - A mapping of section name, converted to lower case, to the cleaned up section text
     ///     i.e. dedented, section header not present, etc for any found sections.
-    fn parse_and_remove_sections(
+    pub(crate) fn parse_and_remove_sections(
         self,
         kind: DocStringKind,
         requested_sections: &[&str],
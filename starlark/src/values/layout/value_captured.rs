@@ -85,3 +85,9 @@ pub(crate) fn value_captured_get<'v>(value_captured: Value<'v>) -> Option<Value<
             .get()
     }
 }
+
+/// Is the value stored in a local slot a captured variable, rather than the variable itself?
+pub(crate) fn is_value_captured(value: Value) -> bool {
+    value.downcast_ref::<ValueCaptured>().is_some()
+        || value.downcast_ref::<FrozenValueCaptured>().is_some()
+}
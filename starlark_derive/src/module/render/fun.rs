@@ -413,7 +413,15 @@ fn render_documentation(x: &StarFun) -> syn::Result<TokenStream> {
             }
         }
         None => {
-            quote_spanned!(span=> starlark::eval::ParametersSpec::<starlark::values::FrozenValue>::new(#name_str.to_owned()).finish())
+            // The function takes the raw arguments, so accepts anything as far as we know.
+            quote_spanned! {
+                span=> {
+                let mut __signature = starlark::eval::ParametersSpec::<starlark::values::FrozenValue>::new(#name_str.to_owned());
+                __signature.args();
+                __signature.kwargs();
+                __signature.finish()
+                }
+            }
         }
     };
 
@@ -27,6 +27,8 @@ let client: LanguageClient;
 
 interface AdditionalClientSettings {
     enable_goto_definition: boolean;
+    format_max_line_length: number;
+    format_sort_loads: boolean;
 }
 
 /// Get a setting at the path, or throw an error if it's not set.
@@ -41,6 +43,8 @@ function requireSetting<T>(path: string): T {
 function additionalClientSettings(): AdditionalClientSettings {
     return {
         enable_goto_definition: vscode.workspace.getConfiguration().get("starlark.enableGotoDefinition", true),
+        format_max_line_length: vscode.workspace.getConfiguration().get("starlark.formatMaxLineLength", 100),
+        format_sort_loads: vscode.workspace.getConfiguration().get("starlark.formatSortLoads", true),
     };
 }
 